@@ -0,0 +1,272 @@
+//! A generic Modbus analog output, driven by a percentage.
+//!
+//! Some field devices (like a proportional valve) take a 0-100% command rather than on/off. They're
+//! usually wired to a Modbus analog output module that takes a raw register value, like `0`-`4095` for a 12 bit
+//! output. This controller scales the percentage in [`DeviceState::output_pct`](crate::state::DeviceState::output_pct)
+//! to the register range configured in the device's [`Connection`](crate::model::device::Connection):
+//!
+//! ```yaml
+//! conn:
+//!   port: /dev/ttyUSB0
+//!   baudrate: 19200
+//!   timeout: 50
+//!   controller: AnalogOutput
+//!   controller_addr: 3
+//!   analog_output:
+//!     register: 0
+//!     min: 0
+//!     max: 4095
+//! ```
+use std::time::Duration;
+
+use async_trait::async_trait;
+use log::trace;
+use serde::{Deserialize, Serialize};
+
+use crate::drivers::mock::MockModbusInstrument;
+use crate::drivers::{modbus::ModbusInstrument, InstrumentError, ModbusDriver, Result};
+use crate::logging_utils::device_trace;
+use crate::model::{Device, SCADADevice};
+use crate::state::StateError;
+
+/// The baudrates we allow for a generic Modbus analog output. These are the common Modbus RTU rates,
+/// check that your device supports the one you pick.
+pub const ANALOG_OUTPUT_BAUDRATES: [usize; 7] = [2400, 4800, 9600, 19200, 38400, 57600, 115200];
+
+/// Where the analog output lives and what register values make up 0% and 100%
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct AnalogOutputConfig {
+    /// The holding register that the output value is written to
+    pub register: u16,
+    /// The register value for 0%
+    pub min: u16,
+    /// The register value for 100%
+    pub max: u16,
+}
+
+impl AnalogOutputConfig {
+    /// Scales a percentage (0-100) to a register value in `[min, max]`
+    ///
+    /// ```rust
+    /// # use brewdrivers::controllers::analog_output::AnalogOutputConfig;
+    /// let config = AnalogOutputConfig { register: 0, min: 0, max: 4000 };
+    /// assert_eq!(config.pct_to_register(50.0), 2000);
+    /// ```
+    pub fn pct_to_register(&self, pct: f64) -> u16 {
+        let pct = pct.clamp(0.0, 100.0);
+        let span = self.max as f64 - self.min as f64;
+        (self.min as f64 + span * pct / 100.0).round() as u16
+    }
+
+    /// Scales a register value back to a percentage (0-100)
+    ///
+    /// ```rust
+    /// # use brewdrivers::controllers::analog_output::AnalogOutputConfig;
+    /// let config = AnalogOutputConfig { register: 0, min: 0, max: 4000 };
+    /// assert_eq!(config.register_to_pct(1000), 25.0);
+    /// ```
+    pub fn register_to_pct(&self, value: u16) -> f64 {
+        let span = self.max as f64 - self.min as f64;
+        if span == 0.0 {
+            return 0.0;
+        }
+        ((value as f64 - self.min as f64) / span * 100.0).clamp(0.0, 100.0)
+    }
+}
+
+/// A generic Modbus analog output
+#[derive(Debug)]
+pub struct AnalogOutput<I = ModbusInstrument> {
+    instr: I,
+    config: AnalogOutputConfig,
+}
+
+#[async_trait]
+impl SCADADevice for AnalogOutput {
+    /// Reads the output register back into `output_pct`
+    async fn update(device: &mut Device) -> Result<()> {
+        device_trace!(device, "updating AnalogOutput device...");
+        // A mock output given to the device (see `Device::attach_mock`) stands in for the real one
+        match device.take_connection::<AnalogOutput<MockModbusInstrument>>() {
+            Some(mock) => mock.update_device(device).await?,
+            None => match device.take_connection::<AnalogOutput>() {
+                Some(ao) => ao.update_device(device).await?,
+                None => {
+                    AnalogOutput::from_device(device)
+                        .await?
+                        .update_device(device)
+                        .await?
+                }
+            },
+        }
+        device_trace!(device, "updated");
+        Ok(())
+    }
+
+    /// Writes `output_pct` to the output register
+    async fn enact(device: &mut Device) -> Result<()> {
+        device_trace!(device, "enacting AnalogOutput device...");
+        match device.take_connection::<AnalogOutput<MockModbusInstrument>>() {
+            Some(mock) => mock.enact_device(device).await?,
+            None => match device.take_connection::<AnalogOutput>() {
+                Some(ao) => ao.enact_device(device).await?,
+                None => {
+                    AnalogOutput::from_device(device)
+                        .await?
+                        .enact_device(device)
+                        .await?
+                }
+            },
+        }
+        device_trace!(device, "enacted");
+        Ok(())
+    }
+}
+
+impl AnalogOutput {
+    /// Opens the port for an analog output. Like [`ModbusInstrument::new`](crate::drivers::ModbusInstrument::new),
+    /// this doesn't fail if the device is unresponsive, only if the port can't be opened.
+    pub async fn connect(
+        slave_addr: u8,
+        port_path: &str,
+        baudrate: u64,
+        timeout: Duration,
+        config: AnalogOutputConfig,
+    ) -> Result<Self> {
+        trace!("[AnalogOutput addr: {}] connecting", slave_addr);
+        let instr = ModbusInstrument::new(slave_addr, port_path, baudrate, timeout).await?;
+        Ok(AnalogOutput { instr, config })
+    }
+
+    /// Tries to connect to the analog output using the connection details from a `Device`
+    pub async fn from_device(device: &Device) -> Result<Self> {
+        let c = &device.conn;
+        let config = c.analog_output.clone().ok_or(InstrumentError::modbusError(
+            format!(
+                "device `{}` is an AnalogOutput but has no `analog_output` connection settings",
+                device.id
+            ),
+            Some(c.controller_addr()),
+        ))?;
+
+        Self::connect(
+            c.controller_addr(),
+            &c.port(),
+            *c.baudrate() as u64,
+            c.timeout(),
+            config,
+        )
+        .await
+    }
+}
+
+impl AnalogOutput<MockModbusInstrument> {
+    /// An analog output on an in-memory mock instrument, for testing without hardware.
+    /// See [`MockModbusInstrument`](crate::drivers::mock::MockModbusInstrument).
+    pub fn connect_mock(slave_addr: u8, config: AnalogOutputConfig) -> Self {
+        AnalogOutput {
+            instr: MockModbusInstrument::new(slave_addr),
+            config,
+        }
+    }
+
+    /// The mock instrument, to check or change its registers
+    pub fn mock(&mut self) -> &mut MockModbusInstrument {
+        &mut self.instr
+    }
+}
+
+impl<I: ModbusDriver + Send + 'static> AnalogOutput<I> {
+    // Reads the output into the device's state, then hands the output back to the device
+    async fn update_device(mut self, device: &mut Device) -> Result<()> {
+        device.state.output_pct = Some(self.get_output_pct().await?);
+        device.store_connection(self);
+        Ok(())
+    }
+
+    // Writes the device's output percentage, then hands the output back to the device
+    async fn enact_device(mut self, device: &mut Device) -> Result<()> {
+        match device.state.output_pct {
+            Some(pct) => self.set_output_pct(pct).await?,
+            None => {
+                return Err(InstrumentError::StateError(StateError::BadValue(
+                    device.state.clone(),
+                )))
+            }
+        }
+        device.store_connection(self);
+        Ok(())
+    }
+
+    /// Sets the output to a percentage, 0-100
+    pub async fn set_output_pct(&mut self, pct: f64) -> Result<()> {
+        trace!(
+            "[AnalogOutput addr: {}] setting output: {pct}%",
+            self.instr.address()
+        );
+        if !(0.0..=100.0).contains(&pct) {
+            return Err(InstrumentError::modbusError(
+                format!("output percentage must be in the range [0, 100], got {pct}"),
+                Some(self.instr.address()),
+            ));
+        }
+
+        let value = self.config.pct_to_register(pct);
+        self.instr.write_register(self.config.register, value).await
+    }
+
+    /// Reads the output as a percentage, 0-100
+    pub async fn get_output_pct(&mut self) -> Result<f64> {
+        trace!(
+            "[AnalogOutput addr: {}] getting output",
+            self.instr.address()
+        );
+        self.instr
+            .read_registers(self.config.register, 1)
+            .await
+            .map(|vec| self.config.register_to_pct(vec[0]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> AnalogOutputConfig {
+        AnalogOutputConfig {
+            register: 0,
+            min: 0,
+            max: 4095,
+        }
+    }
+
+    #[test]
+    fn test_pct_to_register() {
+        let c = config();
+        assert_eq!(c.pct_to_register(0.0), 0);
+        assert_eq!(c.pct_to_register(50.0), 2048);
+        assert_eq!(c.pct_to_register(100.0), 4095);
+    }
+
+    #[test]
+    fn test_pct_to_register_with_offset() {
+        // 4-20mA outputs often have a nonzero minimum
+        let c = AnalogOutputConfig {
+            register: 0,
+            min: 800,
+            max: 4000,
+        };
+        assert_eq!(c.pct_to_register(0.0), 800);
+        assert_eq!(c.pct_to_register(50.0), 2400);
+        assert_eq!(c.pct_to_register(100.0), 4000);
+    }
+
+    #[test]
+    fn test_register_to_pct() {
+        let c = config();
+        assert_eq!(c.register_to_pct(0), 0.0);
+        assert!((c.register_to_pct(2048) - 50.0).abs() < 0.1);
+        assert_eq!(c.register_to_pct(4095), 100.0);
+    }
+}
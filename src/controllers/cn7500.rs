@@ -4,47 +4,184 @@
 //! is a PID that we use to regulate temperatures within the BCS. This module provides a driver implementation for it, based on the
 //! [`ModbusInstrument`](crate::drivers::ModbusInstrument) driver.
 //!
-//! Note: you can set the temperature units (`F` or `C`) of the board with [`CN7500::set_degrees`](crate::controllers::CN7500::set_degrees).
+//! Note: you can set the temperature units (`F` or `C`) of the board with [`CN7500::set_degrees`](crate::controllers::CN7500::set_degrees),
+//! and read them with [`CN7500::get_degrees`](crate::controllers::CN7500::get_degrees).
 //! All units returned from the board or sent to it (when setting the setpoint value) will use the unit that the board is configured to at the time.
+use std::fmt;
 use std::time::Duration;
 
-use crate::drivers::{modbus::ModbusInstrument, InstrumentError, Result};
+use crate::drivers::mock::MockModbusInstrument;
+use crate::drivers::{modbus::ModbusInstrument, InstrumentError, ModbusDriver, Result};
 use crate::logging_utils::device_trace;
 use crate::model::{Device, SCADADevice};
 use crate::state::BinaryState;
 use async_trait::async_trait;
 use log::trace;
+use serde::{Deserialize, Serialize};
 
 pub const CN7500_BAUDRATES: [usize; 5] = [2400, 4800, 9600, 19200, 38400];
 
-#[derive(Debug, Clone)]
+/// The proportional band (P) register. Holds tenths, 0.1-999.9
+pub const CN7500_P_REGISTER: u16 = 0x1009;
+/// The integral time (I) register, in seconds, 0-9999
+pub const CN7500_I_REGISTER: u16 = 0x100A;
+/// The derivative time (D) register, in seconds, 0-9999
+pub const CN7500_D_REGISTER: u16 = 0x100B;
+
+/// The upper limit setpoint register for alarm 1. Holds tenths, like the SV
+pub const CN7500_ALARM1_SV_REGISTER: u16 = 0x1024;
+/// The upper limit setpoint register for alarm 2. Holds tenths, like the SV
+pub const CN7500_ALARM2_SV_REGISTER: u16 = 0x1026;
+/// The LED status register, one bit for each front panel LED. The alarm outputs are read from here.
+pub const CN7500_LED_STATUS_REGISTER: u16 = 0x102A;
+/// The bit in the LED status register that's set while alarm 1 is active
+pub const CN7500_ALARM1_LED_BIT: u16 = 1 << 4;
+/// The bit in the LED status register that's set while alarm 2 is active
+pub const CN7500_ALARM2_LED_BIT: u16 = 1 << 1;
+
+/// The control mode register. Ramp/soak programs only run in program control mode.
+pub const CN7500_CONTROL_MODE_REGISTER: u16 = 0x1005;
+/// The value of the control mode register for program (ramp/soak) control
+pub const CN7500_PROGRAM_CONTROL_MODE: u16 = 3;
+/// The register holding which pattern a program starts from
+pub const CN7500_START_PATTERN_REGISTER: u16 = 0x1030;
+/// The register holding the last step (0-7) of pattern 0
+pub const CN7500_PATTERN0_LAST_STEP_REGISTER: u16 = 0x1040;
+/// The first of pattern 0's step temperatures, one register per step. Hold tenths, like the SV
+pub const CN7500_PATTERN0_TEMP_REGISTER: u16 = 0x2000;
+/// The first of pattern 0's step times, one register per step, in minutes
+pub const CN7500_PATTERN0_TIME_REGISTER: u16 = 0x2080;
+/// The coil that stops program control when it's on, and starts it when it's off
+pub const CN7500_PROGRAM_STOP_COIL: u16 = 0x0815;
+/// The most steps a pattern can have
+pub const CN7500_MAX_PROGRAM_STEPS: usize = 8;
+/// The longest a step can be held, in minutes
+pub const CN7500_MAX_STEP_MINUTES: u16 = 900;
+
+/// The decimal point selection coil: off for whole degrees, on for tenths of a degree.
+/// The PV, SV, alarm setpoints, and program temperatures are all scaled by it.
+pub const CN7500_DECIMAL_POSITION_COIL: u16 = 0x0812;
+/// The decimal point position boards ship with, tenths of a degree
+pub const CN7500_DEFAULT_DECIMAL_POSITION: u8 = 1;
+
+/// The register holding the board's firmware version
+pub const CN7500_SOFTWARE_REVISION_REGISTER: u16 = 0x102F;
+
+/// How many times [`CN7500::connect`](crate::controllers::CN7500::connect) probes the board before giving up
+pub const CN7500_CONNECT_ATTEMPTS: u8 = 3;
+/// How long [`CN7500::connect`](crate::controllers::CN7500::connect) waits between probes
+pub const CN7500_CONNECT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// How many times a read is tried if the board times out. Every read except the connection probe is retried,
+/// the probe has its own retries (see [`CN7500_CONNECT_ATTEMPTS`](crate::controllers::cn7500::CN7500_CONNECT_ATTEMPTS)).
+pub const CN7500_READ_ATTEMPTS: u8 = 3;
+/// How long to wait before trying a read again
+pub const CN7500_READ_RETRY_DELAY: Duration = Duration::from_millis(50);
+
+/// The coil that holds the board's temperature unit, on for Celsius and off for Fahrenheit
+pub const CN7500_DEGREE_COIL: u16 = 0x0811;
+
+/// The temperature unit the CN7500 reads and takes values in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum Degree {
     Fahrenheit,
     Celsius,
 }
 
+/// The CN7500's firmware version, see [`CN7500::software_revision`](crate::controllers::CN7500::software_revision)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SoftwareRevision {
+    pub major: u8,
+    /// The digits after the point, ie. `10` for v1.10
+    pub minor: u8,
+}
+
+impl SoftwareRevision {
+    // The board reports its version in hex digits (BCD), ie. `0x0110` is v1.10. A value that doesn't
+    // look like that most likely came from a different kind of device.
+    fn from_register(value: u16) -> Option<Self> {
+        let [major, minor] = value.to_be_bytes();
+        let decimal_digits = |byte: u8| byte >> 4 <= 9 && byte & 0x0F <= 9;
+        if (1..=9).contains(&major) && decimal_digits(minor) {
+            Some(SoftwareRevision {
+                major,
+                minor: (minor >> 4) * 10 + (minor & 0x0F),
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// Formats like `v1.10`
+impl fmt::Display for SoftwareRevision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "v{}.{:02}", self.major, self.minor)
+    }
+}
+
+/// One of the CN7500's alarm outputs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alarm {
+    Alarm1,
+    Alarm2,
+}
+
+impl Alarm {
+    fn sv_register(&self) -> u16 {
+        match self {
+            Alarm::Alarm1 => CN7500_ALARM1_SV_REGISTER,
+            Alarm::Alarm2 => CN7500_ALARM2_SV_REGISTER,
+        }
+    }
+}
+
+/// Whether each alarm output is active, see [`CN7500::get_alarm_status`](crate::controllers::CN7500::get_alarm_status)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AlarmStatus {
+    pub alarm1: bool,
+    pub alarm2: bool,
+}
+
+impl AlarmStatus {
+    /// True if either alarm is active
+    pub fn any(&self) -> bool {
+        self.alarm1 || self.alarm2
+    }
+}
+
 /// A CN7500 PID Controller
+///
+/// It normally runs on a [`ModbusInstrument`](crate::drivers::ModbusInstrument), but can run on a mock
+/// for testing, see [`CN7500::connect_mock`](crate::controllers::CN7500::connect_mock). It also remembers the board's
+/// decimal point position, which is read when connecting, see [`get_decimal_position`](crate::controllers::CN7500::get_decimal_position).
 #[derive(Debug)]
-pub struct CN7500(ModbusInstrument);
+pub struct CN7500<I = ModbusInstrument>(I, u8);
 
 #[async_trait]
 impl SCADADevice for CN7500 {
     /// Updates the given device state using this controller
     async fn update(device: &mut Device) -> Result<()> {
         device_trace!(device, "updating CN7500 device...");
-
-        let mut cn = CN7500::connect(
-            device.conn.controller_addr(),
-            &device.conn.port(),
-            *device.conn.baudrate() as u64,
-            device.conn.timeout(),
-        )
-        .await?;
-
-        device.state.relay_state = Some(cn.is_running().await?.into());
-        device.state.pv = Some(cn.get_pv().await?);
-        device.state.sv = Some(cn.get_sv().await?);
-
+        // A mock CN7500 given to the device (see `Device::attach_mock`) stands in for the real one
+        match device.take_connection::<CN7500<MockModbusInstrument>>() {
+            Some(mock) => mock.update_device(device).await?,
+            None => match device.take_connection::<CN7500>() {
+                Some(cn) => cn.update_device(device).await?,
+                None => {
+                    CN7500::connect(
+                        device.conn.controller_addr(),
+                        &device.conn.port(),
+                        *device.conn.baudrate() as u64,
+                        device.conn.timeout(),
+                    )
+                    .await?
+                    .update_device(device)
+                    .await?
+                }
+            },
+        }
         device_trace!(device, "updated");
         Ok(())
     }
@@ -52,50 +189,126 @@ impl SCADADevice for CN7500 {
     /// Writes the given device state to this controller
     async fn enact(device: &mut Device) -> Result<()> {
         device_trace!(device, "enacting CN7500 device...");
+        match device.take_connection::<CN7500<MockModbusInstrument>>() {
+            Some(mock) => mock.enact_device(device).await?,
+            None => match device.take_connection::<CN7500>() {
+                Some(cn) => cn.enact_device(device).await?,
+                None => {
+                    CN7500::connect(
+                        device.conn.controller_addr(),
+                        &device.conn.port(),
+                        *device.conn.baudrate() as u64,
+                        device.conn.timeout(),
+                    )
+                    .await?
+                    .enact_device(device)
+                    .await?
+                }
+            },
+        }
+        device_trace!(device, "enacted");
+        Ok(())
+    }
+}
 
-        let mut cn = CN7500::connect(
-            device.conn.controller_addr(),
-            &device.conn.port(),
-            *device.conn.baudrate() as u64,
-            device.conn.timeout(),
-        )
-        .await?;
+impl<I: ModbusDriver + Send + 'static> CN7500<I> {
+    // Reads the device's run state, PV, and SV into its state, then hands the CN7500 back to the device
+    async fn update_device(mut self, device: &mut Device) -> Result<()> {
+        device.state.relay_state = Some(self.is_running().await?.into());
+        let (pv, sv) = self.get_pv_sv().await?;
+        device.state.pv = Some(pv);
+        device.state.sv = Some(sv);
+
+        device.store_connection(self);
+        Ok(())
+    }
 
+    // Writes the device's run state and SV to the CN7500, then hands it back to the device
+    async fn enact_device(mut self, device: &mut Device) -> Result<()> {
         match device.state.relay_state {
-            Some(BinaryState::On) => cn.run().await?,
-            Some(BinaryState::Off) => cn.stop().await?,
+            Some(BinaryState::On) => self.run().await?,
+            Some(BinaryState::Off) => self.stop().await?,
             None => {}
         }
 
         if let Some(new_sv) = device.state.sv {
-            cn.set_sv(new_sv).await?;
+            self.set_sv(new_sv).await?;
         }
 
-        device_trace!(device, "enacted");
+        device.store_connection(self);
         Ok(())
     }
 }
 
 impl CN7500 {
     /// Connects to a CN7500 board
+    ///
+    /// Right after it powers up, the CN7500 is often too slow to answer its first request, so the
+    /// board is probed up to [`CN7500_CONNECT_ATTEMPTS`](crate::controllers::cn7500::CN7500_CONNECT_ATTEMPTS) times.
+    /// See [`CN7500::connect_with_retries`](crate::controllers::CN7500::connect_with_retries).
     pub async fn connect(
         slave_addr: u8,
         port_path: &str,
         baudrate: u64,
         timeout: Duration,
     ) -> Result<Self> {
-        trace!("[CN7500 addr: {}] connected", slave_addr);
-        let mut cn = CN7500(ModbusInstrument::new(slave_addr, port_path, baudrate, timeout).await?);
-        cn.connected().await.map_err(|instr_err| {
-            InstrumentError::modbusError(
-                format!(
-                    "CN7500 connection failed, likely busy. Error: {}",
-                    instr_err
-                ),
-                Some(slave_addr),
-            )
-        })?;
-        Ok(cn)
+        Self::connect_with_retries(
+            slave_addr,
+            port_path,
+            baudrate,
+            timeout,
+            CN7500_CONNECT_ATTEMPTS,
+            CN7500_CONNECT_RETRY_DELAY,
+        )
+        .await
+    }
+
+    /// The same as [`CN7500::connect`](crate::controllers::CN7500::connect), but you choose how many times
+    /// to probe the board and how long to wait in between. Only the probe is retried, if the port can't be
+    /// opened this fails right away.
+    pub async fn connect_with_retries(
+        slave_addr: u8,
+        port_path: &str,
+        baudrate: u64,
+        timeout: Duration,
+        attempts: u8,
+        retry_delay: Duration,
+    ) -> Result<Self> {
+        let mut cn = CN7500(
+            ModbusInstrument::new(slave_addr, port_path, baudrate, timeout).await?,
+            CN7500_DEFAULT_DECIMAL_POSITION,
+        );
+
+        let attempts = attempts.max(1);
+        for attempt in 1..=attempts {
+            match cn.connected().await {
+                Ok(_) => {
+                    trace!("[CN7500 addr: {}] connected", slave_addr);
+                    cn.get_decimal_position().await?;
+                    return Ok(cn);
+                }
+                Err(instr_err) if attempt == attempts => {
+                    return Err(InstrumentError::modbusError(
+                        format!(
+                            "CN7500 connection failed after {} attempts, likely busy. Error: {}",
+                            attempts, instr_err
+                        ),
+                        Some(slave_addr),
+                    ));
+                }
+                Err(_) => {
+                    trace!(
+                        "[CN7500 addr: {}] no response (attempt {} of {}), it may still be powering up",
+                        slave_addr,
+                        attempt,
+                        attempts
+                    );
+                    tokio::time::sleep(retry_delay).await;
+                }
+            }
+        }
+
+        unreachable!("the last attempt always returns")
     }
 
     /// Tries to connect to the CN7500 using the connection details from a `Device`
@@ -112,82 +325,465 @@ impl CN7500 {
         .await
     }
 
+    // The PV register holds tenths of a degree, unless something's wrong with the input,
+    // then it holds one of these codes instead
+    fn pv_from_register(value: u16, scale: f64, addr: u8) -> Result<f64> {
+        let reason = match value {
+            0x8002 => "initial process, no reading yet",
+            0x8003 => "temperature sensor not connected",
+            0x8004 => "temperature sensor input error",
+            0x8006 => "ADC input error",
+            0x8007 => "memory read/write error",
+            _ => return Ok((value as f64) / scale),
+        };
+
+        Err(InstrumentError::SensorFault {
+            addr,
+            value,
+            reason,
+        })
+    }
+}
+
+impl<I: ModbusDriver> CN7500<I> {
     /// Returns `Ok(())` if the instrument is connected, `Err(InstrumentError)` otherwise.
     pub async fn connected(&mut self) -> Result<()> {
-        // Try to read a coil, this could really be anything
-        // Note: 'running' means the relay is on, not that the CN7500 is on
+        // The revision is checked, so this also fails if a different device is at this address
         self.software_revision().await?;
         Ok(())
     }
 
+    // Reads registers, trying again if the board times out. The CN7500 sometimes misses a request while it's busy
+    // answering another one, so a single timeout isn't worth failing over. Other errors aren't retried.
+    async fn read_registers(&mut self, register: u16, count: u16) -> Result<Vec<u16>> {
+        let mut attempt = 1;
+        loop {
+            match self.0.read_registers(register, count).await {
+                Err(InstrumentError::ModbusTimeoutError { .. })
+                    if attempt < CN7500_READ_ATTEMPTS =>
+                {
+                    Self::before_read_retry(self.0.address(), attempt).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    // The same as `read_registers`, for coils
+    async fn read_coils(&mut self, coil: u16, count: u16) -> Result<Vec<bool>> {
+        let mut attempt = 1;
+        loop {
+            match self.0.read_coils(coil, count).await {
+                Err(InstrumentError::ModbusTimeoutError { .. })
+                    if attempt < CN7500_READ_ATTEMPTS =>
+                {
+                    Self::before_read_retry(self.0.address(), attempt).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    // Takes the address instead of `&self`, so the future doesn't hold a borrow of the client across the sleep.
+    // The Modbus client isn't `Sync`, and holding `&self` here would make `update` and `enact` non-`Send`.
+    async fn before_read_retry(address: u8, attempt: u8) {
+        trace!(
+            "[CN7500 addr: {}] read timed out (attempt {} of {}), trying again",
+            address,
+            attempt,
+            CN7500_READ_ATTEMPTS
+        );
+        tokio::time::sleep(CN7500_READ_RETRY_DELAY).await;
+    }
+
+    // How many register counts make up one degree, from the decimal position
+    fn scale(&self) -> f64 {
+        10f64.powi(self.1 as i32)
+    }
+
+    /// Reads the decimal point position, how many digits after the decimal point the PV and SV have.
+    /// `0` is whole degrees and `1` is tenths.
+    ///
+    /// This is read when connecting and cached. Calling this reads it again, in case it was changed on the board.
+    pub async fn get_decimal_position(&mut self) -> Result<u8> {
+        trace!(
+            "[CN7500 addr: {}] getting decimal position",
+            self.0.address()
+        );
+        let tenths = self.read_coils(CN7500_DECIMAL_POSITION_COIL, 1).await?[0];
+        self.1 = tenths as u8;
+        Ok(self.1)
+    }
+
+    /// Sets the decimal point position, 0 or 1. See [`get_decimal_position`](crate::controllers::CN7500::get_decimal_position).
+    pub async fn set_decimal_position(&mut self, position: u8) -> Result<()> {
+        trace!(
+            "[CN7500 addr: {}] setting decimal position: {position}",
+            self.0.address()
+        );
+        if position > 1 {
+            return Err(InstrumentError::modbusError(
+                format!("decimal position must be 0 or 1, got {position}"),
+                Some(self.0.address()),
+            ));
+        }
+        self.0
+            .write_coil(CN7500_DECIMAL_POSITION_COIL, position == 1)
+            .await?;
+        self.1 = position;
+        Ok(())
+    }
+
     /// Sets the setpoint value (target) of the CN7500. Should be a decimal between 1.0-999.0.
     pub async fn set_sv(&mut self, new_sv: f64) -> Result<()> {
-        trace!("[CN7500 addr: {}] Setting sv: {new_sv}", self.0.slave_addr);
-        self.0.write_register(0x1001, (new_sv * 10.0) as u16).await
+        trace!("[CN7500 addr: {}] Setting sv: {new_sv}", self.0.address());
+        self.0
+            .write_register(0x1001, (new_sv * self.scale()) as u16)
+            .await
     }
 
     /// Gets the setpoint value
     pub async fn get_sv(&mut self) -> Result<f64> {
-        trace!("[CN7500 addr: {}] getting sv", self.0.slave_addr);
+        trace!("[CN7500 addr: {}] getting sv", self.0.address());
+        self.read_registers(0x1001, 1)
+            .await
+            .map(|vec| (vec[0] as f64) / self.scale())
+    }
+
+    /// Sets the proportional band (P). Should be a decimal between 0.1-999.9, it's stored in tenths like the SV.
+    pub async fn set_p(&mut self, new_p: f64) -> Result<()> {
+        trace!("[CN7500 addr: {}] setting p: {new_p}", self.0.address());
+        if !(0.1..=999.9).contains(&new_p) {
+            return Err(InstrumentError::modbusError(
+                format!("P must be in the range [0.1, 999.9], got {new_p}"),
+                Some(self.0.address()),
+            ));
+        }
         self.0
-            .read_registers(0x1001, 1)
+            .write_register(CN7500_P_REGISTER, (new_p * 10.0).round() as u16)
+            .await
+    }
+
+    /// Gets the proportional band (P)
+    pub async fn get_p(&mut self) -> Result<f64> {
+        trace!("[CN7500 addr: {}] getting p", self.0.address());
+        self.read_registers(CN7500_P_REGISTER, 1)
             .await
             .map(|vec| (vec[0] as f64) / 10.0)
     }
 
+    /// Sets the integral time (I) in seconds, 0-9999
+    pub async fn set_i(&mut self, new_i: u16) -> Result<()> {
+        trace!("[CN7500 addr: {}] setting i: {new_i}", self.0.address());
+        self.set_pid_time(CN7500_I_REGISTER, "I", new_i).await
+    }
+
+    /// Gets the integral time (I) in seconds
+    pub async fn get_i(&mut self) -> Result<u16> {
+        trace!("[CN7500 addr: {}] getting i", self.0.address());
+        self.read_registers(CN7500_I_REGISTER, 1)
+            .await
+            .map(|vec| vec[0])
+    }
+
+    /// Sets the derivative time (D) in seconds, 0-9999
+    pub async fn set_d(&mut self, new_d: u16) -> Result<()> {
+        trace!("[CN7500 addr: {}] setting d: {new_d}", self.0.address());
+        self.set_pid_time(CN7500_D_REGISTER, "D", new_d).await
+    }
+
+    /// Gets the derivative time (D) in seconds
+    pub async fn get_d(&mut self) -> Result<u16> {
+        trace!("[CN7500 addr: {}] getting d", self.0.address());
+        self.read_registers(CN7500_D_REGISTER, 1)
+            .await
+            .map(|vec| vec[0])
+    }
+
+    // I and D are both whole seconds, 0-9999
+    async fn set_pid_time(&mut self, register: u16, name: &str, seconds: u16) -> Result<()> {
+        if seconds > 9999 {
+            return Err(InstrumentError::modbusError(
+                format!("{name} must be in the range [0, 9999], got {seconds}"),
+                Some(self.0.address()),
+            ));
+        }
+        self.0.write_register(register, seconds).await
+    }
+
     /// Gets the process value
+    ///
+    /// If the CN7500 reports a fault instead of a temperature (ie. the thermocouple
+    /// is disconnected), this returns [`InstrumentError::SensorFault`](crate::drivers::InstrumentError::SensorFault).
     pub async fn get_pv(&mut self) -> Result<f64> {
-        trace!("[CN7500 addr: {}] getting pv", self.0.slave_addr);
-        self.0
-            .read_registers(0x1000, 1)
-            .await
-            .map(|vec| (vec[0] as f64) / 10.0)
+        trace!("[CN7500 addr: {}] getting pv", self.0.address());
+        let value = self.read_registers(0x1000, 1).await?[0];
+        CN7500::pv_from_register(value, self.scale(), self.0.address())
+    }
+
+    /// Gets the process value and setpoint value together, as `(pv, sv)`. They're in adjacent registers,
+    /// so this reads both in one Modbus transaction instead of one each with [`get_pv`](Self::get_pv) and
+    /// [`get_sv`](Self::get_sv).
+    ///
+    /// Like [`get_pv`](Self::get_pv), this returns [`InstrumentError::SensorFault`](crate::drivers::InstrumentError::SensorFault)
+    /// if the CN7500 reports a fault instead of a temperature.
+    pub async fn get_pv_sv(&mut self) -> Result<(f64, f64)> {
+        trace!("[CN7500 addr: {}] getting pv and sv", self.0.address());
+        let values = self.read_registers(0x1000, 2).await?;
+        let pv = CN7500::pv_from_register(values[0], self.scale(), self.0.address())?;
+        Ok((pv, (values[1] as f64) / self.scale()))
     }
 
     /// Returns `Ok(true)` if the relay is activated. The relay may or may not be on if it's activated,
     /// because the PID will control when to feather the relay on or off to control temperature. The relay
     /// will never be on if it's not active (ie. this method returns `Ok(false)`)
     pub async fn is_running(&mut self) -> Result<bool> {
-        trace!("[CN7500 addr: {}] polled is running", self.0.slave_addr);
-        self.0.read_coils(0x0814, 1).await.map(|vals| vals[0])
+        trace!("[CN7500 addr: {}] polled is running", self.0.address());
+        self.read_coils(0x0814, 1).await.map(|vals| vals[0])
     }
 
     /// Activates the relay
     pub async fn run(&mut self) -> Result<()> {
-        trace!("[CN7500 addr: {}] set to run", self.0.slave_addr);
+        trace!("[CN7500 addr: {}] set to run", self.0.address());
         self.0.write_coil(0x0814, true).await
     }
 
     /// Deactivates the relay
     pub async fn stop(&mut self) -> Result<()> {
-        trace!("[CN7500 addr: {}] set to stop", self.0.slave_addr);
+        trace!("[CN7500 addr: {}] set to stop", self.0.address());
         self.0.write_coil(0x0814, false).await
     }
 
+    /// Sets the setpoint, starts the controller, then reads both back to confirm they took.
+    ///
+    /// Returns the confirmed `(sv, running)`. If the readback doesn't match what was written,
+    /// this returns an error, so a partial failure can't go unnoticed.
+    pub async fn apply_setpoint_and_run(&mut self, sv: f64) -> Result<(f64, bool)> {
+        trace!(
+            "[CN7500 addr: {}] applying sv {sv} and running",
+            self.0.address()
+        );
+        self.set_sv(sv).await?;
+        self.run().await?;
+
+        let confirmed_sv = self.get_sv().await?;
+        let running = self.is_running().await?;
+
+        // The SV is stored in tenths, so compare against what actually fits in the register
+        let expected_sv = ((sv * self.scale()) as u16 as f64) / self.scale();
+        if confirmed_sv != expected_sv || !running {
+            return Err(InstrumentError::modbusError(
+                format!(
+                    "readback didn't match after applying setpoint: wrote sv {expected_sv} and run, read sv {confirmed_sv}, running = {running}"
+                ),
+                Some(self.0.address()),
+            ));
+        }
+
+        Ok((confirmed_sv, running))
+    }
+
+    /// Reads which alarm outputs are active
+    pub async fn get_alarm_status(&mut self) -> Result<AlarmStatus> {
+        trace!("[CN7500 addr: {}] getting alarm status", self.0.address());
+        let leds = self.read_registers(CN7500_LED_STATUS_REGISTER, 1).await?[0];
+        Ok(AlarmStatus {
+            alarm1: leds & CN7500_ALARM1_LED_BIT != 0,
+            alarm2: leds & CN7500_ALARM2_LED_BIT != 0,
+        })
+    }
+
+    /// Sets the setpoint (upper limit) of an alarm. Like the SV, should be a decimal between 1.0-999.0.
+    pub async fn set_alarm_sv(&mut self, alarm: Alarm, new_sv: f64) -> Result<()> {
+        trace!(
+            "[CN7500 addr: {}] setting {:?} sv: {new_sv}",
+            self.0.address(),
+            alarm
+        );
+        if !(1.0..=999.0).contains(&new_sv) {
+            return Err(InstrumentError::modbusError(
+                format!("alarm setpoint must be in the range [1.0, 999.0], got {new_sv}"),
+                Some(self.0.address()),
+            ));
+        }
+        self.0
+            .write_register(alarm.sv_register(), (new_sv * self.scale()).round() as u16)
+            .await
+    }
+
+    /// Gets the setpoint (upper limit) of an alarm
+    pub async fn get_alarm_sv(&mut self, alarm: Alarm) -> Result<f64> {
+        trace!("[CN7500 addr: {}] getting {:?} sv", self.0.address(), alarm);
+        self.read_registers(alarm.sv_register(), 1)
+            .await
+            .map(|vec| (vec[0] as f64) / self.scale())
+    }
+
+    /// Writes a ramp/soak program to pattern 0, as `(temperature, hold minutes)` steps. The board ramps to each
+    /// temperature and holds it for that many minutes before moving on to the next step.
+    ///
+    /// There can be 1-8 steps. Temperatures should be between 1.0-999.0 like the SV, and steps can be held for up
+    /// to 900 minutes. This doesn't start the program, see [`start_program`](crate::controllers::CN7500::start_program).
+    pub async fn set_ramp_soak(&mut self, steps: &[(f64, u16)]) -> Result<()> {
+        trace!(
+            "[CN7500 addr: {}] setting ramp/soak program: {:?}",
+            self.0.address(),
+            steps
+        );
+        if steps.is_empty() || steps.len() > CN7500_MAX_PROGRAM_STEPS {
+            return Err(InstrumentError::modbusError(
+                format!(
+                    "a ramp/soak program must have 1-{CN7500_MAX_PROGRAM_STEPS} steps, got {}",
+                    steps.len()
+                ),
+                Some(self.0.address()),
+            ));
+        }
+        for (i, &(temp, minutes)) in steps.iter().enumerate() {
+            if !(1.0..=999.0).contains(&temp) || minutes > CN7500_MAX_STEP_MINUTES {
+                return Err(InstrumentError::modbusError(
+                    format!(
+                        "step {i} must have a temperature in the range [1.0, 999.0] and at most {CN7500_MAX_STEP_MINUTES} minutes, got ({temp}, {minutes})"
+                    ),
+                    Some(self.0.address()),
+                ));
+            }
+        }
+
+        for (i, &(temp, minutes)) in steps.iter().enumerate() {
+            let i = i as u16;
+            self.0
+                .write_register(
+                    CN7500_PATTERN0_TEMP_REGISTER + i,
+                    (temp * self.scale()).round() as u16,
+                )
+                .await?;
+            self.0
+                .write_register(CN7500_PATTERN0_TIME_REGISTER + i, minutes)
+                .await?;
+        }
+        self.0
+            .write_register(CN7500_PATTERN0_LAST_STEP_REGISTER, steps.len() as u16 - 1)
+            .await?;
+        self.0
+            .write_register(CN7500_START_PATTERN_REGISTER, 0)
+            .await
+    }
+
+    /// Reads the ramp/soak program in pattern 0 back as `(temperature, hold minutes)` steps
+    pub async fn get_ramp_soak(&mut self) -> Result<Vec<(f64, u16)>> {
+        trace!(
+            "[CN7500 addr: {}] getting ramp/soak program",
+            self.0.address()
+        );
+        let last_step = self
+            .read_registers(CN7500_PATTERN0_LAST_STEP_REGISTER, 1)
+            .await?[0];
+        let count = (last_step + 1).min(CN7500_MAX_PROGRAM_STEPS as u16);
+
+        let temps = self
+            .read_registers(CN7500_PATTERN0_TEMP_REGISTER, count)
+            .await?;
+        let times = self
+            .read_registers(CN7500_PATTERN0_TIME_REGISTER, count)
+            .await?;
+        Ok(temps
+            .iter()
+            .zip(times)
+            .map(|(&temp, minutes)| ((temp as f64) / self.scale(), minutes))
+            .collect())
+    }
+
+    /// Puts the board in program control mode and starts the ramp/soak program.
+    /// See [`set_ramp_soak`](crate::controllers::CN7500::set_ramp_soak).
+    pub async fn start_program(&mut self) -> Result<()> {
+        trace!("[CN7500 addr: {}] starting program", self.0.address());
+        self.0
+            .write_register(CN7500_CONTROL_MODE_REGISTER, CN7500_PROGRAM_CONTROL_MODE)
+            .await?;
+        self.0.write_coil(CN7500_PROGRAM_STOP_COIL, false).await
+    }
+
+    /// Stops the ramp/soak program. The board stays in program control mode.
+    pub async fn stop_program(&mut self) -> Result<()> {
+        trace!("[CN7500 addr: {}] stopping program", self.0.address());
+        self.0.write_coil(CN7500_PROGRAM_STOP_COIL, true).await
+    }
+
     /// Sets the degree mode of the board to either Fahrenheit or Celsius
     pub async fn set_degrees(&mut self, degree_mode: Degree) -> Result<()> {
         trace!(
             "[CN7500 addr: {}] setting degree mode to {:?}",
-            self.0.slave_addr,
+            self.0.address(),
             degree_mode
         );
         match degree_mode {
-            Degree::Celsius => self.0.write_coil(0x0811, true).await,
-            Degree::Fahrenheit => self.0.write_coil(0x0811, false).await,
+            Degree::Celsius => self.0.write_coil(CN7500_DEGREE_COIL, true).await,
+            Degree::Fahrenheit => self.0.write_coil(CN7500_DEGREE_COIL, false).await,
         }
     }
 
-    pub async fn software_revision(&mut self) -> Result<Vec<u16>> {
+    /// Gets the degree mode the board is in. The PV and SV are in this unit.
+    pub async fn get_degrees(&mut self) -> Result<Degree> {
+        trace!("[CN7500 addr: {}] getting degree mode", self.0.address());
+        match self.read_coils(CN7500_DEGREE_COIL, 1).await?[0] {
+            true => Ok(Degree::Celsius),
+            false => Ok(Degree::Fahrenheit),
+        }
+    }
+
+    /// Reads the board's firmware version, like `v1.10`
+    ///
+    /// If the register holds something that isn't a CN7500 version, this returns an error. That usually
+    /// means a different device is at this address.
+    pub async fn software_revision(&mut self) -> Result<SoftwareRevision> {
         trace!(
             "[CN7500 addr: {}] polled software revision",
-            self.0.slave_addr
+            self.0.address()
         );
-        self.0.read_registers(0x102F, 1).await.map_err(|_|
-            InstrumentError::SerialError {
-                msg: format!("Software revision couldn't be retrieved, the controller likely isn't connected"),
-                addr: Some(self.0.slave_addr)
-            }
-        )
+        let value = self
+            .0
+            .read_registers(CN7500_SOFTWARE_REVISION_REGISTER, 1)
+            .await
+            .map_err(|_| InstrumentError::serialError(
+                String::from(
+                    "Software revision couldn't be retrieved, the controller likely isn't connected",
+                ),
+                Some(self.0.address()),
+            ))?[0];
+
+        SoftwareRevision::from_register(value).ok_or(InstrumentError::serialError(
+            format!(
+                "Software revision {value:#06x} isn't a CN7500 version, check that the right device is at this address"
+            ),
+            Some(self.0.address()),
+        ))
+    }
+}
+
+impl CN7500<MockModbusInstrument> {
+    /// Connects to an in-memory mock CN7500 at the given address, for testing without hardware.
+    /// See [`MockModbusInstrument`](crate::drivers::mock::MockModbusInstrument).
+    ///
+    /// The mock reports software revision v1.00, and uses the default decimal position of tenths.
+    pub async fn connect_mock(slave_addr: u8) -> Result<Self> {
+        let mut instr = MockModbusInstrument::new(slave_addr);
+        instr.set_register(CN7500_SOFTWARE_REVISION_REGISTER, 0x0100);
+        instr.set_coil(
+            CN7500_DECIMAL_POSITION_COIL,
+            CN7500_DEFAULT_DECIMAL_POSITION == 1,
+        );
+        let mut cn = CN7500(instr, CN7500_DEFAULT_DECIMAL_POSITION);
+        cn.connected().await?;
+        cn.get_decimal_position().await?;
+        Ok(cn)
+    }
+
+    /// The mock instrument, to check or change its registers
+    pub fn mock(&mut self) -> &mut MockModbusInstrument {
+        &mut self.0
     }
 }
 
@@ -199,52 +795,365 @@ mod tests {
 
     use tokio::test;
 
-    async fn instr() -> CN7500 {
-        let device = crate::tests::test_device_from_type(Controller::CN7500);
-        CN7500::connect(
+    async fn instr() -> Option<CN7500> {
+        let device = crate::tests::try_test_device_from_type(Controller::CN7500)?;
+        let cn = CN7500::connect(
             device.conn.controller_addr(),
             &device.conn.port(),
             *device.conn.baudrate() as u64,
             device.conn.timeout(),
         )
         .await
-        .unwrap()
+        .unwrap();
+        Some(cn)
+    }
+
+    #[test]
+    async fn test_pv_sensor_fault() {
+        assert_eq!(CN7500::pv_from_register(1457, 10.0, 0x16).unwrap(), 145.7);
+
+        let err = CN7500::pv_from_register(0x8003, 10.0, 0x16).unwrap_err();
+        assert!(matches!(
+            err,
+            InstrumentError::SensorFault {
+                addr: 0x16,
+                value: 0x8003,
+                ..
+            }
+        ));
+        assert!(err.to_string().contains("not connected"));
+
+        for code in [0x8002, 0x8004, 0x8006, 0x8007] {
+            assert!(CN7500::pv_from_register(code, 10.0, 0x16).is_err());
+        }
     }
 
     #[test]
     async fn test_new_cn7500() {
-        let cn = instr().await;
+        let Some(cn) = instr().await else {
+            return;
+        };
         assert_eq!(cn.0.port_path, "/dev/ttyUSB0");
     }
 
+    #[test]
+    async fn test_mock_sv_pv_and_relay() {
+        let mut cn = CN7500::connect_mock(0x16).await.unwrap();
+
+        cn.set_sv(145.7).await.unwrap();
+        assert_eq!(cn.mock().register(0x1001), 1457);
+        assert_eq!(cn.get_sv().await.unwrap(), 145.7);
+
+        cn.mock().set_register(0x1000, 1523);
+        assert_eq!(cn.get_pv().await.unwrap(), 152.3);
+        cn.mock().set_register(0x1000, 0x8003);
+        assert!(matches!(
+            cn.get_pv().await,
+            Err(InstrumentError::SensorFault { .. })
+        ));
+
+        assert!(!cn.is_running().await.unwrap());
+        assert_eq!(
+            cn.apply_setpoint_and_run(150.0).await.unwrap(),
+            (150.0, true)
+        );
+        cn.stop().await.unwrap();
+        assert!(!cn.is_running().await.unwrap());
+    }
+
+    #[test]
+    async fn test_mock_pv_sv() {
+        let mut cn = CN7500::connect_mock(0x16).await.unwrap();
+        cn.mock().set_register(0x1000, 1523);
+        cn.mock().set_register(0x1001, 1457);
+        let reads = cn.mock().register_reads().len();
+
+        assert_eq!(cn.get_pv_sv().await.unwrap(), (152.3, 145.7));
+        assert_eq!(&cn.mock().register_reads()[reads..], &[(0x1000, 2)]);
+
+        cn.mock().set_register(0x1000, 0x8003);
+        assert!(matches!(
+            cn.get_pv_sv().await,
+            Err(InstrumentError::SensorFault { .. })
+        ));
+    }
+
+    #[test]
+    async fn test_mock_read_retries_timeout() {
+        let mut cn = CN7500::connect_mock(0x16).await.unwrap();
+        cn.run().await.unwrap();
+
+        // The first read times out, the second answers
+        cn.mock().time_out_next_reads(1);
+        assert!(cn.is_running().await.unwrap());
+
+        // Every attempt times out
+        cn.mock().time_out_next_reads(CN7500_READ_ATTEMPTS);
+        assert!(matches!(
+            cn.is_running().await,
+            Err(InstrumentError::ModbusTimeoutError {
+                register: 0x0814,
+                ..
+            })
+        ));
+        assert!(cn.is_running().await.unwrap());
+    }
+
+    #[test]
+    async fn test_mock_pid_parameters() {
+        let mut cn = CN7500::connect_mock(0x16).await.unwrap();
+
+        cn.set_p(47.6).await.unwrap();
+        assert_eq!(cn.mock().register(CN7500_P_REGISTER), 476);
+        assert_eq!(cn.get_p().await.unwrap(), 47.6);
+
+        cn.set_i(260).await.unwrap();
+        assert_eq!(cn.mock().register(CN7500_I_REGISTER), 260);
+        assert_eq!(cn.get_i().await.unwrap(), 260);
+
+        cn.set_d(41).await.unwrap();
+        assert_eq!(cn.mock().register(CN7500_D_REGISTER), 41);
+        assert_eq!(cn.get_d().await.unwrap(), 41);
+
+        assert!(cn.set_p(0.0).await.is_err());
+        assert!(cn.set_p(1000.0).await.is_err());
+        assert!(cn.set_i(10000).await.is_err());
+        assert!(cn.set_d(10000).await.is_err());
+        // Nothing was written by the bad values
+        assert_eq!(cn.get_p().await.unwrap(), 47.6);
+    }
+
+    #[test]
+    async fn test_mock_alarms() {
+        let mut cn = CN7500::connect_mock(0x16).await.unwrap();
+        assert_eq!(cn.get_alarm_status().await.unwrap(), AlarmStatus::default());
+
+        // The out LEDs don't count
+        cn.mock().set_register(
+            CN7500_LED_STATUS_REGISTER,
+            CN7500_ALARM2_LED_BIT | 0b0110_0000,
+        );
+        let status = cn.get_alarm_status().await.unwrap();
+        assert_eq!(
+            status,
+            AlarmStatus {
+                alarm1: false,
+                alarm2: true
+            }
+        );
+        assert!(status.any());
+
+        cn.set_alarm_sv(Alarm::Alarm1, 180.5).await.unwrap();
+        assert_eq!(cn.mock().register(CN7500_ALARM1_SV_REGISTER), 1805);
+        assert_eq!(cn.get_alarm_sv(Alarm::Alarm1).await.unwrap(), 180.5);
+        assert_eq!(cn.get_alarm_sv(Alarm::Alarm2).await.unwrap(), 0.0);
+        assert!(cn.set_alarm_sv(Alarm::Alarm2, 1000.0).await.is_err());
+    }
+
+    #[test]
+    async fn test_mock_ramp_soak() {
+        let mut cn = CN7500::connect_mock(0x16).await.unwrap();
+
+        // Mash in at 152 for an hour, then mash out at 168 for 10 minutes
+        let program = [(152.0, 60), (168.5, 10)];
+        cn.set_ramp_soak(&program).await.unwrap();
+        assert_eq!(cn.mock().register(CN7500_PATTERN0_LAST_STEP_REGISTER), 1);
+        assert_eq!(cn.mock().register(CN7500_PATTERN0_TEMP_REGISTER + 1), 1685);
+        assert_eq!(cn.get_ramp_soak().await.unwrap(), program.to_vec());
+
+        cn.start_program().await.unwrap();
+        assert_eq!(
+            cn.mock().register(CN7500_CONTROL_MODE_REGISTER),
+            CN7500_PROGRAM_CONTROL_MODE
+        );
+        assert!(!cn.mock().coil(CN7500_PROGRAM_STOP_COIL));
+        cn.stop_program().await.unwrap();
+        assert!(cn.mock().coil(CN7500_PROGRAM_STOP_COIL));
+
+        assert!(cn.set_ramp_soak(&[]).await.is_err());
+        assert!(cn.set_ramp_soak(&[(152.0, 60); 9]).await.is_err());
+        assert!(cn.set_ramp_soak(&[(1000.0, 60)]).await.is_err());
+        assert!(cn.set_ramp_soak(&[(152.0, 901)]).await.is_err());
+        // Nothing was written by the bad programs
+        assert_eq!(cn.get_ramp_soak().await.unwrap(), program.to_vec());
+    }
+
+    #[test]
+    async fn test_software_revision_range() {
+        let rev = SoftwareRevision::from_register(0x0110).unwrap();
+        assert_eq!(
+            rev,
+            SoftwareRevision {
+                major: 1,
+                minor: 10
+            }
+        );
+        assert_eq!(rev.to_string(), "v1.10");
+        assert_eq!(
+            SoftwareRevision::from_register(0x0205).unwrap().to_string(),
+            "v2.05"
+        );
+
+        for value in [0x0000, 0x0010, 0x0A00, 0x010A, 0xFFFF] {
+            assert!(
+                SoftwareRevision::from_register(value).is_none(),
+                "{value:#06x}"
+            );
+        }
+    }
+
+    #[test]
+    async fn test_mock_wrong_device() {
+        let mut cn = CN7500::connect_mock(0x16).await.unwrap();
+        assert_eq!(cn.software_revision().await.unwrap().to_string(), "v1.00");
+
+        // Something answered at this address, but it isn't a CN7500
+        cn.mock()
+            .set_register(CN7500_SOFTWARE_REVISION_REGISTER, 0x1F40);
+        assert!(matches!(
+            cn.software_revision().await,
+            Err(InstrumentError::SerialError { .. })
+        ));
+        assert!(cn.connected().await.is_err());
+    }
+
+    #[test]
+    async fn test_mock_decimal_position() {
+        let mut cn = CN7500::connect_mock(0x16).await.unwrap();
+        cn.mock().set_register(0x1000, 1234);
+        assert_eq!(cn.get_pv().await.unwrap(), 123.4);
+
+        cn.set_decimal_position(0).await.unwrap();
+        assert!(!cn.mock().coil(CN7500_DECIMAL_POSITION_COIL));
+        assert_eq!(cn.get_pv().await.unwrap(), 1234.0);
+        cn.set_sv(145.0).await.unwrap();
+        assert_eq!(cn.mock().register(0x1001), 145);
+        assert_eq!(cn.get_sv().await.unwrap(), 145.0);
+
+        // Changed on the board, picked up when it's read again
+        cn.mock().set_coil(CN7500_DECIMAL_POSITION_COIL, true);
+        assert_eq!(cn.get_decimal_position().await.unwrap(), 1);
+        assert_eq!(cn.get_pv().await.unwrap(), 123.4);
+
+        assert!(cn.set_decimal_position(2).await.is_err());
+        // Other registers aren't touched
+        assert_eq!(cn.mock().register(0x1050), 0);
+    }
+
+    #[test]
+    async fn test_mock_degrees() {
+        let mut cn = CN7500::connect_mock(0x16).await.unwrap();
+
+        cn.set_degrees(Degree::Celsius).await.unwrap();
+        assert!(cn.mock().coil(CN7500_DEGREE_COIL));
+        assert_eq!(cn.get_degrees().await.unwrap(), Degree::Celsius);
+
+        cn.set_degrees(Degree::Fahrenheit).await.unwrap();
+        assert_eq!(cn.get_degrees().await.unwrap(), Degree::Fahrenheit);
+
+        assert_eq!(
+            serde_yaml::to_string(&Degree::Celsius).unwrap().trim(),
+            "Celsius"
+        );
+    }
+
+    #[test]
+    async fn test_get_alarm_status() {
+        let Some(mut cn) = instr().await else {
+            return;
+        };
+        assert!(cn.get_alarm_status().await.is_ok());
+    }
+
+    #[test]
+    async fn test_set_get_pid_parameters() {
+        let Some(mut cn) = instr().await else {
+            return;
+        };
+        // Put the board's tuning back when we're done
+        let (p, i, d) = (
+            cn.get_p().await.unwrap(),
+            cn.get_i().await.unwrap(),
+            cn.get_d().await.unwrap(),
+        );
+
+        assert!(cn.set_p(47.6).await.is_ok());
+        assert_eq!(cn.get_p().await.unwrap(), 47.6);
+        assert!(cn.set_i(260).await.is_ok());
+        assert_eq!(cn.get_i().await.unwrap(), 260);
+        assert!(cn.set_d(41).await.is_ok());
+        assert_eq!(cn.get_d().await.unwrap(), 41);
+
+        cn.set_p(p).await.unwrap();
+        cn.set_i(i).await.unwrap();
+        cn.set_d(d).await.unwrap();
+    }
+
     #[test]
     async fn test_set_sv() {
-        let mut cn = instr().await;
+        let Some(mut cn) = instr().await else {
+            return;
+        };
         let rsp = cn.set_sv(123.4).await;
         assert!(rsp.is_ok());
     }
 
     #[test]
     async fn test_get_pv() {
-        let mut cn = instr().await;
+        let Some(mut cn) = instr().await else {
+            return;
+        };
         assert!(cn.get_pv().await.unwrap() > 0.0);
     }
 
     #[test]
     async fn test_get_sv() {
-        let mut cn = instr().await;
+        let Some(mut cn) = instr().await else {
+            return;
+        };
         assert!(cn.set_sv(145.7).await.is_ok());
         assert_eq!(cn.get_sv().await.unwrap(), 145.7);
     }
 
     #[test]
     async fn test_turn_on_relay() {
-        let mut cn = instr().await;
+        let Some(mut cn) = instr().await else {
+            return;
+        };
         assert!(cn.run().await.is_ok());
         assert!(cn.is_running().await.unwrap());
         assert!(cn.stop().await.is_ok());
     }
 
+    #[test]
+    async fn test_apply_setpoint_and_run() {
+        let Some(mut cn) = instr().await else {
+            return;
+        };
+        let (sv, running) = cn.apply_setpoint_and_run(152.3).await.unwrap();
+        assert_eq!(sv, 152.3);
+        assert!(running);
+        assert!(cn.stop().await.is_ok());
+    }
+
+    #[test]
+    async fn test_connect_after_power_up() {
+        // Power cycle the CN7500 right before running this one
+        let Some(device) = crate::tests::try_test_device_from_type(Controller::CN7500) else {
+            return;
+        };
+        let cn = CN7500::connect_with_retries(
+            device.conn.controller_addr(),
+            &device.conn.port(),
+            *device.conn.baudrate() as u64,
+            device.conn.timeout(),
+            10,
+            Duration::from_millis(200),
+        )
+        .await;
+        assert!(cn.is_ok());
+    }
+
     #[test]
     async fn test_cn7500_doesnt_respond_when_bad_conn() {
         let cn2 = CN7500::connect(0x18, "/dev/ttyUSB0", 9600, Duration::from_millis(100)).await;
@@ -1,13 +1,20 @@
 //! A controller is a specific implementation of driver, made for one
 //! specific instrument. This module also includes pieces of data like state enums 
 //! that are used by the controller and above layer but not the driver layer.
+use std::str::FromStr;
+use std::time::Duration;
+
 use serde::{Serialize, Deserialize};
+use thiserror::Error;
 
+pub mod analog_output;
 pub mod cn7500;
 pub mod str1;
 pub mod waveshare;
+pub(crate) mod waveshare_common;
 pub mod wavesharev2;
 
+pub use analog_output::AnalogOutput;
 pub use cn7500::CN7500;
 pub use str1::STR1;
 pub use waveshare::Waveshare;
@@ -17,7 +24,10 @@ pub use crate::drivers::InstrumentError;
 
 /// These are the types of controllers that the BCS supports. This enum should reflect every
 /// controller in `brewdrivers::controllers`.
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+///
+/// This is a fieldless enum, so it's `Copy`. Pass it around by value.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum Controller {
     /// An STR1XX relay board. They come in STR116 (16-relay) or STR108 (8-relay).
     /// The driver is the same either way.
@@ -27,7 +37,73 @@ pub enum Controller {
     /// The Waveshare relay board, similar in usage to the STR1
     Waveshare,
     /// Same as `Waveshare`, but software version 2.00
-    WaveshareV2
+    WaveshareV2,
+    /// A generic Modbus analog output, driven by a percentage. See [`AnalogOutput`](crate::controllers::AnalogOutput).
+    AnalogOutput,
+}
+
+impl Controller {
+    /// Returns the lowest timeout that we've found to be reasonably stable for this
+    /// controller at the given baudrate.
+    ///
+    /// These are the values I've tested:
+    ///
+    /// | Controller    | Timeout | Baudrate |
+    /// |---------------|---------|----------|
+    /// | `STR1`        | 17ms    | 38400    |
+    /// | `CN7500`      | 36ms    | 19200    |
+    /// | `WaveshareV2` | 41ms    | 38400    |
+    ///
+    /// The `Waveshare` (v1) hasn't been tested, so it uses the same value as the `WaveshareV2`.
+    /// `AnalogOutput` could be any Modbus device, so it uses the same value as the `CN7500`.
+    /// At a slower baudrate than the one tested, the timeout is scaled up because each byte takes
+    /// longer to send. At a faster baudrate the tested value is kept, we don't have data to go lower.
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use brewdrivers::controllers::Controller;
+    /// assert_eq!(Controller::STR1.recommended_timeout(38400), Duration::from_millis(17));
+    /// assert_eq!(Controller::STR1.recommended_timeout(19200), Duration::from_millis(34));
+    /// ```
+    pub fn recommended_timeout(&self, baudrate: usize) -> Duration {
+        let (tested_ms, tested_baudrate): (u64, usize) = match self {
+            Self::STR1 => (17, 38400),
+            Self::CN7500 | Self::AnalogOutput => (36, 19200),
+            Self::Waveshare | Self::WaveshareV2 => (41, 38400),
+        };
+
+        if baudrate == 0 || baudrate >= tested_baudrate {
+            return Duration::from_millis(tested_ms);
+        }
+
+        // Round up, it's better to wait a little too long than not long enough
+        let scaled = (tested_ms * tested_baudrate as u64).div_ceil(baudrate as u64);
+        Duration::from_millis(scaled)
+    }
+
+    /// The baudrates this controller can run at, from its module (ie. [`STR1_BAUDRATES`](crate::controllers::str1::STR1_BAUDRATES))
+    ///
+    /// ```rust
+    /// # use brewdrivers::controllers::Controller;
+    /// assert!(Controller::CN7500.valid_baudrates().contains(&19200));
+    /// ```
+    pub fn valid_baudrates(&self) -> &'static [usize] {
+        match self {
+            Self::STR1 => &str1::STR1_BAUDRATES,
+            Self::CN7500 => &cn7500::CN7500_BAUDRATES,
+            Self::Waveshare => &waveshare::WAVESHARE_BAUDRATES,
+            Self::WaveshareV2 => &wavesharev2::WAVESHAREV2_BAUDRATES,
+            Self::AnalogOutput => &analog_output::ANALOG_OUTPUT_BAUDRATES,
+        }
+    }
+
+    /// The baudrate this controller ships with. They all come set to 9600.
+    /// For a generic `AnalogOutput` that's only the most common default, check your device.
+    pub fn default_baudrate(&self) -> usize {
+        match self {
+            Self::STR1 | Self::CN7500 | Self::Waveshare | Self::WaveshareV2 | Self::AnalogOutput => 9600,
+        }
+    }
 }
 
 impl std::fmt::Display for Controller {
@@ -36,19 +112,158 @@ impl std::fmt::Display for Controller {
             Self::CN7500 => write!(f, "CN7500"),
             Self::STR1 => write!(f, "STR1"),
             Self::Waveshare => write!(f, "Waveshare"),
-            Self::WaveshareV2 => write!(f, "WaveshareV2")
+            Self::WaveshareV2 => write!(f, "WaveshareV2"),
+            Self::AnalogOutput => write!(f, "AnalogOutput"),
+        }
+    }
+}
+
+/// An error from working with the [`Controller`](crate::controllers::Controller) type itself, rather than a controller
+#[derive(Error, Debug, PartialEq)]
+pub enum ControllerError {
+    #[error("`{0}` is not a valid controller name")]
+    UnknownController(String),
+}
+
+/// Parses a controller name, like `"STR1"`. Names are case sensitive and match the variant names.
+///
+/// ```rust
+/// # use brewdrivers::controllers::Controller;
+/// assert_eq!("CN7500".parse::<Controller>().unwrap(), Controller::CN7500);
+/// assert!("cn7500".parse::<Controller>().is_err());
+/// ```
+impl FromStr for Controller {
+    type Err = ControllerError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "STR1" => Ok(Self::STR1),
+            "CN7500" => Ok(Self::CN7500),
+            "Waveshare" => Ok(Self::Waveshare),
+            "WaveshareV2" => Ok(Self::WaveshareV2),
+            "AnalogOutput" => Ok(Self::AnalogOutput),
+            _ => Err(ControllerError::UnknownController(value.to_string()))
         }
     }
 }
 
+/// This panics if the name isn't a valid controller. Use [`str::parse`] for anything that comes
+/// from a user, like config values, so you get a [`ControllerError`](crate::controllers::ControllerError) instead.
 impl<T: AsRef<str>> From<T> for Controller {
     fn from(value: T) -> Self {
-        match value.as_ref() {
-            "STR1" => Self::STR1,
-            "CN7500" => Self::CN7500,
-            "Waveshare" => Self::Waveshare,
-            "WaveshareV2" => Self::WaveshareV2,
-            _ => panic!("`{}` is not a valid controller name", value.as_ref())
+        match value.as_ref().parse() {
+            Ok(controller) => controller,
+            Err(e) => panic!("{}", e)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_controller_is_copy() {
+        let controller = Controller::STR1;
+        let copied = controller;
+        // Still usable after being copied
+        assert_eq!(controller, copied);
+        assert_eq!(controller.to_string(), copied.to_string());
+
+        let mut seen = std::collections::HashSet::new();
+        assert!(seen.insert(Controller::CN7500));
+        assert!(!seen.insert(Controller::CN7500));
+    }
+
+    #[test]
+    fn test_controller_from_str() {
+        for controller in [
+            Controller::STR1,
+            Controller::CN7500,
+            Controller::Waveshare,
+            Controller::WaveshareV2,
+            Controller::AnalogOutput,
+        ] {
+            assert_eq!(controller.to_string().parse::<Controller>(), Ok(controller));
+        }
+
+        assert!("Foo".parse::<Controller>().is_err());
+        assert_eq!(
+            "Foo".parse::<Controller>().unwrap_err().to_string(),
+            "`Foo` is not a valid controller name"
+        );
+        assert!("".parse::<Controller>().is_err());
+    }
+
+    #[test]
+    fn test_valid_baudrates() {
+        assert_eq!(
+            Controller::STR1.valid_baudrates(),
+            &[300, 600, 1200, 2400, 4800, 9600, 19200, 38400, 57600, 115200]
+        );
+        assert_eq!(Controller::CN7500.valid_baudrates(), &[2400, 4800, 9600, 19200, 38400]);
+        for controller in [Controller::Waveshare, Controller::WaveshareV2] {
+            assert_eq!(
+                controller.valid_baudrates(),
+                &[4800, 9600, 19200, 38400, 57600, 115200, 128000, 256000]
+            );
+        }
+        assert_eq!(
+            Controller::AnalogOutput.valid_baudrates(),
+            &[2400, 4800, 9600, 19200, 38400, 57600, 115200]
+        );
+    }
+
+    #[test]
+    fn test_default_baudrate_is_valid() {
+        for controller in [
+            Controller::STR1,
+            Controller::CN7500,
+            Controller::Waveshare,
+            Controller::WaveshareV2,
+            Controller::AnalogOutput,
+        ] {
+            assert!(controller.valid_baudrates().contains(&controller.default_baudrate()));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "not a valid controller name")]
+    fn test_controller_from_panics() {
+        let _ = Controller::from("Foo");
+    }
+
+    #[test]
+    fn test_recommended_timeout_documented_values() {
+        assert_eq!(
+            Controller::STR1.recommended_timeout(38400),
+            Duration::from_millis(17)
+        );
+        assert_eq!(
+            Controller::CN7500.recommended_timeout(19200),
+            Duration::from_millis(36)
+        );
+        assert_eq!(
+            Controller::WaveshareV2.recommended_timeout(38400),
+            Duration::from_millis(41)
+        );
+        assert_eq!(
+            Controller::Waveshare.recommended_timeout(38400),
+            Duration::from_millis(41)
+        );
+    }
+
+    #[test]
+    fn test_recommended_timeout_scales_with_baudrate() {
+        // Half the baudrate, twice the timeout
+        assert_eq!(
+            Controller::CN7500.recommended_timeout(9600),
+            Duration::from_millis(72)
+        );
+        // Faster than tested keeps the tested value
+        assert_eq!(
+            Controller::STR1.recommended_timeout(115200),
+            Duration::from_millis(17)
+        );
+    }
+}
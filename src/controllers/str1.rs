@@ -20,7 +20,9 @@ use async_trait::async_trait;
 use log::trace;
 
 // internal uses
-use crate::drivers::{serial::Bytestring, InstrumentError, Result, SerialInstrument};
+use crate::drivers::mock::MockSerialInstrument;
+use crate::drivers::serial::{bytestring::MasterBytes, Bytestring};
+use crate::drivers::{InstrumentError, Result, SerialDriver, SerialInstrument};
 use crate::logging_utils::device_trace;
 use crate::model::{Device, SCADADevice};
 use crate::state::{BinaryState, StateError};
@@ -29,45 +31,80 @@ pub const STR1_BAUDRATES: [usize; 10] = [
     300, 600, 1200, 2400, 4800, 9600, 19200, 38400, 57600, 115200,
 ];
 
+/// The most relays an STR1 board has (the STR116). The STR108 has 8.
+pub const STR1_MAX_RELAYS: u8 = 16;
+
+/// What an STR1 board has on it, see [`STR1::board_info`](crate::controllers::STR1::board_info)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoardInfo {
+    pub relay_count: u8,
+    pub input_count: u8,
+    pub analog_input_count: u8,
+    pub analog_output_count: u8,
+}
+
 /// An `STR1XX` board.
 ///
-/// This struct contains connection details for an STR108 or STR116 relay board.
+/// This struct contains connection details for an STR108 or STR116 relay board. It also
+/// remembers the board's relay count once it's been read, see [`relay_count`](crate::controllers::STR1::relay_count).
+///
+/// It normally runs on a [`SerialInstrument`](crate::drivers::SerialInstrument), but can run on a mock
+/// board for testing, see [`STR1::connect_mock`](crate::controllers::STR1::connect_mock).
+///
+/// Every message is wrapped in the board's master bytes, which are the standard ones unless it's connected
+/// with [`STR1::connect_with_master_bytes`](crate::controllers::STR1::connect_with_master_bytes).
 #[derive(Debug)]
-pub struct STR1(SerialInstrument);
+pub struct STR1<I = SerialInstrument>(I, Option<u8>, MasterBytes);
 
 #[async_trait]
 impl SCADADevice for STR1 {
     async fn update(device: &mut Device) -> Result<()> {
         device_trace!(device, "updating STR1 device...");
-        let mut board = STR1::connect(
-            device.conn.controller_addr(),
-            &device.conn.port(),
-            *device.conn.baudrate(),
-            device.conn.timeout(),
-        )?;
-        device.state.relay_state = Some(board.get_relay(device.conn.addr())?);
+        // A mock board given to the device (see `Device::attach_mock`) stands in for the real one
+        match device.take_connection::<STR1<MockSerialInstrument>>() {
+            Some(mock) => mock.update_device(device)?,
+            None => match device.take_connection::<STR1>() {
+                Some(board) => board.update_device(device)?,
+                None => STR1::try_from(&*device)?.update_device(device)?,
+            },
+        }
         device_trace!(device, "updated");
         Ok(())
     }
 
     async fn enact(device: &mut Device) -> Result<()> {
         device_trace!(device, "enacting STR1 device...");
-        let mut board = STR1::connect(
-            device.conn.controller_addr(),
-            &device.conn.port(),
-            *device.conn.baudrate(),
-            device.conn.timeout(),
-        )?;
+        match device.take_connection::<STR1<MockSerialInstrument>>() {
+            Some(mock) => mock.enact_device(device)?,
+            None => match device.take_connection::<STR1>() {
+                Some(board) => board.enact_device(device)?,
+                None => STR1::try_from(&*device)?.enact_device(device)?,
+            },
+        }
+        device_trace!(device, "enacted");
+        Ok(())
+    }
+}
 
+impl<I: SerialDriver + 'static> STR1<I> {
+    // Reads the device's relay into its state, then hands the board back to the device
+    fn update_device(mut self, device: &mut Device) -> Result<()> {
+        device.state.relay_state = Some(self.get_relay(device.conn.addr())?);
+        device.store_connection(self);
+        Ok(())
+    }
+
+    // Writes the device's relay state to the board, then hands the board back to the device
+    fn enact_device(mut self, device: &mut Device) -> Result<()> {
         match device.state.relay_state {
-            Some(new_state) => board.set_relay(device.conn.addr(), new_state)?,
+            Some(new_state) => self.set_relay(device.conn.addr(), new_state)?,
             None => {
                 return Err(InstrumentError::StateError(StateError::BadValue(
                     device.state.clone(),
                 )))
             }
         }
-        device_trace!(device, "enacted");
+        device.store_connection(self);
         Ok(())
     }
 }
@@ -79,11 +116,31 @@ impl STR1 {
         port_path: &str,
         baudrate: usize,
         timeout: Duration,
+    ) -> Result<Self> {
+        STR1::connect_with_master_bytes(
+            address,
+            port_path,
+            baudrate,
+            timeout,
+            MasterBytes::default(),
+        )
+    }
+
+    /// Attempts to connect to an STR1 board that's been programmed with other master bytes. Boards only
+    /// answer messages with their own master bytes, so this fails if they're wrong.
+    pub fn connect_with_master_bytes(
+        address: u8,
+        port_path: &str,
+        baudrate: usize,
+        timeout: Duration,
+        master: MasterBytes,
     ) -> Result<Self> {
         trace!("[STR1 addr: {}] connected", address);
-        let mut str1 = STR1(SerialInstrument::new(
-            address, port_path, baudrate, timeout,
-        )?);
+        let mut str1 = STR1(
+            SerialInstrument::new(address, port_path, baudrate, timeout)?,
+            None,
+            master,
+        );
         str1.connected().map_err(|instr_err| {
             InstrumentError::serialError(
                 format!(
@@ -96,10 +153,71 @@ impl STR1 {
         Ok(str1)
     }
 
+    // The board responds to a status read (0x14) with
+    // SL0, SL1, BC, one status byte per relay..., CS, SLE
+    fn parse_relay_statuses(resp: &[u8], count: u8) -> Option<Vec<BinaryState>> {
+        let statuses = resp.get(3..3 + count as usize)?;
+        Some(
+            statuses
+                .iter()
+                .map(|&status| BinaryState::from(status & 0x01 == 0x01))
+                .collect(),
+        )
+    }
+
+    // The board responds to an I/O count request (0x02) with
+    // SL0, SL1, 0x09, number of outputs,
+    // number of inputs, number of analog inputs,
+    // number of analog outputs, 0, 0, CS, SLE
+    fn parse_board_info(resp: &[u8]) -> Option<BoardInfo> {
+        match resp.get(3..7)? {
+            &[relay_count, input_count, analog_input_count, analog_output_count] => {
+                Some(BoardInfo {
+                    relay_count,
+                    input_count,
+                    analog_input_count,
+                    analog_output_count,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    // Returns the cached count, or probes and caches it if there isn't one. Failed probes aren't cached.
+    fn cached_relay_count<F>(cache: &mut Option<u8>, probe: F) -> Result<u8>
+    where
+        F: FnOnce() -> Result<u8>,
+    {
+        match *cache {
+            Some(count) => Ok(count),
+            None => {
+                let count = probe()?;
+                *cache = Some(count);
+                Ok(count)
+            }
+        }
+    }
+}
+
+impl<I: SerialDriver> STR1<I> {
+    // Wraps an instrument without talking to the board, for scanning the bus
+    pub(crate) fn from_instrument(instr: I) -> Self {
+        STR1(instr, None, MasterBytes::default())
+    }
+
+    // Points the instrument at another controller number and checks if a board answers there
+    pub(crate) fn probe_at(&mut self, address: u8) -> bool {
+        self.0.set_address(address);
+        self.1 = None;
+        self.probe_relay_count().is_ok()
+    }
+
     /// Attempts to communicate with the board, returning Ok(()) if it responds.
+    ///
+    /// This always talks to the board, and refreshes the cached relay count.
     pub fn connected(&mut self) -> Result<()> {
         trace!("[STR1 addr: {}] connected", self.0.address());
-        self.relay_count()?;
+        self.1 = Some(self.probe_relay_count()?);
         Ok(())
     }
 
@@ -114,25 +232,67 @@ impl STR1 {
             BinaryState::On => 1,
         };
 
-        self.write_to_device(Bytestring::from(vec![
-            0x08,
+        self.write_to_device(Bytestring::with_command(
             0x17,
             self.0.address(),
-            relay_num,
-            0x01,
-            new_state_num,
-        ]))?;
+            vec![relay_num, 0x01, new_state_num],
+        ))?;
+
+        Ok(())
+    }
+
+    /// Sets a block of relays with one command, starting at `start`. `states[0]` goes to relay `start`,
+    /// `states[1]` to `start + 1`, and so on. This is one message on the bus no matter how many relays it sets.
+    pub fn set_relays(&mut self, start: u8, states: &[BinaryState]) -> Result<()> {
+        trace!(
+            "[STR1 addr: {}] setting {} relays from relay {start}",
+            self.0.address(),
+            states.len()
+        );
+        if start as usize + states.len() > STR1_MAX_RELAYS as usize {
+            return Err(InstrumentError::serialError(
+                format!(
+                    "Can't set {} relays starting at relay {start}, an STR1 has at most {STR1_MAX_RELAYS}",
+                    states.len()
+                ),
+                Some(self.0.address()),
+            ));
+        }
+        if states.is_empty() {
+            return Ok(());
+        }
+
+        let mut data = vec![start, states.len() as u8];
+        data.extend(states.iter().map(|&state| match state {
+            BinaryState::Off => 0,
+            BinaryState::On => 1,
+        }));
+        self.write_to_device(Bytestring::with_command(0x17, self.0.address(), data))?;
 
         Ok(())
     }
 
+    /// Sets a relay like [`set_relay`](crate::controllers::STR1::set_relay), but reads it first and returns
+    /// the state it was in before. Compare it to `new_state` to see if the relay actually changed.
+    ///
+    /// This is two commands instead of one, so only use it when you need the old state.
+    pub fn set_relay_returning_prev(
+        &mut self,
+        relay_num: u8,
+        new_state: BinaryState,
+    ) -> Result<BinaryState> {
+        let prev = self.get_relay(relay_num)?;
+        self.set_relay(relay_num, new_state)?;
+        Ok(prev)
+    }
+
     /// Gets the status of a relay, as a [`State`](crate::controllers::BinaryState).
     pub fn get_relay(&mut self, relay_num: u8) -> Result<BinaryState> {
         trace!(
             "[STR1 addr: {}] getting relay {relay_num}",
             self.0.address()
         );
-        let bytes = Bytestring::from(vec![0x07, 0x14, self.0.address(), relay_num, 0x01]);
+        let bytes = Bytestring::with_command(0x14, self.0.address(), vec![relay_num, 0x01]);
         let output_buf: Vec<u8> = self.write_to_device(bytes)?;
 
         let result = hex::encode(output_buf);
@@ -143,19 +303,73 @@ impl STR1 {
         }
     }
 
+    /// Gets the status of every relay on the board with one command, rather than one command per relay.
+    ///
+    /// The returned `Vec` is as long as the board has relays (see [`relay_count`](crate::controllers::STR1::relay_count)).
+    pub fn get_all_relays(&mut self) -> Result<Vec<BinaryState>> {
+        trace!("[STR1 addr: {}] getting all relays", self.0.address());
+        let count = self.relay_count()?;
+        let sent = self.command_bytes(0x14, vec![0x00, count]);
+        let output_buf: Vec<u8> = self.0.write_to_device(sent.clone())?;
+        STR1::parse_relay_statuses(&output_buf, count).ok_or_else(|| {
+            InstrumentError::serialErrorWithFrames(
+                format!("The STR1 board didn't return {count} relay statuses"),
+                Some(self.0.address()),
+                sent,
+                output_buf.clone(),
+            )
+        })
+    }
+
+    /// Gets the state of every digital input on the board with one command. Inputs are opto-isolated, so
+    /// they're useful for things like limit switches.
+    ///
+    /// The returned `Vec` is as long as the board has inputs (see [`input_count`](crate::controllers::STR1::input_count)),
+    /// and is empty if it doesn't have any.
+    pub fn get_inputs(&mut self) -> Result<Vec<BinaryState>> {
+        trace!("[STR1 addr: {}] getting inputs", self.0.address());
+        let count = self.input_count()?;
+        if count == 0 {
+            return Ok(vec![]);
+        }
+        let sent = self.command_bytes(0x15, vec![0x00, count]);
+        let output_buf: Vec<u8> = self.0.write_to_device(sent.clone())?;
+        // Inputs come back in the same shape as relay statuses, one byte each
+        STR1::parse_relay_statuses(&output_buf, count).ok_or_else(|| {
+            InstrumentError::serialErrorWithFrames(
+                format!("The STR1 board didn't return {count} input statuses"),
+                Some(self.0.address()),
+                sent,
+                output_buf.clone(),
+            )
+        })
+    }
+
     /// Writes a command to the device. This is useful if you want to use a command
     /// that we haven't implemented with this struct. See the [software manual](https://www.smarthardware.eu/manual/str1xxxxxx_com.pdf)
     /// for a full list of commands.
     ///
     /// This method uses a [`Bytestring`](crate::drivers::serial::Bytestring) to serialize the bytes you pass in,
     /// meaning you don't have to add the `MA0`, `MA1`, `CS` (checksum), and `MA0` bytes that the board requires.
-    pub fn write_to_device(&mut self, bytestring: Bytestring) -> Result<Vec<u8>> {
+    ///
+    /// The bytestring is sent with this board's master bytes, whatever it was built with.
+    pub fn write_to_device(&mut self, mut bytestring: Bytestring) -> Result<Vec<u8>> {
         trace!("[STR1 addr: {}] writing to device", self.0.address());
+        bytestring.master = self.2;
         self.0.write_to_device(bytestring.to_bytes())
     }
 
+    // The full message for a command to this board, for when the sent bytes are needed after writing them
+    fn command_bytes(&self, cc: u8, data: Vec<u8>) -> Vec<u8> {
+        let mut bytestring = Bytestring::with_command(cc, self.0.address(), data);
+        bytestring.master = self.2;
+        bytestring.to_bytes()
+    }
+
     /// Lists all relays status. This prints to `stdout`, so it should really only
     /// be used in scripts and with the CLI.
+    ///
+    /// The statuses are read with one command, see [`get_all_relays`](crate::controllers::STR1::get_all_relays).
     pub fn list_all_relays(&mut self) -> Result<()> {
         trace!("[STR1 addr: {}] listing all relays", self.0.address());
         // Leave that space there >:(
@@ -167,9 +381,8 @@ impl STR1 {
 
         println!("{0: >6} | {1: <6}", "Relay", "Status");
 
-        for i in 0..self.relay_count()? {
-            // TODO: #14 Replace this with the command that gets all the relays status
-            println!("{0: >6} | {1: <6?}", i, self.get_relay(i));
+        for (i, state) in self.get_all_relays()?.iter().enumerate() {
+            println!("{0: >6} | {1: <6}", i, state);
         }
 
         Ok(())
@@ -182,15 +395,20 @@ impl STR1 {
             "[STR1 addr: {}] setting controller number to {new_cn}",
             self.0.address()
         );
-        let bs = Bytestring::from(vec![0x06, 0x01, self.0.address(), new_cn]);
+        let bs = Bytestring::with_command(0x01, self.0.address(), vec![new_cn]);
 
         self.write_to_device(bs)?;
 
         self.0.set_address(new_cn);
+        // We could be talking to a different board now
+        self.1 = None;
         Ok(())
     }
 
     /// Sets the baudrate of the board. See [`STR1_BAUDRATES`](crate::controllers::str1::STR1_BAUDRATES)
+    ///
+    /// The serial port is reopened at the new baudrate, so the board stays reachable afterwards.
+    /// Be sure to update your configuration file too.
     pub fn set_baudrate(&mut self, new_baudrate: usize) -> Result<()> {
         trace!(
             "Setting STR1 (addr {}) baudrate to {}",
@@ -199,46 +417,84 @@ impl STR1 {
         );
         match STR1_BAUDRATES.iter().position(|&rate| rate == new_baudrate) {
             Some(baud_code) => {
-                let bs = Bytestring::from(vec![
-                    0x08,
+                let bs = Bytestring::with_command(
                     0x33,
                     self.0.address(),
-                    0xAA,
-                    0x55,
-                    baud_code as u8,
-                ]);
+                    vec![0xAA, 0x55, baud_code as u8],
+                );
                 self.write_to_device(bs)?;
-                self.0.set_baudrate(new_baudrate);
+                // The board changes rate right away, so we have to reopen the
+                // port to keep talking to it
+                self.0.reopen_port(new_baudrate)?;
+                self.1 = None;
                 return Ok(());
             }
             None => {
-                return Err(InstrumentError::SerialError {
-                    msg: format!("Bad baudrate for STR1 `{}`", new_baudrate),
-                    addr: Some(self.0.address()),
-                });
+                return Err(InstrumentError::serialError(
+                    format!("Bad baudrate for STR1 `{}`", new_baudrate),
+                    Some(self.0.address()),
+                ));
             }
         }
     }
 
-    /// Gets the amount of relays on this board, if any
+    /// Gets the amount of relays on this board, if any.
+    ///
+    /// The board is only asked the first time, after that the count is cached until the board's
+    /// address or baudrate is changed. [`connected`](crate::controllers::STR1::connected) also refreshes it.
     pub fn relay_count(&mut self) -> Result<u8> {
-        trace!("[STR1 addr: {}] getting relay count", self.0.address());
-        let out = self.write_to_device(Bytestring::from(vec![0x05, 0x02, self.0.address()]))?;
-        // return:
-        // SL0, SL1, 0x09, number of outputs,
-        // number of inputs, number of analog inputs,
-        // number of analog outputs, 0, 0, CS, SLE
-        if out.len() < 4 {
-            return Err(InstrumentError::serialError(
-                format!(
-                    "The STR1 board didn't return the correct response, recieved {:?}",
-                    out
-                ),
+        let mut cache = self.1;
+        let count = STR1::cached_relay_count(&mut cache, || self.probe_relay_count())?;
+        self.1 = cache;
+        Ok(count)
+    }
+
+    /// Gets the amount of digital inputs on this board, if any. Unlike the relay count, this isn't cached.
+    pub fn input_count(&mut self) -> Result<u8> {
+        trace!("[STR1 addr: {}] getting input count", self.0.address());
+        Ok(self.board_info()?.input_count)
+    }
+
+    /// Reads the board's whole I/O profile with one command: how many relays, inputs, analog inputs, and
+    /// analog outputs it has. This also refreshes the cached relay count.
+    pub fn board_info(&mut self) -> Result<BoardInfo> {
+        trace!("[STR1 addr: {}] getting board info", self.0.address());
+        let sent = self.command_bytes(0x02, vec![]);
+        let out = self.0.write_to_device(sent.clone())?;
+        let info = STR1::parse_board_info(&out).ok_or_else(|| {
+            InstrumentError::serialErrorWithFrames(
+                String::from("The STR1 board didn't return the correct response"),
                 Some(self.0.address()),
-            ));
-        } else {
-            return Ok(out[3]);
-        }
+                sent,
+                out.clone(),
+            )
+        })?;
+        self.1 = Some(info.relay_count);
+        Ok(info)
+    }
+
+    // Asks the board how many relays it has
+    fn probe_relay_count(&mut self) -> Result<u8> {
+        Ok(self.board_info()?.relay_count)
+    }
+}
+
+impl STR1<MockSerialInstrument> {
+    /// Connects to an in-memory mock board with the given controller number and number of relays,
+    /// for testing without hardware. See [`MockSerialInstrument`](crate::drivers::mock::MockSerialInstrument).
+    pub fn connect_mock(address: u8, relay_count: u8) -> Result<Self> {
+        let mut str1 = STR1(
+            MockSerialInstrument::new(address, relay_count),
+            None,
+            MasterBytes::default(),
+        );
+        str1.connected()?;
+        Ok(str1)
+    }
+
+    /// The mock board, to check its relays or what was written to it
+    pub fn mock(&self) -> &MockSerialInstrument {
+        &self.0
     }
 }
 
@@ -246,11 +502,12 @@ impl STR1 {
 impl TryFrom<&Device> for STR1 {
     type Error = InstrumentError;
     fn try_from(device: &Device) -> std::result::Result<Self, Self::Error> {
-        Self::connect(
+        Self::connect_with_master_bytes(
             device.conn.controller_addr(),
             &device.conn.port(),
-            device.conn.baudrate().clone(),
+            *device.conn.baudrate(),
             device.conn.timeout(),
+            device.conn.master_bytes(),
         )
     }
 }
@@ -260,15 +517,265 @@ mod tests {
     use super::*;
     use crate::controllers::Controller;
 
-    fn test_board() -> STR1 {
-        let device = crate::tests::test_device_from_type(Controller::STR1);
-        STR1::connect(
+    #[test]
+    fn test_parse_relay_statuses() {
+        // SL0, SL1, BC, 4 statuses, CS, SLE
+        let resp = vec![0x33, 0xCC, 0x09, 0x01, 0x00, 0x00, 0x01, 0x00, 0x77];
+        assert_eq!(
+            STR1::parse_relay_statuses(&resp, 4).unwrap(),
+            vec![
+                BinaryState::On,
+                BinaryState::Off,
+                BinaryState::Off,
+                BinaryState::On
+            ]
+        );
+
+        // Too short for the amount of relays we asked for
+        assert!(STR1::parse_relay_statuses(&resp[..5], 4).is_none());
+        assert!(STR1::parse_relay_statuses(&[], 8).is_none());
+    }
+
+    #[test]
+    fn test_parse_board_info() {
+        // An STR116 with 4 inputs, 2 analog inputs, and 1 analog output
+        let resp = vec![
+            0x33, 0xCC, 0x09, 0x10, 0x04, 0x02, 0x01, 0x00, 0x00, 0x29, 0x77,
+        ];
+        assert_eq!(
+            STR1::parse_board_info(&resp).unwrap(),
+            BoardInfo {
+                relay_count: 16,
+                input_count: 4,
+                analog_input_count: 2,
+                analog_output_count: 1,
+            }
+        );
+        assert!(STR1::parse_board_info(&resp[..6]).is_none());
+    }
+
+    #[test]
+    fn test_mock_board_info() {
+        let mut board = STR1::connect_mock(0xFE, 8).unwrap();
+        board.0.set_inputs(&[false; 6]);
+        let info = board.board_info().unwrap();
+        assert_eq!(info.relay_count, 8);
+        assert_eq!(info.input_count, 6);
+        assert_eq!(info.analog_input_count, 0);
+        assert_eq!(board.relay_count().unwrap(), 8);
+    }
+
+    #[test]
+    fn test_relay_count_is_cached() {
+        // Stands in for the board, recording every relay count command it's sent
+        let mut sent: Vec<Vec<u8>> = vec![];
+        let mut probe = || -> Result<u8> {
+            sent.push(Bytestring::with_command(0x02, 0xFE, vec![]).to_bytes());
+            Ok(16)
+        };
+
+        let mut cache = None;
+        for _ in 0..3 {
+            assert_eq!(
+                STR1::cached_relay_count(&mut cache, &mut probe).unwrap(),
+                16
+            );
+        }
+        assert_eq!(cache, Some(16));
+        assert_eq!(sent.len(), 1);
+    }
+
+    #[test]
+    fn test_relay_count_failed_probe_not_cached() {
+        let mut cache = None;
+        let failed = STR1::cached_relay_count(&mut cache, || {
+            Err(InstrumentError::serialError(
+                String::from("no response"),
+                Some(0xFE),
+            ))
+        });
+        assert!(failed.is_err());
+        assert_eq!(cache, None);
+
+        assert_eq!(STR1::cached_relay_count(&mut cache, || Ok(8)).unwrap(), 8);
+        assert_eq!(cache, Some(8));
+    }
+
+    #[test]
+    fn test_mock_board() {
+        let mut board = STR1::connect_mock(0xFE, 8).unwrap();
+        assert_eq!(board.relay_count().unwrap(), 8);
+
+        board.set_relay(1, BinaryState::On).unwrap();
+        board.set_relay(3, BinaryState::On).unwrap();
+        assert_eq!(board.get_relay(1).unwrap(), BinaryState::On);
+        assert_eq!(board.get_relay(2).unwrap(), BinaryState::Off);
+
+        let mut expected = vec![BinaryState::Off; 8];
+        expected[1] = BinaryState::On;
+        expected[3] = BinaryState::On;
+        assert_eq!(board.get_all_relays().unwrap(), expected);
+
+        // The relay count was only asked for once, when connecting
+        let count_reads = board
+            .mock()
+            .written()
+            .iter()
+            .filter(|bytes| bytes[3] == 0x02)
+            .count();
+        assert_eq!(count_reads, 1);
+    }
+
+    #[test]
+    fn test_set_relays() {
+        use BinaryState::*;
+        let mut board = STR1::connect_mock(0xFE, 8).unwrap();
+        let writes = board.mock().written().len();
+
+        board.set_relays(0, &[On, Off, On, On]).unwrap();
+        assert_eq!(board.mock().written().len(), writes + 1);
+        assert_eq!(
+            board.mock().written().last().unwrap(),
+            &Bytestring::with_command(0x17, 0xFE, vec![0, 4, 1, 0, 1, 1]).to_bytes()
+        );
+
+        for (relay, expected) in [On, Off, On, On, Off].into_iter().enumerate() {
+            assert_eq!(board.get_relay(relay as u8).unwrap(), expected);
+        }
+
+        assert!(board.set_relays(14, &[On, On, On]).is_err());
+    }
+
+    #[test]
+    fn test_set_relay_returning_prev() {
+        let mut board = STR1::connect_mock(0xFE, 8).unwrap();
+
+        let prev = board.set_relay_returning_prev(2, BinaryState::On).unwrap();
+        assert_eq!(prev, BinaryState::Off);
+        assert_eq!(board.get_relay(2).unwrap(), BinaryState::On);
+
+        // Setting it to the same state reports it didn't change
+        let prev = board.set_relay_returning_prev(2, BinaryState::On).unwrap();
+        assert_eq!(prev, BinaryState::On);
+
+        let prev = board.set_relay_returning_prev(2, BinaryState::Off).unwrap();
+        assert_eq!(prev, BinaryState::On);
+        assert!(!board.mock().relays()[2]);
+    }
+
+    #[test]
+    fn test_get_inputs() {
+        use BinaryState::*;
+        let mut board = STR1::connect_mock(0xFE, 8).unwrap();
+        assert_eq!(board.input_count().unwrap(), 0);
+        assert_eq!(board.get_inputs().unwrap(), vec![]);
+
+        board.0.set_inputs(&[true, false, false, true]);
+        assert_eq!(board.input_count().unwrap(), 4);
+        assert_eq!(board.get_inputs().unwrap(), vec![On, Off, Off, On]);
+        assert_eq!(
+            board.mock().written().last().unwrap(),
+            &Bytestring::with_command(0x15, 0xFE, vec![0, 4]).to_bytes()
+        );
+        // Reading inputs doesn't touch the relays
+        assert_eq!(board.relay_count().unwrap(), 8);
+    }
+
+    #[test]
+    fn test_custom_master_bytes() {
+        let master = MasterBytes {
+            ma0: 0x5A,
+            ma1: 0xA5,
+            mae: 0x7E,
+        };
+        let mut board = STR1(MockSerialInstrument::new(0xFE, 8), None, master);
+        board.connected().unwrap();
+        board.set_relay(2, BinaryState::On).unwrap();
+        board.get_all_relays().unwrap();
+
+        let written = board.mock().written();
+        assert_eq!(written.len(), 3);
+        for message in written {
+            assert_eq!(&message[..2], &[0x5A, 0xA5]);
+            assert_eq!(message.last(), Some(&0x7E));
+        }
+        // Bytestrings built with the default master bytes are sent with the board's
+        assert_eq!(
+            written[1],
+            Bytestring::with_markers(vec![0x08, 0x17, 0xFE, 0x02, 0x01, 0x01], master).to_bytes()
+        );
+    }
+
+    #[test]
+    fn test_mock_board_controller_num() {
+        let mut board = STR1::connect_mock(0xFE, 16).unwrap();
+        board.set_controller_num(0x02).unwrap();
+        assert_eq!(board.mock().board_addr(), 0x02);
+        // Still talking to it at the new address
+        assert!(board.connected().is_ok());
+
+        board.set_baudrate(19200).unwrap();
+        assert_eq!(board.mock().baudrate(), 19200);
+        assert!(board.set_baudrate(12345).is_err());
+    }
+
+    #[test]
+    fn test_mock_board_reachable() {
+        let mut board = STR1::connect_mock(0xFE, 8).unwrap();
+        assert!(board.connected().is_ok());
+
+        // Nothing answers at controller number 1, so the relay count probe gets an empty response
+        let mut nobody = STR1(
+            MockSerialInstrument::new(0xFE, 8),
+            None,
+            MasterBytes::default(),
+        );
+        nobody.0.set_address(0x01);
+        assert!(nobody.connected().is_err());
+        assert!(nobody.relay_count().is_err());
+    }
+
+    #[test]
+    fn test_parse_relay_statuses_board_sizes() {
+        // STR108, relay 7 on
+        let mut resp = vec![0x33, 0xCC, 0x0D];
+        resp.extend([0, 0, 0, 0, 0, 0, 0, 1]);
+        resp.extend([0x00, 0x77]);
+        let statuses = STR1::parse_relay_statuses(&resp, 8).unwrap();
+        assert_eq!(statuses.len(), 8);
+        assert_eq!(statuses[7], BinaryState::On);
+        assert!(statuses[..7].iter().all(|&s| s == BinaryState::Off));
+
+        // STR116, relays 8 and 15 on
+        let mut resp = vec![0x33, 0xCC, 0x15];
+        resp.extend([0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 1]);
+        resp.extend([0x00, 0x77]);
+        let statuses = STR1::parse_relay_statuses(&resp, 16).unwrap();
+        assert_eq!(statuses.len(), 16);
+        for (i, state) in statuses.iter().enumerate() {
+            let expected = if i == 8 || i == 15 {
+                BinaryState::On
+            } else {
+                BinaryState::Off
+            };
+            assert_eq!(*state, expected, "relay {}", i);
+        }
+
+        // Asking for 16 from an 8 relay response isn't going to work
+        let resp = &resp[..3 + 8];
+        assert!(STR1::parse_relay_statuses(resp, 16).is_none());
+    }
+
+    fn test_board() -> Option<STR1> {
+        let device = crate::tests::try_test_device_from_type(Controller::STR1)?;
+        let board = STR1::connect(
             device.conn.controller_addr(),
             &device.conn.port(),
             *device.conn.baudrate(),
             device.conn.timeout(),
         )
-        .unwrap()
+        .unwrap();
+        Some(board)
     }
 
     #[test]
@@ -282,13 +789,17 @@ mod tests {
 
     #[test]
     fn test_board_connected() {
-        let mut board = test_board();
+        let Some(mut board) = test_board() else {
+            return;
+        };
         assert!(board.connected().is_ok());
     }
 
     #[test]
     fn set_get_relay_status() {
-        let mut board = test_board();
+        let Some(mut board) = test_board() else {
+            return;
+        };
 
         board.set_relay(0, BinaryState::On).unwrap();
         assert_eq!(BinaryState::On, board.get_relay(0).unwrap());
@@ -299,7 +810,9 @@ mod tests {
 
     #[test]
     fn set_controller_number() {
-        let mut board = test_board();
+        let Some(mut board) = test_board() else {
+            return;
+        };
 
         assert!(board.connected().is_ok());
 
@@ -315,7 +828,9 @@ mod tests {
 
     #[test]
     fn test_all_relays() {
-        let mut board = test_board();
+        let Some(mut board) = test_board() else {
+            return;
+        };
         for i in 0..16 {
             board.set_relay(i, BinaryState::On).unwrap();
         }
@@ -325,9 +840,47 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_get_all_relays() {
+        let Some(mut board) = test_board() else {
+            return;
+        };
+        let count = board.relay_count().unwrap();
+        for i in 0..count {
+            board.set_relay(i, BinaryState::Off).unwrap();
+        }
+        board.set_relay(1, BinaryState::On).unwrap();
+        board.set_relay(3, BinaryState::On).unwrap();
+
+        let mut expected = vec![BinaryState::Off; count as usize];
+        expected[1] = BinaryState::On;
+        expected[3] = BinaryState::On;
+        assert_eq!(board.get_all_relays().unwrap(), expected);
+
+        board.set_relay(1, BinaryState::Off).unwrap();
+        board.set_relay(3, BinaryState::Off).unwrap();
+    }
+
+    #[test]
+    fn test_set_baudrate_stays_connected() {
+        let Some(mut board) = test_board() else {
+            return;
+        };
+        let original_baudrate = board.0.baudrate();
+
+        board.set_baudrate(9600).unwrap();
+        assert!(board.connected().is_ok());
+
+        // Set it back
+        board.set_baudrate(original_baudrate).unwrap();
+        assert!(board.connected().is_ok());
+    }
+
     #[test]
     fn test_relay_count() {
-        let mut board = test_board();
+        let Some(mut board) = test_board() else {
+            return;
+        };
         // I test on an STR108, so there should be 8. We may test on an STR116 with 16 relays
         // later though.
         let count = board.relay_count();
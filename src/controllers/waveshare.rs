@@ -5,13 +5,12 @@
 use std::time::Duration;
 
 use async_trait::async_trait;
-// ext uses
-// Used for checksums
-use crc::{Crc, CRC_16_MODBUS};
 use log::trace;
 
 // internal uses
-use crate::drivers::{serial::SerialInstrument, InstrumentError, Result};
+use crate::controllers::waveshare_common;
+use crate::drivers::mock::MockWaveshareInstrument;
+use crate::drivers::{serial::SerialInstrument, InstrumentError, Result, SerialDriver};
 use crate::logging_utils::device_trace;
 use crate::model::Device;
 use crate::state::{BinaryState, StateError};
@@ -23,52 +22,69 @@ use crate::model::SCADADevice;
 pub const WAVESHARE_BAUDRATES: [usize; 8] =
     [4800, 9600, 19200, 38400, 57600, 115200, 128000, 256000];
 
-// This is the checksum algorithm that the board uses
-const CRC_MODBUS: Crc<u16> = Crc::<u16>::new(&CRC_16_MODBUS);
+/// The relay counts that the Waveshare Modbus RTU relay boards come in. Both versions of the board
+/// use the same protocol for all of them, there are just more status bytes.
+pub const WAVESHARE_RELAY_COUNTS: [u8; 3] = [8, 16, 32];
+
+/// The relay count a Waveshare board is assumed to have if its connection doesn't set `relay_count`
+pub const WAVESHARE_DEFAULT_RELAY_COUNT: u8 = 8;
 
 /// A Waveshare board.
+///
+/// This defaults to 8 relays, use [`Waveshare::with_relay_count`](crate::controllers::Waveshare::with_relay_count)
+/// for the 16 and 32 relay boards.
 #[derive(Debug)]
-pub struct Waveshare(SerialInstrument);
+pub struct Waveshare<I = SerialInstrument>(I, u8, u8);
 
 #[async_trait]
 impl SCADADevice for Waveshare {
     async fn update(device: &mut Device) -> Result<()> {
         device_trace!(device, "updating Waveshare device...");
-
-        let mut board = Self::connect(
-            device.conn.controller_addr,
-            &device.conn.port(),
-            // TODO: read these from the device once it's implemented
-            device.conn.baudrate().clone(),
-            device.conn.timeout(),
-        )?;
-        device.state.relay_state = Some(board.get_relay(device.conn.addr)?);
-
+        // A mock board given to the device (see `Device::attach_mock`) stands in for the real one
+        match device.take_connection::<Waveshare<MockWaveshareInstrument>>() {
+            Some(mock) => mock.update_device(device)?,
+            None => match device.take_connection::<Waveshare>() {
+                Some(board) => board.update_device(device)?,
+                None => Waveshare::try_from(&*device)?.update_device(device)?,
+            },
+        }
         device_trace!(device, "updated");
         Ok(())
     }
 
     async fn enact(device: &mut Device) -> Result<()> {
         device_trace!(device, "enacting Waveshare device...");
+        match device.take_connection::<Waveshare<MockWaveshareInstrument>>() {
+            Some(mock) => mock.enact_device(device)?,
+            None => match device.take_connection::<Waveshare>() {
+                Some(board) => board.enact_device(device)?,
+                None => Waveshare::try_from(&*device)?.enact_device(device)?,
+            },
+        }
+        device_trace!(device, "enacted");
+        Ok(())
+    }
+}
 
-        let mut board = Self::connect(
-            device.conn.controller_addr,
-            &device.conn.port(),
-            // TODO: read these from the device once it's implemented
-            device.conn.baudrate().clone(),
-            device.conn.timeout(),
-        )?;
+impl<I: SerialDriver + 'static> Waveshare<I> {
+    // Reads the device's relay into its state, then hands the board back to the device
+    fn update_device(mut self, device: &mut Device) -> Result<()> {
+        device.state.relay_state = Some(self.get_relay(device.conn.addr())?);
+        device.store_connection(self);
+        Ok(())
+    }
 
+    // Writes the device's relay state to the board, then hands the board back to the device
+    fn enact_device(mut self, device: &mut Device) -> Result<()> {
         match device.state.relay_state {
-            Some(new_state) => board.set_relay(device.conn.addr(), new_state)?,
+            Some(new_state) => self.set_relay(device.conn.addr(), new_state)?,
             None => {
                 return Err(InstrumentError::StateError(StateError::BadValue(
                     device.state.clone(),
                 )))
             }
         }
-
-        device_trace!(device, "enacted");
+        device.store_connection(self);
         Ok(())
     }
 }
@@ -82,17 +98,32 @@ impl Waveshare {
         port_path: &str,
         baudrate: usize,
         timeout: Duration,
+    ) -> Result<Self> {
+        Waveshare::connect_with_retries(address, port_path, baudrate, timeout, 0)
+    }
+
+    /// The same as [`connect`](Self::connect), but if the board doesn't answer the software revision probe,
+    /// it's read again, then sent again, up to `probe_retries` times each. The retries are kept for later discovery calls,
+    /// see [`with_probe_retries`](Self::with_probe_retries).
+    pub fn connect_with_retries(
+        address: u8,
+        port_path: &str,
+        baudrate: usize,
+        timeout: Duration,
+        probe_retries: u8,
     ) -> Result<Self> {
         if !WAVESHARE_BAUDRATES.contains(&baudrate) {
-            return Err(InstrumentError::SerialError {
-                msg: format!("Invalid baudrate `{baudrate}`"),
-                addr: Some(address),
-            });
+            return Err(InstrumentError::serialError(
+                format!("Invalid baudrate `{baudrate}`"),
+                Some(address),
+            ));
         }
 
-        let mut ws = Self(SerialInstrument::new(
-            address, port_path, baudrate, timeout,
-        )?);
+        let mut ws = Self(
+            SerialInstrument::new(address, port_path, baudrate, timeout)?,
+            WAVESHARE_DEFAULT_RELAY_COUNT,
+            probe_retries,
+        );
 
         ws.connected().map_err(|instr_err| {
             InstrumentError::serialError(
@@ -107,6 +138,114 @@ impl Waveshare {
         Ok(ws)
     }
 
+    // Example: 01 01 00 FF 00 01 CD FA
+    // 01       Device address
+    // 01       Command for reading relays
+    // 00 FF    0x00FF reads all 8 relays
+    // 00 01    Fixed
+    // CD FA    CRC16
+    //
+    // The 8 relay board has always been read this way. Bigger boards are read with the
+    // standard Modbus range, from relay 0, like `01 01 00 00 00 10` for 16 relays.
+    fn read_relays_frame(address: u8, relay_count: u8) -> Result<Vec<u8>> {
+        let mut bytes: Vec<u8> = match relay_count {
+            8 => vec![address, 0x01, 0x00, 0xFF, 0x00, 0x01],
+            count => vec![address, 0x01, 0x00, 0x00, 0x00, count],
+        };
+        waveshare_common::append_checksum(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    // Example: 01 05 00 FF FF 00 BC 0A
+    // 01       Device address
+    // 05       Command for controlling Relay
+    // 00 FF    Relay address 0x00FF means all relays
+    // FF 00    Command             0xFF00: Open all relays
+    //                              0x0000: Close all relays
+    // BC 0A    CRC16
+    //
+    // This is the same as v2. The value is still 0xFF00, not 0xFFFF, this is
+    // Modbus "write single coil" and 0xFF00 is the only value it takes for on.
+    fn all_relays_frame(address: u8, state: BinaryState) -> Result<Vec<u8>> {
+        let mut bytes: Vec<u8> = vec![address, 0x05, 0x00, 0xFF];
+
+        match state {
+            BinaryState::On => bytes.push(0xFF),
+            BinaryState::Off => bytes.push(0x00),
+        }
+        bytes.push(0x00);
+
+        waveshare_common::append_checksum(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    // Example: 01 0F 00 00 00 08 01 FF BE D5
+    // 01       Device address
+    // 0F       Command for writing multiple relays
+    // 00 00    Address of the first relay
+    // 00 08    Number of relays
+    // 01       Number of bytes of relay states, one for every 8 relays
+    // FF       Relay states, bit 0 of the first byte is relay 0
+    // BE D5    CRC16
+    fn relay_pattern_frame(address: u8, relay_count: u8, mask: u32) -> Result<Vec<u8>> {
+        // Every relay the board has has to fit in the mask, and the mask can't set relays it doesn't have
+        let extra_relays = mask.checked_shr(relay_count as u32).unwrap_or(0);
+        if relay_count > 32 || extra_relays != 0 {
+            return Err(InstrumentError::serialError(
+                format!(
+                    "relay pattern {:#b} doesn't fit this board's {} relays",
+                    mask, relay_count
+                ),
+                Some(address),
+            ));
+        }
+
+        let byte_count = relay_count.div_ceil(8);
+        let mut bytes: Vec<u8> = vec![address, 0x0F, 0x00, 0x00, 0x00, relay_count, byte_count];
+        bytes.extend_from_slice(&mask.to_le_bytes()[..byte_count as usize]);
+        waveshare_common::append_checksum(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+impl<I: SerialDriver> Waveshare<I> {
+    // Wraps an instrument without talking to the board, for scanning the bus
+    pub(crate) fn from_instrument(instr: I) -> Self {
+        Waveshare(instr, WAVESHARE_DEFAULT_RELAY_COUNT, 0)
+    }
+
+    // Points the instrument at another address and checks if a board answers there
+    pub(crate) fn probe_at(&mut self, address: u8) -> bool {
+        self.0.set_address(address);
+        self.software_revision().is_ok()
+    }
+
+    /// Sets how many relays the board has, one of [`WAVESHARE_RELAY_COUNTS`](crate::controllers::waveshare::WAVESHARE_RELAY_COUNTS).
+    /// Boards are assumed to have 8 unless this is called.
+    pub fn with_relay_count(mut self, relay_count: u8) -> Self {
+        self.1 = relay_count;
+        self
+    }
+
+    /// How many relays the board has
+    pub fn relay_count(&self) -> u8 {
+        self.1
+    }
+
+    /// Sets how many times [`get_address`](Self::get_address) and [`software_revision`](Self::software_revision)
+    /// read again, then send the command again, if the board doesn't answer in full. See
+    /// [`SerialDriver::write_with_retries`](crate::drivers::SerialDriver::write_with_retries).
+    pub fn with_probe_retries(mut self, probe_retries: u8) -> Self {
+        self.2 = probe_retries;
+        self
+    }
+
+    /// How many times discovery commands are retried if the board doesn't answer
+    pub fn probe_retries(&self) -> u8 {
+        self.2
+    }
+
+    /// Checks that the board responds by reading its software revision
     pub fn connected(&mut self) -> Result<()> {
         self.software_revision()?;
         Ok(())
@@ -128,12 +267,13 @@ impl Waveshare {
             relay_num,
             state
         );
+        waveshare_common::check_relay_num(relay_num, self.1, self.0.address())?;
         let mut bytes: Vec<u8> = vec![
             // Address
             self.0.address(),
             // Command to control a relay
             0x05,
-            // Relay number (ours only has 8)
+            // Relay number
             0x00,
             relay_num,
         ];
@@ -147,12 +287,38 @@ impl Waveshare {
         // Add on 0x00, because the board needs it I guess
         bytes.push(0x00);
 
-        Waveshare::append_checksum(&mut bytes).unwrap();
+        waveshare_common::append_checksum(&mut bytes).unwrap();
+
+        self.0.write_to_device(bytes)?;
+        Ok(())
+    }
 
+    /// Flips a relay, turning it on if it's off and off if it's on. The board does this itself,
+    /// so it's one command instead of reading the relay then setting it.
+    pub fn flip_relay(&mut self, relay_num: u8) -> Result<()> {
+        trace!(
+            "[Waveshare addr: {}] flipping relay {}",
+            self.0.address(),
+            relay_num
+        );
+        waveshare_common::check_relay_num(relay_num, self.1, self.0.address())?;
+        let bytes = waveshare_common::flip_relay_frame(self.0.address(), relay_num)?;
         self.0.write_to_device(bytes)?;
         Ok(())
     }
 
+    /// Sets a relay like [`set_relay`](crate::controllers::Waveshare::set_relay), but reads it first and returns
+    /// the state it was in before. This is two commands instead of one.
+    pub fn set_relay_returning_prev(
+        &mut self,
+        relay_num: u8,
+        new_state: BinaryState,
+    ) -> Result<BinaryState> {
+        let prev = self.get_relay(relay_num)?;
+        self.set_relay(relay_num, new_state)?;
+        Ok(prev)
+    }
+
     /// Gets a relay state. See [`BinaryState`](crate::controllers::BinaryState).
     pub fn get_relay(&mut self, relay_num: u8) -> Result<BinaryState> {
         trace!(
@@ -178,48 +344,29 @@ impl Waveshare {
         }
     }
 
-    // Calculates the CRC checksum for the data bytes to send to the board
-    fn append_checksum(bytes: &mut Vec<u8>) -> Result<()> {
-        let checksum = CRC_MODBUS.checksum(&bytes).to_le_bytes();
-        bytes.push(checksum[0]);
-        bytes.push(checksum[1]);
-        Ok(())
-    }
-
-    /// Returns a `Vec<BinaryState>` of all 8 relays.
+    /// Returns a `Vec<BinaryState>` of all the relays on the board, one for each of its
+    /// [`relay_count`](Self::relay_count) relays.
     pub fn get_all_relays(&mut self) -> Result<Vec<BinaryState>> {
         trace!("[Waveshare addr: {}] getting all relays", self.0.address());
-        let mut bytes: Vec<u8> = vec![self.0.address(), 0x01, 0x00, 0xFF, 0x00, 0x01];
-        Waveshare::append_checksum(&mut bytes)?;
-
-        let resp = self.0.write_to_device(bytes)?;
-        if let Some(status_number) = resp.get(3) {
-            // this is a little cursed but i don't know how else to work with binary
-            let binary = format!("{:08b}", status_number);
-            let statuses: Vec<BinaryState> = binary
-                .chars()
-                .filter(|&ch| ch == '1' || ch == '0')
-                .map(|ch| {
-                    // Usually 0 and 1 are stepper states, not binary
-                    // That's why theres no FromStr for BinaryState
-                    match ch {
-                        '1' => BinaryState::On,
-                        '0' => BinaryState::Off,
-                        _ => BinaryState::default(),
-                    }
-                })
-                .rev()
-                .collect();
-
-            Ok(statuses)
-        } else {
-            Err(InstrumentError::serialError(
+        let bytes = Waveshare::read_relays_frame(self.0.address(), self.1)?;
+
+        let resp = self.0.write_to_device(bytes.clone())?;
+        waveshare_common::verify_response(&resp, self.0.address())?;
+        match waveshare_common::parse_relay_statuses(&resp) {
+            Some(mut statuses) if statuses.len() >= self.1 as usize => {
+                // The last status byte is padded out to 8 relays
+                statuses.truncate(self.1 as usize);
+                Ok(statuses)
+            }
+            _ => Err(InstrumentError::serialErrorWithFrames(
                 format!(
-                    "Board did not return the proper response, received {:?}",
-                    resp
+                    "Board did not return the proper response for {} relays",
+                    self.1
                 ),
                 Some(self.0.address()),
-            ))
+                bytes,
+                resp,
+            )),
         }
     }
 
@@ -227,9 +374,9 @@ impl Waveshare {
     pub fn software_revision(&mut self) -> Result<String> {
         let mut bytes: Vec<u8> = vec![self.0.address(), 0x03, 0x80, 0x00, 0x00, 0x01];
 
-        Waveshare::append_checksum(&mut bytes)?;
+        waveshare_common::append_checksum(&mut bytes)?;
 
-        let resp = self.0.write_to_device(bytes)?;
+        let resp = self.0.write_with_retries(bytes, 5, self.2)?;
 
         if let Some(&version_num) = resp.get(4) {
             Ok(format!("v{:.2}", (version_num as f64 / 100.0)))
@@ -238,7 +385,7 @@ impl Waveshare {
                 InstrumentError::serialError(
                     format!(
                         "The board didn't return it's software revision correctly. Possible connection issue. port: {:?}, response: {:?}",
-                        self.0.port(),
+                        self.0.port_path(),
                         resp
                     ),
                     Some(self.0.address())
@@ -257,18 +404,17 @@ impl Waveshare {
         trace!("[Waveshare addr: {}] getting address", self.0.address());
         let mut bytes: Vec<u8> = vec![0x00, 0x03, 0x40, 0x00, 0x0, 0x01];
 
-        Waveshare::append_checksum(&mut bytes)?;
+        waveshare_common::append_checksum(&mut bytes)?;
 
-        let resp = self.0.write_to_device(bytes)?;
-        resp.get(3)
-            .ok_or(InstrumentError::serialError(
-                format!(
-                    "The board didn't return the proper response, recieved: {:?}",
-                    resp
-                ),
+        let resp = self.0.write_with_retries(bytes.clone(), 4, self.2)?;
+        resp.get(3).copied().ok_or_else(|| {
+            InstrumentError::serialErrorWithFrames(
+                String::from("The board didn't return the proper response"),
                 Some(self.0.address()),
-            ))
-            .copied()
+                bytes,
+                resp.clone(),
+            )
+        })
     }
 
     /// Sets the address of a board. You don't need to reconnect to the board
@@ -283,7 +429,7 @@ impl Waveshare {
         );
         let mut bytes: Vec<u8> = vec![self.0.address(), 0x06, 0x40, 0x00, 0x00, new_addr];
 
-        Waveshare::append_checksum(&mut bytes)?;
+        waveshare_common::append_checksum(&mut bytes)?;
 
         let _resp = self.0.write_to_device(bytes)?;
         self.0.set_address(new_addr);
@@ -297,42 +443,218 @@ impl Waveshare {
             self.0.address(),
             state
         );
-        let mut bytes: Vec<u8> = vec![
-            self.0.address(),
-            // These are all constant, reading all relays status
-            0x05,
-            0x00,
-            0xFF,
-        ];
-
-        match state {
-            BinaryState::On => {
-                bytes.push(0xFF);
-                bytes.push(0xFF);
-            }
-            BinaryState::Off => {
-                bytes.push(0x00);
-                bytes.push(0x00);
-            }
-        }
-
-        Waveshare::append_checksum(&mut bytes)?;
+        let bytes = Waveshare::all_relays_frame(self.0.address(), state)?;
+        self.0.write_to_device(bytes)?;
+        Ok(())
+    }
 
+    /// Sets every relay on the board at once from a bitmask. Bit 0 is relay 0, so `0b0000_0101`
+    /// turns on relays 0 and 2 and turns off the rest. Fails if a bit is set for a relay past
+    /// [`relay_count`](Self::relay_count).
+    pub fn set_relay_pattern(&mut self, mask: u32) -> Result<()> {
+        trace!(
+            "[Waveshare addr: {}] setting relay pattern to {:b}",
+            self.0.address(),
+            mask
+        );
+        let bytes = Waveshare::relay_pattern_frame(self.0.address(), self.1, mask)?;
         self.0.write_to_device(bytes)?;
         Ok(())
     }
 }
 
+impl Waveshare<MockWaveshareInstrument> {
+    /// Connects to an in-memory mock board with the given address and number of relays, for testing
+    /// without hardware. See [`MockWaveshareInstrument`](crate::drivers::mock::MockWaveshareInstrument).
+    pub fn connect_mock(address: u8, relay_count: u8) -> Self {
+        Self(
+            MockWaveshareInstrument::new(address, relay_count),
+            relay_count,
+            0,
+        )
+    }
+
+    /// The mock board, to check its relays or what was written to it
+    pub fn mock(&self) -> &MockWaveshareInstrument {
+        &self.0
+    }
+}
+
 /// Creates a controller connection from a Device
 impl TryFrom<&Device> for Waveshare {
     type Error = InstrumentError;
     fn try_from(device: &Device) -> std::result::Result<Self, Self::Error> {
-        Self::connect(
+        Self::connect_with_retries(
             device.conn.controller_addr(),
             &device.conn.port(),
-            device.conn.baudrate().clone(),
+            *device.conn.baudrate(),
             device.conn.timeout(),
+            device.conn.probe_retries(),
         )
+        .map(|ws| ws.with_relay_count(device.conn.relay_count()))
+    }
+}
+
+// These don't need a board, they check the bytes we'd send or run on a mock board
+#[cfg(test)]
+mod frame_tests {
+    use super::*;
+
+    #[test]
+    fn test_all_relays_frame() {
+        assert_eq!(
+            Waveshare::all_relays_frame(0x01, BinaryState::On).unwrap(),
+            vec![0x01, 0x05, 0x00, 0xFF, 0xFF, 0x00, 0xBC, 0x0A]
+        );
+        assert_eq!(
+            Waveshare::all_relays_frame(0x01, BinaryState::Off).unwrap(),
+            vec![0x01, 0x05, 0x00, 0xFF, 0x00, 0x00, 0xFD, 0xFA]
+        );
+    }
+
+    #[test]
+    fn test_relay_pattern_frame() {
+        assert_eq!(
+            Waveshare::relay_pattern_frame(0x01, 8, 0xFF).unwrap(),
+            vec![0x01, 0x0F, 0x00, 0x00, 0x00, 0x08, 0x01, 0xFF, 0xBE, 0xD5]
+        );
+        assert_eq!(
+            Waveshare::relay_pattern_frame(0x01, 8, 0x00).unwrap(),
+            vec![0x01, 0x0F, 0x00, 0x00, 0x00, 0x08, 0x01, 0x00, 0xFE, 0x95]
+        );
+
+        // Larger boards get a byte for every 8 relays
+        let frame = Waveshare::relay_pattern_frame(0x01, 16, 0x0201).unwrap();
+        assert_eq!(
+            &frame[..9],
+            &[0x01, 0x0F, 0x00, 0x00, 0x00, 0x10, 0x02, 0x01, 0x02]
+        );
+        assert_eq!(frame.len(), 11);
+        let frame = Waveshare::relay_pattern_frame(0x01, 32, 0x8000_0000).unwrap();
+        assert_eq!(&frame[5..11], &[0x20, 0x04, 0x00, 0x00, 0x00, 0x80]);
+
+        // Relays the board doesn't have
+        assert!(Waveshare::relay_pattern_frame(0x01, 8, 0x100).is_err());
+        assert!(Waveshare::relay_pattern_frame(0x01, 16, 0x1_0000).is_err());
+        assert!(Waveshare::relay_pattern_frame(0x01, 40, 0x01).is_err());
+    }
+
+    #[test]
+    fn test_relay_pattern_mock() {
+        let mut ws = Waveshare::connect_mock(0x01, 16);
+        ws.set_relay_pattern(0b0000_0010_0000_0101).unwrap();
+        let on: Vec<usize> = (0..16).filter(|&i| ws.mock().relays()[i]).collect();
+        assert_eq!(on, vec![0, 2, 9]);
+
+        assert!(ws.set_relay_pattern(1 << 16).is_err());
+        assert_eq!(ws.mock().written().len(), 1);
+    }
+
+    #[test]
+    fn test_read_relays_frame() {
+        // The 8 relay board keeps the v1 read all command
+        assert_eq!(
+            Waveshare::read_relays_frame(0x01, 8).unwrap(),
+            vec![0x01, 0x01, 0x00, 0xFF, 0x00, 0x01, 0xCD, 0xFA]
+        );
+        assert_eq!(
+            Waveshare::read_relays_frame(0x01, 16).unwrap(),
+            vec![0x01, 0x01, 0x00, 0x00, 0x00, 0x10, 0x3D, 0xC6]
+        );
+        assert_eq!(
+            Waveshare::read_relays_frame(0x01, 32).unwrap(),
+            vec![0x01, 0x01, 0x00, 0x00, 0x00, 0x20, 0x3D, 0xD2]
+        );
+    }
+
+    #[test]
+    fn test_mock_16_relays() {
+        use BinaryState::*;
+        let mut ws = Waveshare::connect_mock(0x01, 16);
+        assert_eq!(ws.relay_count(), 16);
+
+        ws.set_relay(1, On).unwrap();
+        ws.set_relay(15, On).unwrap();
+        let statuses = ws.get_all_relays().unwrap();
+        assert_eq!(statuses.len(), 16);
+        for (i, state) in statuses.iter().enumerate() {
+            let expected = if i == 1 || i == 15 { On } else { Off };
+            assert_eq!(*state, expected, "relay {}", i);
+        }
+        assert_eq!(ws.get_relay(15).unwrap(), On);
+
+        ws.flip_relay(15).unwrap();
+        assert_eq!(ws.get_relay(15).unwrap(), Off);
+        assert!(ws.set_relay(16, On).is_err());
+    }
+
+    #[test]
+    fn test_mock_32_relays() {
+        use BinaryState::*;
+        let mut ws = Waveshare::connect_mock(0x01, 32);
+
+        ws.set_all_relays(On).unwrap();
+        ws.set_relay(30, Off).unwrap();
+        let mut expected = vec![On; 32];
+        expected[30] = Off;
+        assert_eq!(ws.get_all_relays().unwrap(), expected);
+        assert!(!ws.mock().relays()[30]);
+
+        // Out of range
+        assert!(ws.get_relay(32).is_err());
+        assert!(ws.flip_relay(32).is_err());
+        // Nothing was written to relay 32
+        assert!(!ws
+            .mock()
+            .written()
+            .iter()
+            .any(|msg| msg[1] == 0x05 && msg[3] == 32));
+    }
+
+    #[test]
+    fn test_set_relay_returning_prev() {
+        use BinaryState::*;
+        let mut ws = Waveshare::connect_mock(0x01, 8);
+        assert_eq!(ws.set_relay_returning_prev(5, On).unwrap(), Off);
+        assert_eq!(ws.set_relay_returning_prev(5, Off).unwrap(), On);
+        assert_eq!(ws.get_relay(5).unwrap(), Off);
+        assert!(ws.set_relay_returning_prev(8, On).is_err());
+    }
+
+    #[test]
+    fn test_relay_count_too_high() {
+        // Configured for 16 relays, but the board only has 8
+        let mut ws = Waveshare::connect_mock(0x01, 8).with_relay_count(16);
+        assert!(ws.get_all_relays().is_err());
+    }
+
+    #[test]
+    fn test_probe_retries() {
+        // The board is slow to answer the first probe
+        let late_board = || {
+            let mut instr = MockWaveshareInstrument::new(0x01, 8);
+            instr.delay_next_responses(1);
+            instr
+        };
+
+        let mut ws = Waveshare(late_board(), 8, 0);
+        assert!(ws.connected().is_err());
+
+        let mut ws = Waveshare(late_board(), 8, 0).with_probe_retries(2);
+        assert!(ws.connected().is_ok());
+        assert_eq!(ws.software_revision().unwrap(), "v1.00");
+        // The probe is only written once, it's the read that's retried
+        assert_eq!(ws.mock().written().len(), 2);
+        // And only for discovery, other commands keep the instrument's setting
+        assert_eq!(ws.0.empty_read_retries(), 0);
+
+        // A probe the board never heard, or an answer that was cut short, is written again
+        let mut instr = MockWaveshareInstrument::new(0x01, 8);
+        instr.ignore_next_messages(1);
+        instr.cut_next_responses(1);
+        let mut ws = Waveshare(instr, 8, 0).with_probe_retries(2);
+        assert!(ws.connected().is_ok());
+        assert_eq!(ws.mock().written().len(), 3);
     }
 }
 
@@ -349,9 +671,9 @@ mod tests {
     use std::time::Duration;
 
     // Helper function
-    fn ws() -> Waveshare {
-        let device = crate::tests::test_device_from_type(Controller::Waveshare);
-        Waveshare::connect(device.conn.controller_addr(), &device.conn.port()).unwrap()
+    fn ws() -> Option<Waveshare> {
+        let device = crate::tests::try_test_device_from_type(Controller::Waveshare)?;
+        Some(Waveshare::connect(device.conn.controller_addr(), &device.conn.port()).unwrap())
     }
 
     #[test]
@@ -364,7 +686,7 @@ mod tests {
     #[test]
 
     fn test_crc_16_checksum() {
-        let checksum = CRC_MODBUS.checksum(&[0x01, 0x05, 0x00, 0x00, 0xFF, 0x00]);
+        let checksum = waveshare_common::CRC_MODBUS.checksum(&[0x01, 0x05, 0x00, 0x00, 0xFF, 0x00]);
         assert_eq!(checksum, 0x3A8C);
 
         // test swapping the bytes
@@ -372,10 +694,25 @@ mod tests {
         assert_eq!([0x8C, 0x3A], checksum.to_le_bytes());
     }
 
+    #[test]
+    fn test_flip_relay() {
+        let Some(mut ws) = ws() else {
+            return;
+        };
+
+        let original = ws.get_relay(0).unwrap();
+        ws.flip_relay(0).unwrap();
+        assert_ne!(ws.get_relay(0).unwrap(), original);
+        ws.flip_relay(0).unwrap();
+        assert_eq!(ws.get_relay(0).unwrap(), original);
+    }
+
     #[test]
 
     fn test_write_relay_state() {
-        let mut ws = ws();
+        let Some(mut ws) = ws() else {
+            return;
+        };
 
         assert!(ws.set_relay(0, BinaryState::On).is_ok());
         sleep(Duration::from_millis(200));
@@ -385,7 +722,9 @@ mod tests {
     #[test]
 
     fn test_get_relay_status() {
-        let mut ws = ws();
+        let Some(mut ws) = ws() else {
+            return;
+        };
 
         ws.set_relay(0, BinaryState::On).unwrap();
         assert_eq!(ws.get_relay(0).unwrap(), BinaryState::On);
@@ -397,7 +736,9 @@ mod tests {
     #[test]
 
     fn test_write_all_relays() {
-        let mut ws = ws();
+        let Some(mut ws) = ws() else {
+            return;
+        };
 
         ws.set_all_relays(BinaryState::On).unwrap();
         for i in 0..8 {
@@ -410,7 +751,9 @@ mod tests {
     #[test]
 
     fn test_get_all_relays_status() {
-        let mut ws = ws();
+        let Some(mut ws) = ws() else {
+            return;
+        };
 
         let expected = vec![
             BinaryState::On,
@@ -434,14 +777,18 @@ mod tests {
     #[test]
 
     fn test_software_revision() {
-        let mut ws = ws();
+        let Some(mut ws) = ws() else {
+            return;
+        };
         assert_eq!(ws.software_revision().unwrap(), "v2.00");
     }
 
     #[test]
 
     fn test_get_device_address() {
-        let mut ws = ws();
+        let Some(mut ws) = ws() else {
+            return;
+        };
         let addr = ws.get_address();
         assert_eq!(addr.unwrap(), 0x01);
     }
@@ -449,7 +796,9 @@ mod tests {
     #[test]
 
     fn test_set_device_address() {
-        let mut ws = ws();
+        let Some(mut ws) = ws() else {
+            return;
+        };
 
         assert_eq!(ws.get_address().unwrap(), 0x01);
         assert!(ws.set_address(0x05).is_ok());
@@ -457,4 +806,20 @@ mod tests {
         assert!(ws.set_address(0x01).is_ok());
         assert_eq!(ws.get_address().unwrap(), 0x01);
     }
+
+    #[test]
+    fn test_relay_pattern() {
+        use BinaryState::*;
+        let Some(mut ws) = ws() else {
+            return;
+        };
+
+        ws.set_relay_pattern(0b0000_0101).unwrap();
+        assert_eq!(
+            ws.get_all_relays().unwrap(),
+            vec![On, Off, On, Off, Off, Off, Off, Off]
+        );
+        sleep(Duration::from_millis(50));
+        ws.set_relay_pattern(0).unwrap();
+    }
 }
@@ -0,0 +1,180 @@
+//! The frame handling that both versions of the Waveshare board share.
+//!
+//! Version 1 and 2 answer some commands differently, but checksums, relay numbers, flipping a relay,
+//! and reading relay statuses are the same on both. [`Waveshare`](crate::controllers::Waveshare) and
+//! [`WaveshareV2`](crate::controllers::WaveshareV2) build on the functions here.
+
+// ext uses
+// Used for checksums
+use crc::{Crc, CRC_16_MODBUS};
+
+// internal uses
+use crate::drivers::{InstrumentError, Result};
+use crate::state::BinaryState;
+
+// This is the checksum algorithm that the board uses
+pub(crate) const CRC_MODBUS: Crc<u16> = Crc::<u16>::new(&CRC_16_MODBUS);
+
+// Calculates the CRC checksum for the data bytes to send to the board
+pub(crate) fn append_checksum(bytes: &mut Vec<u8>) -> Result<()> {
+    let checksum = CRC_MODBUS.checksum(bytes).to_le_bytes();
+    bytes.push(checksum[0]);
+    bytes.push(checksum[1]);
+    Ok(())
+}
+
+// Relays are numbered from 0, so anything past the relay count isn't on the board
+pub(crate) fn check_relay_num(relay_num: u8, relay_count: u8, address: u8) -> Result<()> {
+    if relay_num >= relay_count {
+        return Err(InstrumentError::serialError(
+            format!(
+                "relay {} doesn't exist, this board has {} relays (0-{})",
+                relay_num,
+                relay_count,
+                relay_count.saturating_sub(1)
+            ),
+            Some(address),
+        ));
+    }
+    Ok(())
+}
+
+// Example: 01 05 00 00 55 00 F2 9A
+// The same as setting a relay, but with 0x5500 (flip) as the command
+pub(crate) fn flip_relay_frame(address: u8, relay_num: u8) -> Result<Vec<u8>> {
+    let mut bytes: Vec<u8> = vec![address, 0x05, 0x00, relay_num, 0x55, 0x00];
+    append_checksum(&mut bytes)?;
+    Ok(bytes)
+}
+
+// A read response is [addr, func_code, byte_count, data..., crc, crc]. This checks that all of it
+// arrived and that the CRC matches before we trust any of it.
+pub(crate) fn verify_response(resp: &[u8], address: u8) -> Result<()> {
+    let expected_len = match resp.get(2) {
+        Some(&byte_count) => 3 + byte_count as usize + 2,
+        None => 5,
+    };
+    if resp.len() < expected_len {
+        return Err(InstrumentError::serialError(
+            format!(
+                "The board's response was cut short, expected {} bytes, received {:02X?}",
+                expected_len, resp
+            ),
+            Some(address),
+        ));
+    }
+
+    let (frame, crc) = resp[..expected_len].split_at(expected_len - 2);
+    let expected_crc = CRC_MODBUS.checksum(frame).to_le_bytes();
+    if crc != expected_crc {
+        return Err(InstrumentError::serialError(
+            format!(
+                "CRC mismatch in the board's response, expected {:02X?}, received {:02X?} in {:02X?}",
+                expected_crc, crc, resp
+            ),
+            Some(address),
+        ));
+    }
+    Ok(())
+}
+
+// Parses the relay statuses out of a read relay response:
+// [addr, func_code, byte_count, status bytes..., crc, crc]
+//
+// Each status byte holds 8 relays, least significant bit first. We go by the byte count
+// the board sends rather than assuming 8 relays, so larger boards get all their relays.
+pub(crate) fn parse_relay_statuses(resp: &[u8]) -> Option<Vec<BinaryState>> {
+    let byte_count = *resp.get(2)? as usize;
+    let status_bytes = resp.get(3..3 + byte_count)?;
+
+    let statuses = status_bytes
+        .iter()
+        .flat_map(|byte| (0..8).map(move |bit| (byte >> bit) & 1 == 1))
+        .map(BinaryState::from)
+        .collect();
+    Some(statuses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc_16_checksum() {
+        let checksum = CRC_MODBUS.checksum(&[0x01, 0x05, 0x00, 0x00, 0xFF, 0x00]);
+        assert_eq!(checksum, 0x3A8C);
+
+        // test swapping the bytes
+        // from 0x3A8C we want [8C, 3A]
+        assert_eq!([0x8C, 0x3A], checksum.to_le_bytes());
+    }
+
+    #[test]
+    fn test_check_relay_num() {
+        assert!(check_relay_num(0, 8, 0x01).is_ok());
+        assert!(check_relay_num(31, 32, 0x01).is_ok());
+
+        let err = check_relay_num(8, 8, 0x01).unwrap_err();
+        assert!(err.to_string().contains("relay 8 doesn't exist"));
+        assert!(check_relay_num(0, 0, 0x01).is_err());
+    }
+
+    #[test]
+    fn test_flip_relay_frame() {
+        assert_eq!(
+            flip_relay_frame(0x01, 0x00).unwrap(),
+            vec![0x01, 0x05, 0x00, 0x00, 0x55, 0x00, 0xF2, 0x9A]
+        );
+        assert_eq!(
+            flip_relay_frame(0x01, 0x03).unwrap(),
+            vec![0x01, 0x05, 0x00, 0x03, 0x55, 0x00, 0x02, 0x9A]
+        );
+    }
+
+    #[test]
+    fn test_verify_response() {
+        use BinaryState::*;
+
+        let resp = [0x01, 0x01, 0x01, 0b0000_0101, 0x91, 0x8B];
+        assert!(verify_response(&resp, 0x01).is_ok());
+        assert_eq!(
+            parse_relay_statuses(&resp).unwrap(),
+            vec![On, Off, On, Off, Off, Off, Off, Off]
+        );
+
+        // A flipped bit in the statuses
+        let corrupted = [0x01, 0x01, 0x01, 0b0000_0111, 0x91, 0x8B];
+        let err = verify_response(&corrupted, 0x01).unwrap_err();
+        assert!(err.to_string().contains("CRC mismatch"));
+
+        // Missing the CRC, and nothing at all
+        let err = verify_response(&resp[..4], 0x01).unwrap_err();
+        assert!(err.to_string().contains("cut short"));
+        assert!(verify_response(&[], 0x01).is_err());
+    }
+
+    #[test]
+    fn test_parse_relay_statuses() {
+        use BinaryState::*;
+
+        // 8 relays, relays 0 and 2 on
+        let resp = [0x01, 0x01, 0x01, 0b0000_0101, 0x00, 0x00];
+        assert_eq!(
+            parse_relay_statuses(&resp).unwrap(),
+            vec![On, Off, On, Off, Off, Off, Off, Off]
+        );
+
+        // A 16 channel board sends two status bytes. Relays 1 and 15 on
+        let resp = [0x01, 0x01, 0x02, 0b0000_0010, 0b1000_0000, 0x00, 0x00];
+        let statuses = parse_relay_statuses(&resp).unwrap();
+        assert_eq!(statuses.len(), 16);
+        for (i, state) in statuses.iter().enumerate() {
+            let expected = if i == 1 || i == 15 { On } else { Off };
+            assert_eq!(*state, expected, "relay {}", i);
+        }
+
+        // Byte count says there's more data than we got
+        assert!(parse_relay_statuses(&[0x01, 0x01, 0x02, 0xFF]).is_none());
+        assert!(parse_relay_statuses(&[]).is_none());
+    }
+}
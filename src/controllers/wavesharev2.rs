@@ -8,13 +8,13 @@ use async_trait::async_trait;
 use log::*;
 use std::time::Duration;
 
-// ext uses
-// Used for checksums
-use crc::{Crc, CRC_16_MODBUS};
 use log::trace;
 
 // internal uses
-use crate::drivers::{serial::SerialInstrument, InstrumentError, Result};
+use crate::controllers::waveshare::WAVESHARE_DEFAULT_RELAY_COUNT;
+use crate::controllers::waveshare_common;
+use crate::drivers::mock::MockWaveshareInstrument;
+use crate::drivers::{serial::SerialInstrument, InstrumentError, Result, SerialDriver};
 use crate::logging_utils::device_trace;
 use crate::model::Device;
 use crate::state::{BinaryState, StateError};
@@ -28,57 +28,73 @@ pub mod func_codes {
     pub const WRITE_RELAY: u8 = 0x05;
     pub const SET_BAUD: u8 = 0x06;
     pub const WRITE_ALL_RELAYS: u8 = 0x0F;
+    /// Not a function code on its own. This goes in the high byte of the relay address with
+    /// [`WRITE_RELAY`] to turn a relay on, then back off after a delay.
+    pub const FLASH_ON: u8 = 0x02;
+    /// The same as [`FLASH_ON`], but turns the relay off, then back on after the delay
+    pub const FLASH_OFF: u8 = 0x04;
 }
 
-// This is the checksum algorithm that the board uses
-const CRC_MODBUS: Crc<u16> = Crc::<u16>::new(&CRC_16_MODBUS);
 // The baudrates that the WaveshareV2 supports
 pub const WAVESHAREV2_BAUDRATES: [usize; 8] =
     [4800, 9600, 19200, 38400, 57600, 115200, 128000, 256000];
 
 /// A Waveshare board.
+///
+/// Like [`Waveshare`](crate::controllers::Waveshare), this defaults to 8 relays, see
+/// [`WaveshareV2::with_relay_count`](crate::controllers::WaveshareV2::with_relay_count) for the bigger boards.
 #[derive(Debug)]
-pub struct WaveshareV2(SerialInstrument);
+pub struct WaveshareV2<I = SerialInstrument>(I, u8, u8);
 
 #[async_trait]
 impl SCADADevice for WaveshareV2 {
     async fn update(device: &mut Device) -> Result<()> {
         device_trace!(device, "updating WaveshareV2 device...");
-        let mut board = Self::connect(
-            device.conn.controller_addr,
-            &device.conn.port(),
-            // TODO: read these from the device once it's implemented
-            device.conn.baudrate().clone(),
-            device.conn.timeout(),
-        )?;
-
-        device.state.relay_state = Some(board.get_relay(device.conn.addr)?);
-
+        // A mock board given to the device (see `Device::attach_mock`) stands in for the real one
+        match device.take_connection::<WaveshareV2<MockWaveshareInstrument>>() {
+            Some(mock) => mock.update_device(device)?,
+            None => match device.take_connection::<WaveshareV2>() {
+                Some(board) => board.update_device(device)?,
+                None => WaveshareV2::try_from(&*device)?.update_device(device)?,
+            },
+        }
         device_trace!(device, "updated");
         Ok(())
     }
 
     async fn enact(device: &mut Device) -> Result<()> {
         device_trace!(device, "enacting WaveshareV2 device...");
+        match device.take_connection::<WaveshareV2<MockWaveshareInstrument>>() {
+            Some(mock) => mock.enact_device(device)?,
+            None => match device.take_connection::<WaveshareV2>() {
+                Some(board) => board.enact_device(device)?,
+                None => WaveshareV2::try_from(&*device)?.enact_device(device)?,
+            },
+        }
+        device_trace!(device, "enacted");
+        Ok(())
+    }
+}
 
-        let mut board = Self::connect(
-            device.conn.controller_addr,
-            &device.conn.port(),
-            // TODO: read these from the device once it's implemented
-            device.conn.baudrate().clone(),
-            device.conn.timeout(),
-        )?;
+impl<I: SerialDriver + 'static> WaveshareV2<I> {
+    // Reads the device's relay into its state, then hands the board back to the device
+    fn update_device(mut self, device: &mut Device) -> Result<()> {
+        device.state.relay_state = Some(self.get_relay(device.conn.addr())?);
+        device.store_connection(self);
+        Ok(())
+    }
 
+    // Writes the device's relay state to the board, then hands the board back to the device
+    fn enact_device(mut self, device: &mut Device) -> Result<()> {
         match device.state.relay_state {
-            Some(new_state) => board.set_relay(device.conn.addr(), new_state)?,
+            Some(new_state) => self.set_relay(device.conn.addr(), new_state)?,
             None => {
                 return Err(InstrumentError::StateError(StateError::BadValue(
                     device.state.clone(),
                 )))
             }
         }
-
-        device_trace!(device, "enacted");
+        device.store_connection(self);
         Ok(())
     }
 }
@@ -92,17 +108,32 @@ impl WaveshareV2 {
         port_path: &str,
         baudrate: usize,
         timeout: Duration,
+    ) -> Result<Self> {
+        WaveshareV2::connect_with_retries(address, port_path, baudrate, timeout, 0)
+    }
+
+    /// The same as [`connect`](Self::connect), but if the board doesn't answer the software revision probe,
+    /// it's read again, then sent again, up to `probe_retries` times each. The retries are kept for later discovery calls,
+    /// see [`with_probe_retries`](Self::with_probe_retries).
+    pub fn connect_with_retries(
+        address: u8,
+        port_path: &str,
+        baudrate: usize,
+        timeout: Duration,
+        probe_retries: u8,
     ) -> Result<Self> {
         if !WAVESHAREV2_BAUDRATES.contains(&baudrate) {
-            return Err(InstrumentError::SerialError {
-                msg: format!("Invalid baudrate `{baudrate}`"),
-                addr: Some(address),
-            });
+            return Err(InstrumentError::serialError(
+                format!("Invalid baudrate `{baudrate}`"),
+                Some(address),
+            ));
         }
 
-        let mut ws = Self(SerialInstrument::new(
-            address, port_path, baudrate, timeout,
-        )?);
+        let mut ws = Self(
+            SerialInstrument::new(address, port_path, baudrate, timeout)?,
+            WAVESHARE_DEFAULT_RELAY_COUNT,
+            probe_retries,
+        );
 
         ws.connected().map_err(|instr_err| {
             InstrumentError::serialError(
@@ -117,6 +148,89 @@ impl WaveshareV2 {
         Ok(ws)
     }
 
+    // Example: 01 05 02 00 00 07 8D B0
+    // 01       Device address
+    // 05       Command for controlling a relay
+    // 02       Flash on (04 for flash off)
+    // 00       Relay number
+    // 00 07    Delay, in units of 100 ms
+    // 8D B0    CRC16
+    fn flash_relay_frame(
+        address: u8,
+        relay_num: u8,
+        on: bool,
+        delay_units: u16,
+    ) -> Result<Vec<u8>> {
+        let flash = match on {
+            true => func_codes::FLASH_ON,
+            false => func_codes::FLASH_OFF,
+        };
+        let mut bytes: Vec<u8> = vec![address, func_codes::WRITE_RELAY, flash, relay_num];
+        bytes.extend(delay_units.to_be_bytes());
+        waveshare_common::append_checksum(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    // Example: 01 01 00 00 00 08 3D CC
+    // 01       Device address
+    // 01       Command for reading relays
+    // 00 00    Address of the first relay
+    // 00 08    Number of relays to read
+    // 3D CC    CRC16
+    //
+    // The board responds with one status byte for every 8 relays
+    fn read_relays_frame(address: u8, relay_count: u8) -> Result<Vec<u8>> {
+        let mut bytes: Vec<u8> = vec![
+            address,
+            func_codes::READ_RELAY,
+            0x00,
+            0x00, // Initial addr
+            0x00,
+            relay_count, // Number of relays
+        ];
+        waveshare_common::append_checksum(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+impl<I: SerialDriver> WaveshareV2<I> {
+    // Wraps an instrument without talking to the board, for scanning the bus
+    pub(crate) fn from_instrument(instr: I) -> Self {
+        WaveshareV2(instr, WAVESHARE_DEFAULT_RELAY_COUNT, 0)
+    }
+
+    // Points the instrument at another address and checks if a board answers there
+    pub(crate) fn probe_at(&mut self, address: u8) -> bool {
+        self.0.set_address(address);
+        self.software_revision().is_ok()
+    }
+
+    /// Sets how many relays the board has, one of [`WAVESHARE_RELAY_COUNTS`](crate::controllers::waveshare::WAVESHARE_RELAY_COUNTS).
+    /// Boards are assumed to have 8 unless this is called.
+    pub fn with_relay_count(mut self, relay_count: u8) -> Self {
+        self.1 = relay_count;
+        self
+    }
+
+    /// How many relays the board has
+    pub fn relay_count(&self) -> u8 {
+        self.1
+    }
+
+    /// Sets how many times [`get_address`](Self::get_address) and [`software_revision`](Self::software_revision)
+    /// read again, then send the command again, if the board doesn't answer in full. See
+    /// [`SerialDriver::write_with_retries`](crate::drivers::SerialDriver::write_with_retries).
+    pub fn with_probe_retries(mut self, probe_retries: u8) -> Self {
+        self.2 = probe_retries;
+        self
+    }
+
+    /// How many times discovery commands are retried if the board doesn't answer
+    pub fn probe_retries(&self) -> u8 {
+        self.2
+    }
+
+    /// Checks that the board responds by reading its software revision
     pub fn connected(&mut self) -> Result<()> {
         self.software_revision()?;
         Ok(())
@@ -142,12 +256,13 @@ impl WaveshareV2 {
             relay_num,
             state
         );
+        waveshare_common::check_relay_num(relay_num, self.1, self.0.address())?;
         let mut bytes: Vec<u8> = vec![
             // Address
             self.0.address(),
             // Command to control a relay
             func_codes::WRITE_RELAY,
-            // Relay number (ours only has 8)
+            // Relay number
             0x00,
             relay_num,
         ];
@@ -161,12 +276,54 @@ impl WaveshareV2 {
         // Add on 0x00, because the board needs it I guess
         bytes.push(0x00);
 
-        Self::append_checksum(&mut bytes).unwrap();
+        waveshare_common::append_checksum(&mut bytes).unwrap();
+
+        self.0.write_to_device(bytes)?;
+        Ok(())
+    }
 
+    /// Flips a relay, turning it on if it's off and off if it's on. The board does this itself,
+    /// so it's one command instead of reading the relay then setting it.
+    pub fn flip_relay(&mut self, relay_num: u8) -> Result<()> {
+        trace!(
+            "[WaveshareV2 addr: {}] flipping relay {}",
+            self.0.address(),
+            relay_num
+        );
+        waveshare_common::check_relay_num(relay_num, self.1, self.0.address())?;
+        let bytes = waveshare_common::flip_relay_frame(self.0.address(), relay_num)?;
         self.0.write_to_device(bytes)?;
         Ok(())
     }
 
+    /// Flashes a relay, so you can find it on the panel by its click. If `on` is true the relay turns on, then back
+    /// off after `delay_units` * 100 ms. If it's false the relay turns off, then back on. The board times this itself.
+    pub fn flash_relay(&mut self, relay_num: u8, on: bool, delay_units: u16) -> Result<()> {
+        trace!(
+            "[WaveshareV2 addr: {}] flashing relay {} {} for {} ms",
+            self.0.address(),
+            relay_num,
+            if on { "on" } else { "off" },
+            delay_units as u32 * 100
+        );
+        waveshare_common::check_relay_num(relay_num, self.1, self.0.address())?;
+        let bytes = WaveshareV2::flash_relay_frame(self.0.address(), relay_num, on, delay_units)?;
+        self.0.write_to_device(bytes)?;
+        Ok(())
+    }
+
+    /// Sets a relay like [`set_relay`](crate::controllers::WaveshareV2::set_relay), but reads it first and returns
+    /// the state it was in before. This is two commands instead of one.
+    pub fn set_relay_returning_prev(
+        &mut self,
+        relay_num: u8,
+        new_state: BinaryState,
+    ) -> Result<BinaryState> {
+        let prev = self.get_relay(relay_num)?;
+        self.set_relay(relay_num, new_state)?;
+        Ok(prev)
+    }
+
     /// Gets a relay state. See [`BinaryState`](crate::controllers::BinaryState).
     pub fn get_relay(&mut self, relay_num: u8) -> Result<BinaryState> {
         trace!(
@@ -192,61 +349,34 @@ impl WaveshareV2 {
         }
     }
 
-    // Calculates the CRC checksum for the data bytes to send to the board
-    fn append_checksum(bytes: &mut Vec<u8>) -> Result<()> {
-        let checksum = CRC_MODBUS.checksum(&bytes).to_le_bytes();
-        bytes.push(checksum[0]);
-        bytes.push(checksum[1]);
-        Ok(())
-    }
-
-    /// Returns a `Vec<BinaryState>` of all 8 relays.
+    /// Returns a `Vec<BinaryState>` of all the relays on the board, one for each of its
+    /// [`relay_count`](Self::relay_count) relays.
     pub fn get_all_relays(&mut self) -> Result<Vec<BinaryState>> {
         trace!(
             "[WaveshareV2 addr: {}] getting all relays",
             self.0.address()
         );
-        let mut bytes: Vec<u8> = vec![
-            self.0.address(),
-            func_codes::READ_RELAY,
-            0x00,
-            0x00, // Initial addr
-            0x00,
-            0x08, // Final addr
-        ];
-        Self::append_checksum(&mut bytes)?;
-        let resp = self.0.write_to_device(bytes)?;
+        let bytes = WaveshareV2::read_relays_frame(self.0.address(), self.1)?;
+        let resp = self.0.write_to_device(bytes.clone())?;
 
         trace!("Got all relay states: {:X?}", resp);
+        waveshare_common::verify_response(&resp, self.0.address())?;
 
-        if let Some(status_number) = resp.get(3) {
-            // this is a little cursed but i don't know how else to work with binary
-            let binary = format!("{:08b}", status_number);
-            trace!("States as binary: {:?}", binary);
-            let statuses: Vec<BinaryState> = binary
-                .chars()
-                .filter(|&ch| ch == '1' || ch == '0')
-                .map(|ch| {
-                    // Usually 0 and 1 are stepper states, not binary
-                    // That's why theres no FromStr for BinaryState
-                    match ch {
-                        '1' => BinaryState::On,
-                        '0' => BinaryState::Off,
-                        _ => BinaryState::default(),
-                    }
-                })
-                .rev()
-                .collect();
-
-            Ok(statuses)
-        } else {
-            Err(InstrumentError::serialError(
+        match waveshare_common::parse_relay_statuses(&resp) {
+            Some(mut statuses) if statuses.len() >= self.1 as usize => {
+                // The last status byte is padded out to 8 relays
+                statuses.truncate(self.1 as usize);
+                Ok(statuses)
+            }
+            _ => Err(InstrumentError::serialErrorWithFrames(
                 format!(
-                    "Board did not return the proper response, received {:?}",
-                    resp
+                    "Board did not return the proper response for {} relays",
+                    self.1
                 ),
                 Some(self.0.address()),
-            ))
+                bytes,
+                resp,
+            )),
         }
     }
 
@@ -261,9 +391,9 @@ impl WaveshareV2 {
             0x01, // Fixed
         ];
 
-        Self::append_checksum(&mut bytes)?;
+        waveshare_common::append_checksum(&mut bytes)?;
 
-        let resp = self.0.write_to_device(bytes)?;
+        let resp = self.0.write_with_retries(bytes, 5, self.2)?;
 
         if let Some(&version_num) = resp.get(4) {
             Ok(format!("v{:.2}", (version_num as f64 / 100.0)))
@@ -272,7 +402,7 @@ impl WaveshareV2 {
                 InstrumentError::serialError(
                     format!(
                         "The board didn't return it's software revision correctly. Possible connection issue. port: {:?}, response: {:?}",
-                        self.0.port(),
+                        self.0.port_path(),
                         resp
                     ),
                     Some(self.0.address())
@@ -298,21 +428,20 @@ impl WaveshareV2 {
             0x01, // Fixed
         ];
 
-        Self::append_checksum(&mut bytes)?;
+        waveshare_common::append_checksum(&mut bytes)?;
 
-        let resp = self.0.write_to_device(bytes)?;
+        let resp = self.0.write_with_retries(bytes.clone(), 5, self.2)?;
 
         trace!("get_address() Resp: {:X?}", resp);
 
-        resp.get(4)
-            .ok_or(InstrumentError::serialError(
-                format!(
-                    "The board didn't return the proper response, recieved: {:?}",
-                    resp
-                ),
+        resp.get(4).copied().ok_or_else(|| {
+            InstrumentError::serialErrorWithFrames(
+                String::from("The board didn't return the proper response"),
                 Some(self.0.address()),
-            ))
-            .copied()
+                bytes,
+                resp.clone(),
+            )
+        })
     }
 
     /// Sets the address of a board. You don't need to reconnect to the board
@@ -334,7 +463,7 @@ impl WaveshareV2 {
             new_addr, // new address
         ];
 
-        Self::append_checksum(&mut bytes)?;
+        waveshare_common::append_checksum(&mut bytes)?;
 
         let _resp = self.0.write_to_device(bytes)?;
         self.0.set_address(new_addr);
@@ -366,7 +495,7 @@ impl WaveshareV2 {
                 bytes.push(0x00);
             }
         }
-        Self::append_checksum(&mut bytes)?;
+        waveshare_common::append_checksum(&mut bytes)?;
 
         self.0.write_to_device(bytes)?;
         Ok(())
@@ -376,10 +505,10 @@ impl WaveshareV2 {
         if !WAVESHAREV2_BAUDRATES.contains(&new_baud) {
             error!("Invalid baud rate: `{}`", new_baud);
             error!("Valid baudrates are: {:?}", WAVESHAREV2_BAUDRATES);
-            return Err(InstrumentError::SerialError {
-                msg: format!("`{new_baud}` is not a valid baudrate for the WaveshareV2"),
-                addr: Some(self.0.address()),
-            });
+            return Err(InstrumentError::serialError(
+                format!("`{new_baud}` is not a valid baudrate for the WaveshareV2"),
+                Some(self.0.address()),
+            ));
         }
 
         let baud_code = WAVESHAREV2_BAUDRATES
@@ -396,7 +525,7 @@ impl WaveshareV2 {
             baud_code,
         ];
 
-        Self::append_checksum(&mut bytes)?;
+        waveshare_common::append_checksum(&mut bytes)?;
         self.0.write_to_device(bytes)?;
         warn!(
             "New baudrate set to {} for WaveshareV2 (addr {}), you need to reconnect to the board",
@@ -407,16 +536,35 @@ impl WaveshareV2 {
     }
 }
 
+impl WaveshareV2<MockWaveshareInstrument> {
+    /// Connects to an in-memory mock board with the given address and number of relays, for testing
+    /// without hardware. See [`MockWaveshareInstrument`](crate::drivers::mock::MockWaveshareInstrument).
+    pub fn connect_mock(address: u8, relay_count: u8) -> Self {
+        Self(
+            MockWaveshareInstrument::new(address, relay_count),
+            relay_count,
+            0,
+        )
+    }
+
+    /// The mock board, to check its relays or what was written to it
+    pub fn mock(&self) -> &MockWaveshareInstrument {
+        &self.0
+    }
+}
+
 /// Creates a controller connection from a Device
 impl TryFrom<&Device> for WaveshareV2 {
     type Error = InstrumentError;
     fn try_from(device: &Device) -> std::result::Result<Self, Self::Error> {
-        Self::connect(
+        Self::connect_with_retries(
             device.conn.controller_addr(),
             &device.conn.port(),
-            device.conn.baudrate().clone(),
+            *device.conn.baudrate(),
             device.conn.timeout(),
+            device.conn.probe_retries(),
         )
+        .map(|ws| ws.with_relay_count(device.conn.relay_count()))
     }
 }
 
@@ -430,38 +578,97 @@ mod tests {
     use std::time::Duration;
 
     // Helper function
-    fn ws() -> WaveshareV2 {
-        let device = crate::tests::test_device_from_type(Controller::WaveshareV2);
-        WaveshareV2::try_from(&device).unwrap()
+    fn ws() -> Option<WaveshareV2> {
+        let device = crate::tests::try_test_device_from_type(Controller::WaveshareV2)?;
+        Some(WaveshareV2::try_from(&device).unwrap())
     }
 
     #[test]
     fn test_connect_to_wavesharev2() {
         // get the connection details
-        let device = crate::tests::test_device_from_type(Controller::WaveshareV2);
+        let Some(device) = crate::tests::try_test_device_from_type(Controller::WaveshareV2) else {
+            return;
+        };
         let c = device.conn;
-        let ws = WaveshareV2::connect(
-            c.controller_addr(),
-            &c.port(),
-            c.baudrate().clone(),
-            c.timeout(),
-        );
+        let ws = WaveshareV2::connect(c.controller_addr(), &c.port(), *c.baudrate(), c.timeout());
         assert!(ws.is_ok());
     }
 
     #[test]
-    fn test_crc_16_checksum() {
-        let checksum = CRC_MODBUS.checksum(&[0x01, 0x05, 0x00, 0x00, 0xFF, 0x00]);
-        assert_eq!(checksum, 0x3A8C);
+    fn test_flash_relay_frame() {
+        assert_eq!(
+            WaveshareV2::flash_relay_frame(0x01, 0x00, true, 7).unwrap(),
+            vec![0x01, 0x05, 0x02, 0x00, 0x00, 0x07, 0x8D, 0xB0]
+        );
+        assert_eq!(
+            WaveshareV2::flash_relay_frame(0x01, 0x00, false, 5).unwrap(),
+            vec![0x01, 0x05, 0x04, 0x00, 0x00, 0x05, 0x0C, 0xF9]
+        );
+        assert_eq!(
+            WaveshareV2::flash_relay_frame(0x01, 0x03, true, 10).unwrap(),
+            vec![0x01, 0x05, 0x02, 0x03, 0x00, 0x0A, 0xBC, 0x75]
+        );
+    }
 
-        // test swapping the bytes
-        // from 0x3A8C we want [8C, 3A]
-        assert_eq!([0x8C, 0x3A], checksum.to_le_bytes());
+    #[test]
+    fn test_read_relays_frame() {
+        assert_eq!(
+            WaveshareV2::read_relays_frame(0x01, 16).unwrap(),
+            vec![0x01, 0x01, 0x00, 0x00, 0x00, 0x10, 0x3D, 0xC6]
+        );
+        assert_eq!(
+            WaveshareV2::read_relays_frame(0x01, 32).unwrap(),
+            vec![0x01, 0x01, 0x00, 0x00, 0x00, 0x20, 0x3D, 0xD2]
+        );
+    }
+
+    #[test]
+    fn test_probe_retries() {
+        // The board is slow to answer the first probe
+        let late_board = || {
+            let mut instr = MockWaveshareInstrument::new(0x01, 8);
+            instr.delay_next_responses(1);
+            instr
+        };
+
+        let mut ws = WaveshareV2(late_board(), 8, 0);
+        assert!(ws.connected().is_err());
+
+        let mut ws = WaveshareV2(late_board(), 8, 0).with_probe_retries(2);
+        assert!(ws.connected().is_ok());
+        assert_eq!(ws.software_revision().unwrap(), "v1.00");
+        // The probe is only written once, it's the read that's retried
+        assert_eq!(ws.mock().written().len(), 2);
+        // And only for discovery, other commands keep the instrument's setting
+        assert_eq!(ws.0.empty_read_retries(), 0);
+
+        // A probe the board never heard, or an answer that was cut short, is written again
+        let mut instr = MockWaveshareInstrument::new(0x01, 8);
+        instr.ignore_next_messages(1);
+        instr.cut_next_responses(1);
+        let mut ws = WaveshareV2(instr, 8, 0).with_probe_retries(2);
+        assert!(ws.connected().is_ok());
+        assert_eq!(ws.mock().written().len(), 3);
+    }
+
+    #[test]
+    fn test_flip_relay() {
+        let Some(mut ws) = ws() else {
+            return;
+        };
+
+        let original = ws.get_relay(0).unwrap();
+        ws.flip_relay(0).unwrap();
+        assert_ne!(ws.get_relay(0).unwrap(), original);
+        ws.flip_relay(0).unwrap();
+        assert_eq!(ws.get_relay(0).unwrap(), original);
     }
 
     #[test]
     fn test_write_relay_state() {
-        let mut ws = ws();
+        let Some(mut ws) = ws() else {
+            return;
+        };
 
         assert!(ws.set_relay(0, BinaryState::On).is_ok());
         sleep(Duration::from_millis(200));
@@ -470,7 +677,9 @@ mod tests {
 
     #[test]
     fn test_get_relay_status() {
-        let mut ws = ws();
+        let Some(mut ws) = ws() else {
+            return;
+        };
 
         ws.set_relay(0, BinaryState::On).unwrap();
         assert_eq!(ws.get_relay(0).unwrap(), BinaryState::On);
@@ -481,7 +690,9 @@ mod tests {
 
     #[test]
     fn test_write_all_relays() {
-        let mut ws = ws();
+        let Some(mut ws) = ws() else {
+            return;
+        };
         let expected = [BinaryState::On; 8];
 
         ws.set_all_relays(BinaryState::On).unwrap();
@@ -492,7 +703,9 @@ mod tests {
 
     #[test]
     fn test_get_all_relays_status() {
-        let mut ws = ws();
+        let Some(mut ws) = ws() else {
+            return;
+        };
 
         let expected = vec![
             BinaryState::On,
@@ -515,13 +728,17 @@ mod tests {
 
     #[test]
     fn test_software_revision() {
-        let mut ws = ws();
+        let Some(mut ws) = ws() else {
+            return;
+        };
         assert_eq!(ws.software_revision().unwrap(), "v2.00");
     }
 
     #[test]
     fn test_get_device_address() {
-        let mut ws = ws();
+        let Some(mut ws) = ws() else {
+            return;
+        };
         let addr = ws.get_address();
         // TODO: Get this value from the config file
         assert_eq!(addr.unwrap(), 0x01);
@@ -529,10 +746,14 @@ mod tests {
 
     #[test]
     fn test_set_device_address() {
-        let mut ws = ws();
+        let Some(mut ws) = ws() else {
+            return;
+        };
 
         // Get any wavesharev2 device from the configuration file
-        let device = crate::tests::test_device_from_type(Controller::WaveshareV2);
+        let Some(device) = crate::tests::try_test_device_from_type(Controller::WaveshareV2) else {
+            return;
+        };
         let addr = device.conn.controller_addr();
         assert_eq!(ws.get_address().unwrap(), addr);
         assert!(ws.set_address(addr + 1).is_ok());
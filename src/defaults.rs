@@ -19,3 +19,7 @@ pub fn default_command_retries() -> u8 {
 pub fn default_retry_delay() -> u64 {
     150
 }
+
+pub fn default_enabled() -> bool {
+    true
+}
@@ -1,14 +1,14 @@
 use std::io;
 use thiserror::Error;
 
-use crate::{model::Device, state::StateError};
+use crate::{controllers::Controller, model::Device, state::StateError};
 
 /// A general purpose error that may be returned from Instrument interactions
 #[derive(Error, Debug)]
 pub enum InstrumentError {
     /// A connection error when using a [`Device`](crate::model::Device) to connect to a controller
     #[error("Connection error, couldn't connect to controller from device {:?}", 0)]
-    ConnectionError(Device),
+    ConnectionError(Box<Device>),
     /// The device timed out. This could be returned erroneously if you set the device timeout too low. Give the devices time to respond.
     #[error("Timeout error: Modbus device on port {port}, slave addr {addr} timed out after request to register 0x{register:X}")]
     ModbusTimeoutError {
@@ -16,18 +16,44 @@ pub enum InstrumentError {
         addr: u8,
         register: u16,
     },
+    /// The device timed out and its port is gone, probably because the USB adapter was unplugged.
+    /// Retrying won't help, the instrument has to be reconnected.
+    #[error(
+        "Connection lost: port {port} for Modbus device at slave addr {addr} no longer exists"
+    )]
+    ConnectionLost { port: String, addr: u8 },
+    /// The controller reported a sensor fault instead of a reading, like a disconnected thermocouple.
+    /// `value` is the raw register value it sent.
+    #[error("Sensor fault: device at addr {addr} reported {reason} (0x{value:X})")]
+    SensorFault {
+        addr: u8,
+        value: u16,
+        reason: &'static str,
+    },
     /// [`std::io::Error`](std::io::Error) wrapper
     #[error("IO Error: {0}")]
-    IOError(io::Error),
+    IOError(#[source] io::Error),
     /// General modbus error
     #[error("addr {addr:?}: {msg}")]
     ModbusError { msg: String, addr: Option<u8> },
-    /// General serial board error
-    #[error("addr {addr:?}: {msg}")]
-    SerialError { msg: String, addr: Option<u8> },
+    /// General serial board error. If the error came from a bad response, `sent` is the command that
+    /// was written and `received` is what came back, and both are hex dumped in the message.
+    #[error("addr {addr:?}: {msg}{}", frames(.sent, .received))]
+    SerialError {
+        msg: String,
+        addr: Option<u8>,
+        sent: Option<Vec<u8>>,
+        received: Option<Vec<u8>>,
+    },
     /// Wrapper around [`StateError`](crate::state::StateError), when provided the wrong type of state
     #[error("State Error: {0:?}")]
-    StateError(StateError),
+    StateError(#[source] StateError),
+    /// A relay-only operation, like [`Device::toggle`](crate::model::Device::toggle), on a device that isn't on a relay board
+    #[error("device `{device_id}` runs on a `{controller}`, which isn't a relay board")]
+    NotARelay {
+        device_id: String,
+        controller: Controller,
+    },
 }
 
 impl InstrumentError {
@@ -40,9 +66,37 @@ impl InstrumentError {
         }
     }
 
+    /// Creates a connection lost error, just a helper function
+    pub fn connectionLost(port: &str, addr: u8) -> Self {
+        Self::ConnectionLost {
+            port: port.to_string(),
+            addr,
+        }
+    }
+
     /// creates a serial error, just a helper function
     pub fn serialError(msg: String, addr: Option<u8>) -> Self {
-        Self::SerialError { msg, addr }
+        Self::SerialError {
+            msg,
+            addr,
+            sent: None,
+            received: None,
+        }
+    }
+
+    /// creates a serial error for a bad response, with the command that was sent and what came back
+    pub fn serialErrorWithFrames(
+        msg: String,
+        addr: Option<u8>,
+        sent: Vec<u8>,
+        received: Vec<u8>,
+    ) -> Self {
+        Self::SerialError {
+            msg,
+            addr,
+            sent: Some(sent),
+            received: Some(received),
+        }
     }
 
     /// creates a modbus error, just a helper function
@@ -51,8 +105,76 @@ impl InstrumentError {
     }
 }
 
+// Hex dumps the frames of a `SerialError`, like ` (sent: 55 AA 06 02 FE 08 77, received: none)`
+fn frames(sent: &Option<Vec<u8>>, received: &Option<Vec<u8>>) -> String {
+    let hex = |frame: &Option<Vec<u8>>| match frame {
+        Some(bytes) if bytes.is_empty() => String::from("nothing"),
+        Some(bytes) => bytes
+            .iter()
+            .map(|byte| format!("{byte:02X}"))
+            .collect::<Vec<_>>()
+            .join(" "),
+        None => String::from("none"),
+    };
+
+    match (sent, received) {
+        (None, None) => String::new(),
+        _ => format!(" (sent: {}, received: {})", hex(sent), hex(received)),
+    }
+}
+
 impl From<io::Error> for InstrumentError {
     fn from(e: io::Error) -> Self {
         Self::IOError(e)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn test_io_error_source() {
+        let err = InstrumentError::from(io::Error::new(io::ErrorKind::TimedOut, "port timed out"));
+
+        let source = err.source().expect("IO errors should have a source");
+        let inner = source.downcast_ref::<io::Error>().unwrap();
+        assert_eq!(inner.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(inner.to_string(), "port timed out");
+    }
+
+    #[test]
+    fn test_serial_error_frames() {
+        let err = InstrumentError::serialErrorWithFrames(
+            String::from("bad response"),
+            Some(254),
+            vec![0x55, 0xAA, 0x06, 0x14, 0xFE, 0x00, 0x08, 0x20, 0x77],
+            vec![0xCC, 0x33],
+        );
+        assert_eq!(
+            err.to_string(),
+            "addr Some(254): bad response (sent: 55 AA 06 14 FE 00 08 20 77, received: CC 33)"
+        );
+
+        let silent = InstrumentError::serialErrorWithFrames(
+            String::from("no response"),
+            Some(254),
+            vec![0x55, 0xAA],
+            vec![],
+        );
+        assert!(silent
+            .to_string()
+            .ends_with("(sent: 55 AA, received: nothing)"));
+
+        // Without frames, the message is unchanged
+        let err = InstrumentError::serialError(String::from("bad response"), Some(254));
+        assert_eq!(err.to_string(), "addr Some(254): bad response");
+    }
+
+    #[test]
+    fn test_errors_without_source() {
+        let err = InstrumentError::serialError(String::from("bad response"), Some(254));
+        assert!(err.source().is_none());
+    }
+}
@@ -0,0 +1,632 @@
+//! In-memory drivers, for testing without hardware
+//!
+//! These stand in for a [`SerialInstrument`](crate::drivers::SerialInstrument) or [`ModbusInstrument`](crate::drivers::ModbusInstrument)
+//! and keep the device's state in memory instead of talking to a port. Controllers that are generic over their driver
+//! can be built on them, see [`STR1::connect_mock`](crate::controllers::STR1::connect_mock),
+//! [`WaveshareV2::connect_mock`](crate::controllers::WaveshareV2::connect_mock),
+//! [`CN7500::connect_mock`](crate::controllers::CN7500::connect_mock), and
+//! [`AnalogOutput::connect_mock`](crate::controllers::analog_output::AnalogOutput::connect_mock). A device
+//! can be given one of these in place of its real controller with
+//! [`Device::attach_mock`](crate::model::Device::attach_mock).
+//!
+//! ```rust
+//! # use brewdrivers::controllers::{BinaryState, STR1};
+//! let mut board = STR1::connect_mock(0xFE, 8).unwrap();
+//! board.set_relay(3, BinaryState::On).unwrap();
+//! assert_eq!(board.get_relay(3).unwrap(), BinaryState::On);
+//! ```
+use std::collections::HashMap;
+use std::fmt;
+
+use async_trait::async_trait;
+use crc::{Crc, CRC_16_MODBUS};
+
+use crate::drivers::{Instrument, InstrumentError, ModbusDriver, Result, SerialDriver};
+
+/// The port path the mock instruments report
+pub const MOCK_PORT: &str = "mock";
+
+// Slave start and end bytes the mock board responds with. Nothing reads these, so they're
+// just here to keep the responses the same shape as the real board's.
+const SL0: u8 = 0xCC;
+const SL1: u8 = 0x33;
+const SLE: u8 = 0x77;
+
+// The Waveshare boards use the Modbus CRC
+const CRC_MODBUS: Crc<u16> = Crc::<u16>::new(&CRC_16_MODBUS);
+
+/// A serial instrument that acts like an `STR1XX` relay board, with its relays kept in memory.
+///
+/// It understands the STR1 commands that [`STR1`](crate::controllers::STR1) sends: relay count, read relays,
+/// set relays, read inputs, set controller number, and set baudrate. Like the real board, it doesn't respond to commands for a
+/// different controller number. Everything written to it is recorded, see [`MockSerialInstrument::written`].
+#[derive(Debug)]
+pub struct MockSerialInstrument {
+    address: u8,
+    baudrate: usize,
+    board_addr: u8,
+    relays: Vec<bool>,
+    inputs: Vec<bool>,
+    written: Vec<Vec<u8>>,
+}
+
+impl MockSerialInstrument {
+    /// A mock board at the given controller number, with `relay_count` relays that all start off and no inputs
+    pub fn new(address: u8, relay_count: u8) -> Self {
+        Self {
+            address,
+            baudrate: 9600,
+            board_addr: address,
+            relays: vec![false; relay_count as usize],
+            inputs: vec![],
+            written: vec![],
+        }
+    }
+
+    /// Sets the state of the board's digital inputs. The board reports as many inputs as are given here.
+    pub fn set_inputs(&mut self, inputs: &[bool]) {
+        self.inputs = inputs.to_vec();
+    }
+
+    /// The state of each relay on the mock board
+    pub fn relays(&self) -> &[bool] {
+        &self.relays
+    }
+
+    /// Every message written to the board, in order, with all their bytes
+    pub fn written(&self) -> &[Vec<u8>] {
+        &self.written
+    }
+
+    /// The controller number the mock board answers to. This changes if the
+    /// board is sent a set controller number command.
+    pub fn board_addr(&self) -> u8 {
+        self.board_addr
+    }
+
+    /// The baudrate the port was last opened at
+    pub fn baudrate(&self) -> usize {
+        self.baudrate
+    }
+
+    // Builds a response: SL0, SL1, BC, data..., CS, SLE
+    fn response(data: &[u8]) -> Vec<u8> {
+        // BC counts itself, the data, CS, and SLE
+        let bc = data.len() as u8 + 3;
+        let cs = data.iter().fold(bc, |sum, &byte| sum.wrapping_add(byte));
+        let mut resp = vec![SL0, SL1, bc];
+        resp.extend(data);
+        resp.push(cs);
+        resp.push(SLE);
+        resp
+    }
+
+    // Acts on one message: MA0, MA1, BC, CC, CN, data..., CS, MAE
+    fn respond(&mut self, bytes: &[u8]) -> Vec<u8> {
+        if bytes.len() < 7 || bytes[4] != self.board_addr {
+            // Not for us, the real board would stay quiet too
+            return vec![];
+        }
+        let command = bytes[3];
+        let data = &bytes[5..bytes.len() - 2];
+
+        match (command, data) {
+            // Relay count: SL0, SL1, 0x09, outputs, inputs, analog inputs, analog outputs, 0, 0, CS, SLE
+            (0x02, _) => {
+                Self::response(&[self.relays.len() as u8, self.inputs.len() as u8, 0, 0, 0, 0])
+            }
+            // Read relays, starting at `first`
+            (0x14, &[first, count]) => {
+                let statuses: Vec<u8> = (first..first.saturating_add(count))
+                    .map(|i| *self.relays.get(i as usize).unwrap_or(&false) as u8)
+                    .collect();
+                Self::response(&statuses)
+            }
+            // Read inputs, starting at `first`
+            (0x15, &[first, count]) => {
+                let statuses: Vec<u8> = (first..first.saturating_add(count))
+                    .map(|i| *self.inputs.get(i as usize).unwrap_or(&false) as u8)
+                    .collect();
+                Self::response(&statuses)
+            }
+            // Set relays, starting at `first`, with one state for all of them or a state per relay
+            (0x17, &[first, count, ref states @ ..])
+                if states.len() == 1 || states.len() == count as usize =>
+            {
+                for (n, i) in (first..first.saturating_add(count)).enumerate() {
+                    let state = states.get(n).unwrap_or(&states[0]);
+                    if let Some(relay) = self.relays.get_mut(i as usize) {
+                        *relay = *state == 1;
+                    }
+                }
+                Self::response(&[])
+            }
+            (0x01, &[new_cn]) => {
+                self.board_addr = new_cn;
+                Self::response(&[])
+            }
+            (0x33, &[0xAA, 0x55, _]) => Self::response(&[]),
+            _ => vec![],
+        }
+    }
+}
+
+#[async_trait]
+impl Instrument for MockSerialInstrument {
+    fn address(&self) -> u8 {
+        self.address
+    }
+
+    fn port_path(&self) -> &str {
+        MOCK_PORT
+    }
+
+    async fn transact(&mut self, bytes: Vec<u8>) -> Result<Vec<u8>> {
+        self.write_to_device(bytes)
+    }
+}
+
+impl SerialDriver for MockSerialInstrument {
+    fn write_to_device(&mut self, bytes: Vec<u8>) -> Result<Vec<u8>> {
+        let resp = self.respond(&bytes);
+        self.written.push(bytes);
+        Ok(resp)
+    }
+
+    fn set_address(&mut self, new_addr: u8) {
+        self.address = new_addr;
+    }
+
+    fn reopen_port(&mut self, new_baudrate: usize) -> Result<()> {
+        self.baudrate = new_baudrate;
+        Ok(())
+    }
+}
+
+/// A serial instrument that acts like a Waveshare Modbus RTU relay board, with its relays kept in memory.
+///
+/// It understands the relay commands that [`Waveshare`](crate::controllers::Waveshare) and
+/// [`WaveshareV2`](crate::controllers::WaveshareV2) send: read relays, write one or all relays, flip a relay,
+/// write several relays at once, and set the address. It also reports its software revision, always `v1.00`. Responses carry a correct CRC. Like
+/// [`MockSerialInstrument`], it ignores messages for other addresses and records everything written to it.
+#[derive(Debug)]
+pub struct MockWaveshareInstrument {
+    address: u8,
+    board_addr: u8,
+    relays: Vec<bool>,
+    written: Vec<Vec<u8>>,
+    empty_read_retries: u8,
+    late: u8,
+    ignored: u8,
+    cut: u8,
+}
+
+impl MockWaveshareInstrument {
+    /// A mock board at the given address, with `relay_count` relays that all start off
+    pub fn new(address: u8, relay_count: u8) -> Self {
+        Self {
+            address,
+            board_addr: address,
+            relays: vec![false; relay_count as usize],
+            written: vec![],
+            empty_read_retries: 0,
+            late: 0,
+            ignored: 0,
+            cut: 0,
+        }
+    }
+
+    /// Makes the board's next `count` answers late, like a busy or noisy bus. Each late answer costs one empty
+    /// read, so it's only received if reads are retried (see [`SerialDriver::set_empty_read_retries`]).
+    pub fn delay_next_responses(&mut self, count: u8) {
+        self.late = count;
+    }
+
+    /// Makes the board miss the next `count` messages for its address, like they were lost on the bus. They're
+    /// still recorded in [`written`](Self::written), but nothing comes back and nothing changes on the board.
+    pub fn ignore_next_messages(&mut self, count: u8) {
+        self.ignored = count;
+    }
+
+    /// Cuts the board's next `count` answers in half, like a response garbled on a noisy bus
+    pub fn cut_next_responses(&mut self, count: u8) {
+        self.cut = count;
+    }
+
+    /// The state of each relay on the mock board
+    pub fn relays(&self) -> &[bool] {
+        &self.relays
+    }
+
+    /// Every message written to the board, in order, with all their bytes
+    pub fn written(&self) -> &[Vec<u8>] {
+        &self.written
+    }
+
+    /// The address the mock board answers to. This changes if the board is sent a set address command.
+    pub fn board_addr(&self) -> u8 {
+        self.board_addr
+    }
+
+    fn with_crc(mut frame: Vec<u8>) -> Vec<u8> {
+        let crc = CRC_MODBUS.checksum(&frame).to_le_bytes();
+        frame.extend(crc);
+        frame
+    }
+
+    // Acts on one message: addr, func code, 2 byte relay address, 2 byte value or count, [data...], CRC
+    fn respond(&mut self, bytes: &[u8]) -> Vec<u8> {
+        if bytes.len() < 8 || bytes[0] != self.board_addr {
+            return vec![];
+        }
+        let (frame, crc) = bytes.split_at(bytes.len() - 2);
+        if crc != CRC_MODBUS.checksum(frame).to_le_bytes() {
+            // The real board drops anything with a bad CRC
+            return vec![];
+        }
+        let start = u16::from_be_bytes([bytes[2], bytes[3]]) as usize;
+        let value = u16::from_be_bytes([bytes[4], bytes[5]]);
+
+        match bytes[1] {
+            // Read relays. 0x00FF is how the v1 board reads all 8.
+            0x01 => {
+                let (first, count) = match start {
+                    0xFF => (0, 8),
+                    _ => (start, value as usize),
+                };
+                if first + count > self.relays.len() {
+                    // Illegal data address exception
+                    return Self::with_crc(vec![self.board_addr, 0x81, 0x02]);
+                }
+                let mut statuses = vec![0u8; count.div_ceil(8)];
+                for i in 0..count {
+                    if *self.relays.get(first + i).unwrap_or(&false) {
+                        statuses[i / 8] |= 1 << (i % 8);
+                    }
+                }
+                let mut resp = vec![self.board_addr, 0x01, statuses.len() as u8];
+                resp.extend(statuses);
+                Self::with_crc(resp)
+            }
+            // Write one relay, or all of them at 0x00FF. The board echoes the message back.
+            0x05 => {
+                let relays = match start {
+                    0xFF => 0..self.relays.len(),
+                    _ => start..start + 1,
+                };
+                for i in relays {
+                    if let Some(relay) = self.relays.get_mut(i) {
+                        *relay = match value {
+                            0xFF00 => true,
+                            0x5500 => !*relay,
+                            _ => false,
+                        };
+                    }
+                }
+                bytes.to_vec()
+            }
+            // Software revision, as a version number times 100
+            0x03 if start == 0x8000 => Self::with_crc(vec![self.board_addr, 0x03, 0x02, 0x00, 100]),
+            // Set address
+            0x06 if start == 0x4000 => {
+                self.board_addr = value as u8;
+                bytes.to_vec()
+            }
+            // Write several relays from a bitmask, after a byte count
+            0x0F => {
+                let data = &frame[7.min(frame.len())..];
+                for i in 0..value as usize {
+                    if let (Some(relay), Some(byte)) =
+                        (self.relays.get_mut(start + i), data.get(i / 8))
+                    {
+                        *relay = (byte >> (i % 8)) & 1 == 1;
+                    }
+                }
+                Self::with_crc(bytes[..6].to_vec())
+            }
+            _ => vec![],
+        }
+    }
+}
+
+#[async_trait]
+impl Instrument for MockWaveshareInstrument {
+    fn address(&self) -> u8 {
+        self.address
+    }
+
+    fn port_path(&self) -> &str {
+        MOCK_PORT
+    }
+
+    async fn transact(&mut self, bytes: Vec<u8>) -> Result<Vec<u8>> {
+        self.write_to_device(bytes)
+    }
+}
+
+impl SerialDriver for MockWaveshareInstrument {
+    fn write_to_device(&mut self, bytes: Vec<u8>) -> Result<Vec<u8>> {
+        let lost = self.ignored > 0 && bytes.first() == Some(&self.board_addr);
+        let mut resp = match lost {
+            true => {
+                self.ignored -= 1;
+                vec![]
+            }
+            false => self.respond(&bytes),
+        };
+        self.written.push(bytes);
+
+        if !resp.is_empty() && self.cut > 0 {
+            self.cut -= 1;
+            resp.truncate(resp.len() / 2);
+        }
+
+        // Every read until a late answer arrives comes back empty
+        if !resp.is_empty() && self.late > 0 {
+            let empty_reads = self.late.min(self.empty_read_retries.saturating_add(1));
+            self.late -= empty_reads;
+            if empty_reads > self.empty_read_retries {
+                return Ok(vec![]);
+            }
+        }
+        Ok(resp)
+    }
+
+    fn empty_read_retries(&self) -> u8 {
+        self.empty_read_retries
+    }
+
+    fn set_empty_read_retries(&mut self, retries: u8) {
+        self.empty_read_retries = retries;
+    }
+
+    fn set_address(&mut self, new_addr: u8) {
+        self.address = new_addr;
+    }
+
+    fn reopen_port(&mut self, _new_baudrate: usize) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// A write the driver made to a [`MockModbusInstrument`], see [`MockModbusInstrument::writes`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModbusWrite {
+    /// `(register, value)`
+    Register(u16, u16),
+    /// `(coil, value)`
+    Coil(u16, bool),
+}
+
+impl fmt::Display for ModbusWrite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ModbusWrite::Register(register, value) => {
+                write!(f, "write {value} to register {register:#06X}")
+            }
+            ModbusWrite::Coil(coil, value) => write!(f, "write {value} to coil {coil:#06X}"),
+        }
+    }
+}
+
+/// A Modbus instrument with its registers and coils kept in memory.
+///
+/// Registers and coils that haven't been written read as `0` and `false`. Writes the driver makes are
+/// recorded, see [`MockModbusInstrument::writes`].
+#[derive(Debug, Default)]
+pub struct MockModbusInstrument {
+    slave_addr: u8,
+    registers: HashMap<u16, u16>,
+    coils: HashMap<u16, bool>,
+    timeouts: u8,
+    register_reads: Vec<(u16, u16)>,
+    writes: Vec<ModbusWrite>,
+}
+
+impl MockModbusInstrument {
+    /// A new mock instrument at the given address, with every register and coil cleared
+    pub fn new(slave_addr: u8) -> Self {
+        Self {
+            slave_addr,
+            ..Default::default()
+        }
+    }
+
+    /// Reads a register directly, without going through the driver
+    pub fn register(&self, register: u16) -> u16 {
+        *self.registers.get(&register).unwrap_or(&0)
+    }
+
+    /// Sets a register directly, like the device changing it on its own (ie. a temperature reading)
+    pub fn set_register(&mut self, register: u16, value: u16) {
+        self.registers.insert(register, value);
+    }
+
+    /// Reads a coil directly, without going through the driver
+    pub fn coil(&self, coil: u16) -> bool {
+        *self.coils.get(&coil).unwrap_or(&false)
+    }
+
+    /// Sets a coil directly
+    pub fn set_coil(&mut self, coil: u16, value: bool) {
+        self.coils.insert(coil, value);
+    }
+
+    /// Every register read the driver has made, as `(first register, count)`
+    pub fn register_reads(&self) -> &[(u16, u16)] {
+        &self.register_reads
+    }
+
+    /// Every register and coil write the driver has made, in order. Setting them directly isn't recorded.
+    pub fn writes(&self) -> &[ModbusWrite] {
+        &self.writes
+    }
+
+    /// Makes the next `count` reads time out, like a busy device that misses requests
+    pub fn time_out_next_reads(&mut self, count: u8) {
+        self.timeouts = count;
+    }
+
+    // Uses up one of the reads that should time out, if there are any left
+    fn read_timed_out(&mut self, register: u16) -> Result<()> {
+        if self.timeouts == 0 {
+            return Ok(());
+        }
+        self.timeouts -= 1;
+        Err(InstrumentError::modbusTimeoutError(
+            MOCK_PORT,
+            self.slave_addr,
+            register,
+        ))
+    }
+}
+
+#[async_trait]
+impl Instrument for MockModbusInstrument {
+    fn address(&self) -> u8 {
+        self.slave_addr
+    }
+
+    fn port_path(&self) -> &str {
+        MOCK_PORT
+    }
+
+    async fn transact(&mut self, _bytes: Vec<u8>) -> Result<Vec<u8>> {
+        Err(InstrumentError::modbusError(
+            String::from("the mock doesn't support raw Modbus requests"),
+            Some(self.slave_addr),
+        ))
+    }
+}
+
+#[async_trait]
+impl ModbusDriver for MockModbusInstrument {
+    async fn read_registers(&mut self, register: u16, count: u16) -> Result<Vec<u16>> {
+        self.register_reads.push((register, count));
+        self.read_timed_out(register)?;
+        Ok((register..register.saturating_add(count))
+            .map(|r| self.register(r))
+            .collect())
+    }
+
+    async fn write_register(&mut self, register: u16, value: u16) -> Result<()> {
+        self.writes.push(ModbusWrite::Register(register, value));
+        self.set_register(register, value);
+        Ok(())
+    }
+
+    async fn read_coils(&mut self, coil: u16, count: u16) -> Result<Vec<bool>> {
+        self.read_timed_out(coil)?;
+        Ok((coil..coil.saturating_add(count))
+            .map(|c| self.coil(c))
+            .collect())
+    }
+
+    async fn write_coil(&mut self, coil: u16, value: bool) -> Result<()> {
+        self.writes.push(ModbusWrite::Coil(coil, value));
+        self.set_coil(coil, value);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drivers::serial::Bytestring;
+
+    #[test]
+    fn test_mock_serial_relays() {
+        let mut board = MockSerialInstrument::new(0xFE, 8);
+
+        let set = Bytestring::with_command(0x17, 0xFE, vec![2, 3, 1]).to_bytes();
+        board.write_to_device(set.clone()).unwrap();
+        assert_eq!(
+            board.relays(),
+            &[false, false, true, true, true, false, false, false]
+        );
+
+        let read = Bytestring::with_command(0x14, 0xFE, vec![1, 3]).to_bytes();
+        let resp = board.write_to_device(read).unwrap();
+        assert_eq!(&resp[3..6], &[0, 1, 1]);
+
+        let count = Bytestring::with_command(0x02, 0xFE, vec![]).to_bytes();
+        assert_eq!(board.write_to_device(count).unwrap()[3], 8);
+
+        assert_eq!(board.written().len(), 3);
+        assert_eq!(board.written()[0], set);
+
+        let set_each = Bytestring::with_command(0x17, 0xFE, vec![0, 3, 1, 0, 1]).to_bytes();
+        board.write_to_device(set_each).unwrap();
+        assert_eq!(
+            board.relays(),
+            &[true, false, true, true, true, false, false, false]
+        );
+    }
+
+    #[test]
+    fn test_mock_serial_ignores_other_boards() {
+        let mut board = MockSerialInstrument::new(0xFE, 8);
+        let count = Bytestring::with_command(0x02, 0x01, vec![]).to_bytes();
+        assert!(board.write_to_device(count).unwrap().is_empty());
+        // Garbage gets nothing back either
+        assert!(board.write_to_device(vec![0x01, 0x02]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_mock_waveshare() {
+        let mut board = MockWaveshareInstrument::new(0x01, 16);
+
+        // Relay 9 on: 01 05 00 09 FF 00
+        let on = MockWaveshareInstrument::with_crc(vec![0x01, 0x05, 0x00, 0x09, 0xFF, 0x00]);
+        assert_eq!(board.write_to_device(on.clone()).unwrap(), on);
+        assert!(board.relays()[9]);
+
+        // Read all 16: 01 01 00 00 00 10
+        let read = MockWaveshareInstrument::with_crc(vec![0x01, 0x01, 0x00, 0x00, 0x00, 0x10]);
+        let resp = board.write_to_device(read).unwrap();
+        assert_eq!(&resp[..5], &[0x01, 0x01, 0x02, 0x00, 0b0000_0010]);
+
+        // Bad CRC and other addresses get nothing back
+        assert!(board
+            .write_to_device(vec![0x01, 0x05, 0x00, 0x09, 0x00, 0x00, 0x00, 0x00])
+            .unwrap()
+            .is_empty());
+        let other = MockWaveshareInstrument::with_crc(vec![0x02, 0x05, 0x00, 0x09, 0x00, 0x00]);
+        assert!(board.write_to_device(other).unwrap().is_empty());
+        assert!(board.relays()[9]);
+    }
+
+    #[tokio::test]
+    async fn test_mock_modbus() {
+        let mut instr = MockModbusInstrument::new(0x16);
+        assert_eq!(instr.read_registers(0x1000, 2).await.unwrap(), vec![0, 0]);
+
+        instr.write_register(0x1001, 1500).await.unwrap();
+        instr.set_register(0x1000, 1457);
+        assert_eq!(
+            instr.read_registers(0x1000, 2).await.unwrap(),
+            vec![1457, 1500]
+        );
+
+        instr.write_coil(0x0814, true).await.unwrap();
+        assert_eq!(
+            instr.read_coils(0x0813, 2).await.unwrap(),
+            vec![false, true]
+        );
+
+        // Only the driver's writes are recorded
+        assert_eq!(
+            instr.writes(),
+            &[
+                ModbusWrite::Register(0x1001, 1500),
+                ModbusWrite::Coil(0x0814, true)
+            ]
+        );
+        assert_eq!(
+            instr.writes()[0].to_string(),
+            "write 1500 to register 0x1001"
+        );
+
+        assert!(instr.transact(vec![0x03]).await.is_err());
+    }
+}
@@ -9,12 +9,156 @@
 //! 
 //! Note that technically the devices that don't provide register and coil addresses are still using Modbus RTU. I don't care.
 
+use async_trait::async_trait;
+
 pub mod modbus;
 pub mod serial;
 pub mod instrument_error;
+pub mod mock;
+pub mod scan;
 
 pub use instrument_error::InstrumentError;
 pub use modbus::ModbusInstrument;
+pub use scan::{scan_bus, scan_bus_with_retries};
 pub use serial::instrument::SerialInstrument;
 
 pub type Result<T> = std::result::Result<T, InstrumentError>;
+
+/// What every driver can do, regardless of whether it talks in registers or bytestrings.
+///
+/// This is for generic tooling, like logging or mocking, that doesn't care what kind of instrument it has.
+/// Type specific operations (reading registers, writing bytestrings, etc.) stay on the instruments themselves.
+#[async_trait]
+pub trait Instrument: Send {
+    /// The address of the device on the bus
+    fn address(&self) -> u8;
+
+    /// The serial port path, like `/dev/ttyUSB0`
+    fn port_path(&self) -> &str;
+
+    /// Sends raw bytes to the device and returns the raw response.
+    ///
+    /// For a [`SerialInstrument`](crate::drivers::SerialInstrument) this is the whole command, and the response is
+    /// whatever the device sends back. For a [`ModbusInstrument`](crate::drivers::ModbusInstrument) this is a Modbus
+    /// PDU (a function code followed by its data), the address and CRC are added for you. The response is the
+    /// PDU the device sends back.
+    async fn transact(&mut self, bytes: Vec<u8>) -> Result<Vec<u8>>;
+}
+
+/// The register and coil operations of a Modbus instrument. Controllers that talk Modbus are written against
+/// this, so they can run on a [`ModbusInstrument`](crate::drivers::ModbusInstrument) or a
+/// [`MockModbusInstrument`](crate::drivers::mock::MockModbusInstrument).
+#[async_trait]
+pub trait ModbusDriver: Instrument {
+    /// See [`ModbusInstrument::read_registers`](crate::drivers::ModbusInstrument::read_registers)
+    async fn read_registers(&mut self, register: u16, count: u16) -> Result<Vec<u16>>;
+    /// See [`ModbusInstrument::write_register`](crate::drivers::ModbusInstrument::write_register)
+    async fn write_register(&mut self, register: u16, value: u16) -> Result<()>;
+    /// See [`ModbusInstrument::read_coils`](crate::drivers::ModbusInstrument::read_coils)
+    async fn read_coils(&mut self, coil: u16, count: u16) -> Result<Vec<bool>>;
+    /// See [`ModbusInstrument::write_coil`](crate::drivers::ModbusInstrument::write_coil)
+    async fn write_coil(&mut self, coil: u16, value: bool) -> Result<()>;
+}
+
+/// The bytestring operations of a serial instrument. Like [`ModbusDriver`](crate::drivers::ModbusDriver),
+/// this lets a controller run on a [`SerialInstrument`](crate::drivers::SerialInstrument) or a
+/// [`MockSerialInstrument`](crate::drivers::mock::MockSerialInstrument).
+pub trait SerialDriver: Instrument {
+    /// See [`SerialInstrument::write_to_device`](crate::drivers::SerialInstrument::write_to_device)
+    fn write_to_device(&mut self, bytes: Vec<u8>) -> Result<Vec<u8>>;
+    /// See [`SerialInstrument::set_address`](crate::drivers::SerialInstrument::set_address)
+    fn set_address(&mut self, new_addr: u8);
+    /// See [`SerialInstrument::reopen_port`](crate::drivers::SerialInstrument::reopen_port)
+    fn reopen_port(&mut self, new_baudrate: usize) -> Result<()>;
+
+    /// See [`SerialInstrument::empty_read_retries`](crate::drivers::SerialInstrument::empty_read_retries).
+    /// Instruments that don't retry empty reads can leave this out, it's `0`.
+    fn empty_read_retries(&self) -> u8 {
+        0
+    }
+    /// See [`SerialInstrument::set_empty_read_retries`](crate::drivers::SerialInstrument::set_empty_read_retries).
+    /// Instruments that don't retry empty reads can leave this out, it does nothing.
+    fn set_empty_read_retries(&mut self, _retries: u8) {}
+
+    /// Writes the bytes like [`write_to_device`](crate::drivers::SerialDriver::write_to_device), but gives the board
+    /// a few more chances to answer. Empty reads are retried at least `retries` times for this one command (see
+    /// [`SerialInstrument::with_empty_read_retries`](crate::drivers::SerialInstrument::with_empty_read_retries)),
+    /// and if the response is still shorter than `min_len` the bytes are written again, up to `retries` more times.
+    /// The last response is returned either way, so the caller still decides if it's good.
+    ///
+    /// Re-reading catches a board that's slow to answer, writing again catches a command or a response that was
+    /// lost or cut short on a noisy bus. This is for discovery and connection checks, don't use it for commands
+    /// that shouldn't be sent twice.
+    fn write_with_retries(
+        &mut self,
+        bytes: Vec<u8>,
+        min_len: usize,
+        retries: u8,
+    ) -> Result<Vec<u8>> {
+        let configured = self.empty_read_retries();
+        self.set_empty_read_retries(configured.max(retries));
+
+        let mut resp = self.write_to_device(bytes.clone());
+        for attempt in 1..=retries {
+            match &resp {
+                Ok(resp) if resp.len() >= min_len => break,
+                _ => {}
+            }
+            log::trace!(
+                "[Serial addr: {}] short response {:02X?}, writing again ({} of {})",
+                self.address(),
+                resp,
+                attempt,
+                retries
+            );
+            resp = self.write_to_device(bytes.clone());
+        }
+
+        self.set_empty_read_retries(configured);
+        resp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controllers::Controller;
+
+    fn describe(instr: &dyn Instrument) -> String {
+        format!("{} @ {}", instr.address(), instr.port_path())
+    }
+
+    fn assert_instrument<T: Instrument>() {}
+
+    #[test]
+    fn test_both_drivers_are_instruments() {
+        assert_instrument::<ModbusInstrument>();
+        assert_instrument::<SerialInstrument>();
+    }
+
+    #[tokio::test]
+    async fn test_dyn_instrument() {
+        if let Some(dev) = crate::tests::try_test_device_from_type(Controller::STR1) {
+            let c = dev.conn;
+            let instr =
+                SerialInstrument::new(c.controller_addr(), &c.port(), *c.baudrate(), c.timeout())
+                    .unwrap();
+            let expected = format!("{} @ {}", c.controller_addr(), c.port());
+            assert_eq!(describe(&instr), expected);
+        }
+
+        if let Some(dev) = crate::tests::try_test_device_from_type(Controller::CN7500) {
+            let c = dev.conn;
+            let instr = ModbusInstrument::new(
+                c.controller_addr(),
+                &c.port(),
+                *c.baudrate() as u64,
+                c.timeout(),
+            )
+            .await
+            .unwrap();
+            let expected = format!("{} @ {}", c.controller_addr(), c.port());
+            assert_eq!(describe(&instr), expected);
+        }
+    }
+}
@@ -6,17 +6,19 @@
 //! This module uses the `tokio v0.2`, and `tokio v1.0` likely won't work.
 
 // std uses
+use std::path::Path;
 
 // external uses
+use async_trait::async_trait;
 use derivative::Derivative;
 use log::{error, trace};
 use tokio::time::{self, Duration};
 use tokio_modbus::{
-    client::{rtu, Context, Reader, Writer},
-    prelude::Slave,
+    client::{rtu, Client, Context, Reader, Writer},
+    prelude::{Request, Response, Slave},
 };
 
-use crate::drivers::{InstrumentError, Result};
+use crate::drivers::{Instrument, InstrumentError, ModbusDriver, Result, SerialInstrument};
 
 /// A generic async Modbus instrument.
 ///
@@ -50,11 +52,13 @@ impl ModbusInstrument {
         let port = match tokio_serial::SerialStream::open(&builder) {
             Ok(port) => port,
             Err(serial_err) => {
-                error!("Error when connecting to Modbus Instrument. There is likely no port location at `{}`", port_path);
+                error!(
+                    "Error when connecting to Modbus Instrument on port `{}`",
+                    port_path
+                );
                 error!("Serial Error: {}", serial_err);
-                return Err(InstrumentError::serialError(
-                    format!("serial error: {}", serial_err),
-                    Some(slave_addr),
+                return Err(SerialInstrument::port_open_error(
+                    port_path, slave_addr, serial_err,
                 ));
             }
         };
@@ -72,7 +76,25 @@ impl ModbusInstrument {
         })
     }
 
+    // Called when a request times out. If the port file is still there the device is just silent
+    // and the request can be retried, otherwise the port is gone and we'll need to reconnect.
+    fn timeout_error(port_path: &str, slave_addr: u8, register: u16) -> InstrumentError {
+        if Path::new(port_path).exists() {
+            InstrumentError::modbusTimeoutError(port_path, slave_addr, register)
+        } else {
+            error!(
+                "Port `{}` disappeared while talking to Modbus device at addr {}",
+                port_path, slave_addr
+            );
+            InstrumentError::connectionLost(port_path, slave_addr)
+        }
+    }
+
     /// Asyncronously reads a number of registers.
+    ///
+    /// On a timeout, this returns [`InstrumentError::ModbusTimeoutError`](crate::drivers::InstrumentError::ModbusTimeoutError) if
+    /// the device was silent, or [`InstrumentError::ConnectionLost`](crate::drivers::InstrumentError::ConnectionLost) if the port
+    /// is gone. The same goes for the other read/write methods.
     pub async fn read_registers(&mut self, register: u16, count: u16) -> Result<Vec<u16>> {
         let task = self.ctx.read_holding_registers(register, count);
 
@@ -81,7 +103,7 @@ impl ModbusInstrument {
         match timeout.await {
             Ok(res) => return res.map_err(|err| InstrumentError::IOError(err)),
             Err(_) => {
-                return Err(InstrumentError::modbusTimeoutError(
+                return Err(ModbusInstrument::timeout_error(
                     &self.port_path,
                     self.slave_addr,
                     register,
@@ -99,7 +121,7 @@ impl ModbusInstrument {
         match timeout.await {
             Ok(resp) => return resp.map_err(|ioerror| InstrumentError::IOError(ioerror)),
             Err(_) => {
-                return Err(InstrumentError::modbusTimeoutError(
+                return Err(ModbusInstrument::timeout_error(
                     &self.port_path,
                     self.slave_addr,
                     register,
@@ -117,7 +139,7 @@ impl ModbusInstrument {
         match timeout.await {
             Ok(resp) => return resp.map_err(|ioerror| InstrumentError::IOError(ioerror)),
             Err(_) => {
-                return Err(InstrumentError::modbusTimeoutError(
+                return Err(ModbusInstrument::timeout_error(
                     &self.port_path,
                     self.slave_addr,
                     coil,
@@ -135,7 +157,7 @@ impl ModbusInstrument {
         match timeout.await {
             Ok(resp) => return resp.map_err(|ioerror| InstrumentError::IOError(ioerror)),
             Err(_) => {
-                return Err(InstrumentError::modbusTimeoutError(
+                return Err(ModbusInstrument::timeout_error(
                     &self.port_path,
                     self.slave_addr,
                     coil,
@@ -145,23 +167,123 @@ impl ModbusInstrument {
     }
 }
 
+#[async_trait]
+impl Instrument for ModbusInstrument {
+    fn address(&self) -> u8 {
+        self.slave_addr
+    }
+
+    fn port_path(&self) -> &str {
+        &self.port_path
+    }
+
+    async fn transact(&mut self, bytes: Vec<u8>) -> Result<Vec<u8>> {
+        let (fn_code, data) = match bytes.split_first() {
+            Some((fn_code, data)) => (*fn_code, data.to_vec()),
+            None => {
+                return Err(InstrumentError::modbusError(
+                    String::from("can't send an empty Modbus request"),
+                    Some(self.slave_addr),
+                ))
+            }
+        };
+
+        let task = self.ctx.call(Request::Custom(fn_code, data));
+
+        match time::timeout(self.timeout, task).await {
+            Ok(Ok(Response::Custom(fn_code, data))) => {
+                let mut resp = vec![fn_code];
+                resp.extend(data);
+                Ok(resp)
+            }
+            Ok(Ok(other)) => Err(InstrumentError::modbusError(
+                format!("unexpected Modbus response: {:?}", other),
+                Some(self.slave_addr),
+            )),
+            Ok(Err(ioerror)) => Err(InstrumentError::IOError(ioerror)),
+            Err(_) => Err(ModbusInstrument::timeout_error(
+                &self.port_path,
+                self.slave_addr,
+                0,
+            )),
+        }
+    }
+}
+
+#[async_trait]
+impl ModbusDriver for ModbusInstrument {
+    async fn read_registers(&mut self, register: u16, count: u16) -> Result<Vec<u16>> {
+        ModbusInstrument::read_registers(self, register, count).await
+    }
+
+    async fn write_register(&mut self, register: u16, value: u16) -> Result<()> {
+        ModbusInstrument::write_register(self, register, value).await
+    }
+
+    async fn read_coils(&mut self, coil: u16, count: u16) -> Result<Vec<bool>> {
+        ModbusInstrument::read_coils(self, coil, count).await
+    }
+
+    async fn write_coil(&mut self, coil: u16, value: bool) -> Result<()> {
+        ModbusInstrument::write_coil(self, coil, value).await
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::controllers::Controller;
 
     use tokio::test;
 
-    async fn instr() -> ModbusInstrument {
+    #[test]
+    async fn test_timeout_error_device_silent() {
+        // The port still exists, so the device just didn't answer
+        let err = ModbusInstrument::timeout_error("/dev/null", 0x16, 0x1001);
+        assert!(matches!(
+            err,
+            InstrumentError::ModbusTimeoutError {
+                addr: 0x16,
+                register: 0x1001,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    async fn test_timeout_error_port_gone() {
+        let err = ModbusInstrument::timeout_error("/dev/ttyUSB-unplugged", 0x16, 0x1001);
+        match err {
+            InstrumentError::ConnectionLost { port, addr } => {
+                assert_eq!(port, "/dev/ttyUSB-unplugged");
+                assert_eq!(addr, 0x16);
+            }
+            other => panic!("expected ConnectionLost, got {:?}", other),
+        }
+    }
+
+    // These tests read and write CN7500 registers, so they need one connected
+    async fn instr() -> Option<ModbusInstrument> {
+        let device = crate::tests::try_test_device_from_type(Controller::CN7500)?;
+        let c = device.conn;
         // We use a high timeout here because performance in tests doesn't matter too much
-        ModbusInstrument::new(0x16, "/dev/ttyUSB0", 19200, Duration::from_millis(100))
-            .await
-            .unwrap()
+        let instr = ModbusInstrument::new(
+            c.controller_addr(),
+            &c.port(),
+            *c.baudrate() as u64,
+            Duration::from_millis(100),
+        )
+        .await
+        .unwrap();
+        Some(instr)
     }
 
     #[test]
 
     async fn test_read_write_coil() {
-        let mut instr = instr().await;
+        let Some(mut instr) = instr().await else {
+            return;
+        };
         let rsp1 = instr.write_coil(0x0814, true).await;
         assert!(rsp1.is_ok());
         let value1 = instr.read_coils(0x0814, 1).await;
@@ -178,7 +300,9 @@ mod tests {
     #[test]
 
     async fn test_read_write_register() {
-        let mut instr = instr().await;
+        let Some(mut instr) = instr().await else {
+            return;
+        };
         // Set SV in register 0x1001 to 1400
         let rsp = instr.write_register(0x1001, 1400).await;
         assert!(rsp.is_ok());
@@ -0,0 +1,212 @@
+//! Finding the controllers on a serial bus
+//!
+//! When setting up a new RTU, you don't always know which addresses the boards were programmed with.
+//! [`scan_bus`](crate::drivers::scan_bus) asks every address on a port and returns the ones that answer.
+//! It's meant to be run once while commissioning, not while the RTU is running.
+use std::time::Duration;
+
+use log::trace;
+
+use crate::controllers::{Controller, Waveshare, WaveshareV2, STR1};
+use crate::drivers::{InstrumentError, Result, SerialDriver, SerialInstrument};
+
+/// Asks every address from 1 to 255 on `port` if there's a `controller` there, and returns the ones that respond.
+///
+/// The probe is the same one the controller's `connected()` uses: the relay count for an STR1 and the software
+/// revision for a Waveshare. Every address that doesn't answer waits out the whole `timeout`, so a scan takes
+/// around `255 * timeout`. Only serial relay boards can be scanned for, anything else returns an error.
+///
+/// ```rust,no_run
+/// # use std::time::Duration;
+/// # use brewdrivers::controllers::Controller;
+/// let found = brewdrivers::drivers::scan_bus("/dev/ttyUSB0", 9600, Duration::from_millis(40), Controller::STR1).unwrap();
+/// println!("STR1 boards at {:?}", found);
+/// ```
+pub fn scan_bus(
+    port: &str,
+    baudrate: usize,
+    timeout: Duration,
+    controller: Controller,
+) -> Result<Vec<u8>> {
+    scan_bus_with_retries(port, baudrate, timeout, controller, 0)
+}
+
+/// The same as [`scan_bus`](crate::drivers::scan_bus), but an address that doesn't answer is read again up to
+/// `probe_retries` times before it's skipped. A Waveshare probe that still isn't answered in full is also
+/// sent again, see [`SerialDriver::write_with_retries`](crate::drivers::SerialDriver::write_with_retries).
+/// This is for noisy buses, and makes the scan take a lot longer.
+pub fn scan_bus_with_retries(
+    port: &str,
+    baudrate: usize,
+    timeout: Duration,
+    controller: Controller,
+    probe_retries: u8,
+) -> Result<Vec<u8>> {
+    let instr =
+        SerialInstrument::with_empty_read_retries(1, port, baudrate, timeout, probe_retries)?;
+    scan(instr, controller, probe_retries)
+}
+
+fn scan<I: SerialDriver>(instr: I, controller: Controller, probe_retries: u8) -> Result<Vec<u8>> {
+    let addresses = 1..=u8::MAX;
+    let found: Vec<u8> = match controller {
+        Controller::STR1 => {
+            let mut board = STR1::from_instrument(instr);
+            addresses.filter(|&addr| board.probe_at(addr)).collect()
+        }
+        Controller::Waveshare => {
+            let mut board = Waveshare::from_instrument(instr).with_probe_retries(probe_retries);
+            addresses.filter(|&addr| board.probe_at(addr)).collect()
+        }
+        Controller::WaveshareV2 => {
+            let mut board = WaveshareV2::from_instrument(instr).with_probe_retries(probe_retries);
+            addresses.filter(|&addr| board.probe_at(addr)).collect()
+        }
+        Controller::CN7500 | Controller::AnalogOutput => {
+            return Err(InstrumentError::serialError(
+                format!(
+                    "can't scan for `{controller}` controllers, only STR1 and Waveshare boards"
+                ),
+                None,
+            ))
+        }
+    };
+
+    trace!("scan for {controller} controllers found {:?}", found);
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drivers::mock::{MockSerialInstrument, MockWaveshareInstrument};
+    use crate::drivers::Instrument;
+    use async_trait::async_trait;
+
+    // Several mock boards on one port. Everything written goes to all of them, and whoever answers is the response.
+    struct MockBus<B>(Vec<B>);
+
+    #[async_trait]
+    impl<B: SerialDriver> Instrument for MockBus<B> {
+        fn address(&self) -> u8 {
+            self.0[0].address()
+        }
+
+        fn port_path(&self) -> &str {
+            self.0[0].port_path()
+        }
+
+        async fn transact(&mut self, bytes: Vec<u8>) -> Result<Vec<u8>> {
+            self.write_to_device(bytes)
+        }
+    }
+
+    impl<B: SerialDriver> SerialDriver for MockBus<B> {
+        fn write_to_device(&mut self, bytes: Vec<u8>) -> Result<Vec<u8>> {
+            let mut resp = vec![];
+            for board in &mut self.0 {
+                let board_resp = board.write_to_device(bytes.clone())?;
+                if !board_resp.is_empty() {
+                    resp = board_resp;
+                }
+            }
+            Ok(resp)
+        }
+
+        fn set_address(&mut self, new_addr: u8) {
+            self.0
+                .iter_mut()
+                .for_each(|board| board.set_address(new_addr));
+        }
+
+        fn reopen_port(&mut self, new_baudrate: usize) -> Result<()> {
+            self.0
+                .iter_mut()
+                .try_for_each(|board| board.reopen_port(new_baudrate))
+        }
+
+        fn empty_read_retries(&self) -> u8 {
+            self.0[0].empty_read_retries()
+        }
+
+        fn set_empty_read_retries(&mut self, retries: u8) {
+            self.0
+                .iter_mut()
+                .for_each(|board| board.set_empty_read_retries(retries));
+        }
+    }
+
+    #[test]
+    fn test_scan_str1_bus() {
+        let bus = MockBus(vec![
+            MockSerialInstrument::new(0x02, 8),
+            MockSerialInstrument::new(0xFE, 16),
+        ]);
+        assert_eq!(scan(bus, Controller::STR1, 0).unwrap(), vec![0x02, 0xFE]);
+    }
+
+    #[test]
+    fn test_scan_waveshare_bus() {
+        let bus = MockBus(vec![
+            MockWaveshareInstrument::new(0x01, 8),
+            MockWaveshareInstrument::new(0x05, 8),
+        ]);
+        assert_eq!(
+            scan(bus, Controller::Waveshare, 0).unwrap(),
+            vec![0x01, 0x05]
+        );
+
+        // STR1 probes don't look like anything to a Waveshare
+        let bus = MockBus(vec![MockWaveshareInstrument::new(0x01, 8)]);
+        assert!(scan(bus, Controller::STR1, 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_scan_retries_late_boards() {
+        let late_bus = || {
+            let mut late = MockWaveshareInstrument::new(0x05, 8);
+            late.delay_next_responses(1);
+            MockBus(vec![MockWaveshareInstrument::new(0x01, 8), late])
+        };
+        assert_eq!(
+            scan(late_bus(), Controller::Waveshare, 0).unwrap(),
+            vec![0x01]
+        );
+
+        let mut bus = late_bus();
+        bus.set_empty_read_retries(1);
+        assert_eq!(
+            scan(bus, Controller::Waveshare, 0).unwrap(),
+            vec![0x01, 0x05]
+        );
+
+        // A board that missed the probe is asked again
+        let deaf_bus = || {
+            let mut deaf = MockWaveshareInstrument::new(0x05, 8);
+            deaf.ignore_next_messages(1);
+            MockBus(vec![MockWaveshareInstrument::new(0x01, 8), deaf])
+        };
+        assert_eq!(
+            scan(deaf_bus(), Controller::Waveshare, 0).unwrap(),
+            vec![0x01]
+        );
+        let bus = deaf_bus();
+        assert_eq!(
+            scan(bus, Controller::Waveshare, 1).unwrap(),
+            vec![0x01, 0x05]
+        );
+    }
+
+    #[test]
+    fn test_scan_unsupported_controller() {
+        let bus = MockBus(vec![MockSerialInstrument::new(0x02, 8)]);
+        assert!(scan(bus, Controller::CN7500, 0).is_err());
+        assert!(scan_bus(
+            "/dev/doesntexist0",
+            9600,
+            Duration::from_millis(20),
+            Controller::STR1
+        )
+        .is_err());
+    }
+}
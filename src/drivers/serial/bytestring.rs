@@ -29,7 +29,9 @@
 //! MAE = 0x77
 //! ```
 //!
-//! This module keeps these as constants and they cannot be changed at this time.
+//! If a board has been programmed with other master bytes, build its bytestrings with
+//! [`Bytestring::with_markers`](crate::drivers::serial::Bytestring::with_markers), or set `master_bytes`
+//! in the device's connection config (see [`MasterBytes`](crate::drivers::serial::bytestring::MasterBytes)).
 //!
 //! The command (`CC`) depends on the command you want to send. A full list can be seen in the
 //! [software manual](https://www.smarthardware.eu/manual/str1xxxxxx_com.pdf).
@@ -57,6 +59,18 @@
 //! // This command gets the status of relay 0x00 on controller 0x01
 //! let bs = Bytestring::from(vec![0x07, 0x14, 0x01, 0x00, 0x01]);
 //! ```
+//!
+//! Or use [`Bytestring::with_command`](crate::drivers::serial::Bytestring::with_command) and the `BC` is calculated for you.
+//!
+//! ```rust
+//! use brewdrivers::drivers::serial::Bytestring;
+//!
+//! // The same command as above
+//! let bs = Bytestring::with_command(0x14, 0x01, vec![0x00, 0x01]);
+//! assert_eq!(bs.data, vec![0x07, 0x14, 0x01, 0x00, 0x01]);
+//! ```
+
+use serde::{Deserialize, Serialize};
 
 // Master start bytes
 const MA0: u8 = 0x55;
@@ -64,12 +78,38 @@ const MA1: u8 = 0xAA;
 // Master end byte
 const MAE: u8 = 0x77;
 
+// The bytes counted by BC that aren't data bytes: BC, CC, CN, CS, and MAE
+const BC_OVERHEAD: u8 = 5;
+
+/// The "master" bytes that wrap every message to the board. These are programmed into the board, and
+/// default to `MA0 = 0x55`, `MA1 = 0xAA`, and `MAE = 0x77`.
+///
+/// In a device's connection config, they're written like `master_bytes: { ma0: 0x55, ma1: 0xAA, mae: 0x77 }`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct MasterBytes {
+    /// Master start 0
+    pub ma0: u8,
+    /// Master start 1
+    pub ma1: u8,
+    /// Master end
+    pub mae: u8,
+}
+
+impl Default for MasterBytes {
+    fn default() -> Self {
+        MasterBytes { ma0: MA0, ma1: MA1, mae: MAE }
+    }
+}
+
 
 /// The [`Bytestring`](crate::drivers::serial::Bytestring) struct, representing a message to the STR1XX board.
 #[derive(Debug)]
 pub struct Bytestring {
     /// The bytes of a datastring, excluding the `MA0`, `MA1`, `CS`, and `MAE`
     pub data: Vec<u8>,
+    /// The master bytes the message is wrapped in, the defaults unless the board has been reprogrammed
+    pub master: MasterBytes,
 }
 
 impl Bytestring {
@@ -85,11 +125,47 @@ impl Bytestring {
     /// assert_eq!(bs.data[1], 0x14);
     /// ```
     pub fn from(bytes: Vec<u8>) -> Bytestring {
+        Bytestring::with_markers(bytes, MasterBytes::default())
+    }
+
+    /// Returns a new Bytestring like [`Bytestring::from`](crate::drivers::serial::Bytestring::from), for a board
+    /// that's been programmed with other master bytes.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use brewdrivers::drivers::serial::{bytestring::MasterBytes, Bytestring};
+    ///
+    /// let master = MasterBytes { ma0: 0x5A, ma1: 0xA5, mae: 0x7E };
+    /// let bs = Bytestring::with_markers(vec![0xF3, 0xF3], master);
+    /// assert_eq!(bs.to_bytes(), vec![0x5A, 0xA5, 0xF3, 0xF3, 0xE6, 0x7E]);
+    /// ```
+    pub fn with_markers(bytes: Vec<u8>, master: MasterBytes) -> Bytestring {
         Bytestring {
-            data: bytes
+            data: bytes,
+            master,
         }
     }
 
+    /// Returns a new Bytestring from a command (`CC`), controller number (`CN`), and data bytes. The
+    /// byte count (`BC`) is calculated and put at the front for you.
+    ///
+    /// `BC` is the number of bytes from itself to the end of the message, so it counts `BC`, `CC`, `CN`,
+    /// the data bytes, `CS`, and `MAE`.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use brewdrivers::drivers::serial::Bytestring;
+    ///
+    /// // Gets the amount of relays on controller 0xFE, this command has no data bytes
+    /// let bs = Bytestring::with_command(0x02, 0xFE, vec![]);
+    /// assert_eq!(bs.data, vec![0x05, 0x02, 0xFE]);
+    /// ```
+    pub fn with_command(cc: u8, cn: u8, data: Vec<u8>) -> Bytestring {
+        let mut bytes: Vec<u8> = vec![BC_OVERHEAD + data.len() as u8, cc, cn];
+        bytes.extend(data);
+        Bytestring::from(bytes)
+    }
+
 
     /// Returns the checksum of the bytestring, as a single byte. The checksum
     /// is the sum of all the bytes, excluding the `MA0`, `MA1` and `MAE` bytes.
@@ -126,7 +202,7 @@ impl Bytestring {
     /// ```
     pub fn full(&self) -> String {
         let data_strings = self.data.iter().map(|&val| format!("{:0>2}", format!("{:x}", val)) ).collect::<Vec<String>>();
-        format!("{:0>2x}{:0>2x}{}{:0>2x}{:0>2x}", MA0, MA1, data_strings.join(""), self.checksum_as_hex(), MAE)
+        format!("{:0>2x}{:0>2x}{}{:0>2x}{:0>2x}", self.master.ma0, self.master.ma1, data_strings.join(""), self.checksum_as_hex(), self.master.mae)
     }
 
     /// Consumes the Bytestring, returning the full bytestring
@@ -139,14 +215,14 @@ impl Bytestring {
     /// assert_eq!(bs.to_bytes(), vec![0x55, 0xAA, 0xF3, 0xF3, 0xE6, 0x77]);
     /// ```
     pub fn to_bytes(self) -> Vec<u8> {
-        let mut bytes: Vec<u8> = vec![MA0, MA1];
+        let mut bytes: Vec<u8> = vec![self.master.ma0, self.master.ma1];
  
         for byte in &self.data {
             bytes.push(*byte);
         }
         
         bytes.push(self.checksum_as_hex());
-        bytes.push(MAE);
+        bytes.push(self.master.mae);
         return bytes;
     }
 }
@@ -179,6 +255,45 @@ mod tests {
         assert_eq!("55aa0077", Bytestring::from(vec![]).full());
     }
 
+    #[test]
+    fn with_command_byte_count() {
+        // Get relay 0x00 status on controller 0xFE, from the software manual
+        assert_eq!(
+            Bytestring::with_command(0x14, 0xFE, vec![0x00, 0x01]).to_bytes(),
+            vec![0x55, 0xAA, 0x07, 0x14, 0xFE, 0x00, 0x01, 0x1A, 0x77]
+        );
+
+        // Set relay 0x00 on, controller 0xFE
+        assert_eq!(
+            Bytestring::with_command(0x17, 0xFE, vec![0x00, 0x01, 0x01]).to_bytes(),
+            vec![0x55, 0xAA, 0x08, 0x17, 0xFE, 0x00, 0x01, 0x01, 0x1F, 0x77]
+        );
+
+        // Matches what callers used to build by hand
+        assert_eq!(
+            Bytestring::with_command(0x02, 0xFE, vec![]).data,
+            Bytestring::from(vec![0x05, 0x02, 0xFE]).data
+        );
+    }
+
+    #[test]
+    fn custom_master_bytes() {
+        let master = MasterBytes { ma0: 0x5A, ma1: 0xA5, mae: 0x7E };
+        assert_eq!("5aa5feff0103017e", Bytestring::with_markers(vec![254, 255, 1, 3], master).full());
+        assert_eq!("5aa5007e", Bytestring::with_markers(vec![], master).full());
+        assert_eq!(
+            Bytestring::with_markers(vec![0x07, 0x14, 0xFE, 0x00, 0x01], master).to_bytes(),
+            vec![0x5A, 0xA5, 0x07, 0x14, 0xFE, 0x00, 0x01, 0x1A, 0x7E]
+        );
+
+        // The defaults are the standard bytes
+        assert_eq!(Bytestring::from(vec![1]).master, MasterBytes::default());
+        assert_eq!(
+            Bytestring::with_markers(vec![1], MasterBytes::default()).to_bytes(),
+            Bytestring::from(vec![1]).to_bytes()
+        );
+    }
+
     #[test]
     fn checksum_as_hex() {
         let bs = Bytestring::from(vec![5, 5, 10]);
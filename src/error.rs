@@ -0,0 +1,93 @@
+//! A crate-level error that every subsystem error converts into.
+//!
+//! Each layer has its own error type ([`InstrumentError`](crate::drivers::InstrumentError) for drivers and controllers,
+//! [`ModelError`](crate::model::ModelError) for the model, [`StateError`](crate::state::StateError) for state). Those are
+//! still what the layers return, so you can match on them for granular handling. If you just want `?` to work across
+//! layers, return [`brewdrivers::Result`](crate::Result) and the `From` impls here do the conversion.
+//!
+//! ```rust,no_run
+//! use brewdrivers::model::RTU;
+//!
+//! async fn snapshot() -> brewdrivers::Result<RTU> {
+//!     // ModelError
+//!     let mut rtu = RTU::generate(None)?;
+//!     // InstrumentError
+//!     rtu.update().await?;
+//!     Ok(rtu)
+//! }
+//! ```
+use thiserror::Error;
+
+use crate::controllers::ControllerError;
+use crate::drivers::InstrumentError;
+use crate::model::ModelError;
+use crate::state::StateError;
+
+/// Any error this crate can return
+#[derive(Error, Debug)]
+pub enum Error {
+    /// An error talking to a controller
+    #[error(transparent)]
+    Instrument(#[from] InstrumentError),
+    /// An error in the RTU model or configuration
+    #[error(transparent)]
+    Model(#[from] ModelError),
+    /// A bad state value
+    #[error(transparent)]
+    State(#[from] StateError),
+    /// A bad controller name
+    #[error(transparent)]
+    Controller(#[from] ControllerError),
+    /// An error publishing to the MQTT broker
+    #[cfg(feature = "mqtt")]
+    #[error(transparent)]
+    Mqtt(#[from] crate::model::mqtt::MqttError),
+}
+
+/// A `Result` with the crate-level [`Error`](crate::Error)
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instrument() -> Result<()> {
+        Err(InstrumentError::serialError(
+            String::from("no response"),
+            Some(254),
+        ))?;
+        Ok(())
+    }
+
+    fn model() -> Result<()> {
+        Err(ModelError::DeviceNotFound(String::from("pump")))?;
+        Ok(())
+    }
+
+    fn state() -> Result<()> {
+        Err(StateError::Deserialize(String::from("Onn")))?;
+        Ok(())
+    }
+
+    fn controller() -> Result<()> {
+        "Foo".parse::<crate::controllers::Controller>()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_subsystem_errors_convert() {
+        assert!(matches!(instrument(), Err(Error::Instrument(_))));
+        assert!(matches!(model(), Err(Error::Model(_))));
+        assert!(matches!(state(), Err(Error::State(_))));
+        assert!(matches!(controller(), Err(Error::Controller(_))));
+    }
+
+    #[test]
+    fn test_error_messages_pass_through() {
+        let err = model().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            ModelError::DeviceNotFound(String::from("pump")).to_string()
+        );
+    }
+}
@@ -27,24 +27,62 @@
 pub mod controllers;
 pub mod defaults;
 pub mod drivers;
+pub mod error;
 pub mod logging_utils;
 pub mod model;
 pub mod state;
 
+pub use error::{Error, Result};
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use std::path::Path;
+
+    /// Returns `true` if the testing configuration file exists. If it doesn't, there's no
+    /// hardware to test against, and hardware tests should skip themselves.
+    pub fn test_config_available() -> bool {
+        if Path::new(crate::defaults::test_config_file()).exists() {
+            return true;
+        }
+        eprintln!(
+            "skipping hardware test: no test configuration at {}",
+            crate::defaults::test_config_file()
+        );
+        false
+    }
+
     /// This is a special little function that will deserialize the test RTU configuration
     /// and return the device details of a given type of controller.
+    ///
+    /// Returns `None` (and prints a skip notice) if there's no test configuration, no device of that
+    /// type in it, or the device's port doesn't exist. Hardware tests should return early on `None`,
+    /// that way the test suite can run on machines without the hardware.
     /// This is just used in tests
-    pub fn test_device_from_type(con_type: controllers::Controller) -> model::Device {
+    pub fn try_test_device_from_type(con_type: controllers::Controller) -> Option<model::Device> {
+        if !test_config_available() {
+            return None;
+        }
+
         let rtu = crate::model::RTU::generate(Some(crate::defaults::test_config_file()))
             .expect("Couldn't read config file into RTU model");
-        rtu.devices
-            .iter()
-            .find(|dev| dev.conn.controller == con_type)
-            .unwrap()
-            .clone()
+        let device = match rtu.devices_by_controller(con_type).first() {
+            Some(&device) => device.clone(),
+            None => {
+                eprintln!("skipping hardware test: no {con_type} device in the test configuration");
+                return None;
+            }
+        };
+
+        if !device.conn.port.exists() {
+            eprintln!(
+                "skipping hardware test: port {} for the {con_type} device doesn't exist",
+                device.conn.port()
+            );
+            return None;
+        }
+
+        Some(device)
     }
 }
@@ -4,9 +4,43 @@
 //!
 //! The state printed will be the current state stored on the Device struct, so be sure to
 //! update the device first if you want accurate logging
+//!
+//! If you don't have a logger set up already, [`init_default_logging`](crate::logging_utils::init_default_logging)
+//! will configure a basic one for you.
+//!
+//! For logs that get parsed by a machine, use [`device_event!`](crate::logging_utils::device_event). With the
+//! `json-logs` feature enabled, it logs each event as one line of JSON.
+use log::LevelFilter;
+use serde::Serialize;
+
 use crate::controllers::Controller;
 use crate::model::Device;
 
+/// Configures a basic [`env_logger`](https://docs.rs/env_logger) logger with timestamps, logging at `level`.
+///
+/// If the `RUST_LOG` environment variable is set, it takes precedence over `level`. This is entirely optional;
+/// if you bring your own logger, don't call this. Calling it more than once does nothing after the first call.
+///
+/// ```rust
+/// use brewdrivers::logging_utils::init_default_logging;
+/// use log::LevelFilter;
+///
+/// init_default_logging(LevelFilter::Info);
+/// log::info!("Now you can see me");
+/// ```
+pub fn init_default_logging(level: LevelFilter) {
+    let result = env_logger::Builder::new()
+        .filter_level(level)
+        .parse_env("RUST_LOG")
+        .format_timestamp_millis()
+        .try_init();
+
+    // This only fails if a logger is already set, which is fine
+    if result.is_err() {
+        log::trace!("init_default_logging() called, but a logger is already configured");
+    }
+}
+
 /// Creates a string prefix to add to the log message containing the device id and states.
 ///
 /// The `brewdrivers::logging_utils` overloads the default log::* macros and adds this prefix to them.
@@ -26,10 +60,73 @@ pub fn format_log_prefix(device: &Device) -> String {
         Controller::Waveshare | Controller::WaveshareV2 | Controller::STR1 => {
             states_string.push_str(&format!("relay_state: {:?}", device.state.relay_state))
         }
+        Controller::AnalogOutput => {
+            states_string.push_str(&format!("output_pct: {:?}", device.state.output_pct))
+        }
     }
     return format!("[`{}` -> {}]", device.id, states_string);
 }
 
+/// One device event, as logged by [`device_event!`](crate::logging_utils::device_event)
+#[derive(Debug, Serialize)]
+pub struct DeviceEvent<'a> {
+    pub device_id: &'a str,
+    pub controller: Controller,
+    /// What the device was doing, like `update` or `enact`
+    pub action: &'a str,
+    pub message: &'a str,
+}
+
+/// Formats a device event for [`device_event!`](crate::logging_utils::device_event).
+///
+/// With the `json-logs` feature this is a JSON object with the fields of [`DeviceEvent`](crate::logging_utils::DeviceEvent),
+/// all on one line. Without it, this is the same text the other `device_*` macros log:
+/// ```text
+/// [`pump` -> relay_state: Some(On)] update: failed, retrying
+/// ```
+pub fn format_device_event(device: &Device, action: &str, message: &str) -> String {
+    #[cfg(feature = "json-logs")]
+    {
+        let event = DeviceEvent {
+            device_id: &device.id,
+            controller: device.conn.controller,
+            action,
+            message,
+        };
+        // This only has strings and a unit enum, so it can't fail
+        serde_json::to_string(&event).unwrap()
+    }
+
+    #[cfg(not(feature = "json-logs"))]
+    {
+        format!("{} {}: {}", format_log_prefix(device), action, message)
+    }
+}
+
+/// Logs a device event with an action, for logs that get parsed by a machine. The level defaults to info.
+///
+/// ```rust
+/// # use brewdrivers::logging_utils::device_event;
+/// # let device: brewdrivers::model::Device = serde_yaml::from_str(
+/// #     "{ id: pump, name: Pump, conn: { port: /dev/ttyUSB0, baudrate: 9600, timeout: 100, controller: STR1, controller_addr: 254 } }"
+/// # ).unwrap();
+/// device_event!(device, "enact", "relay turned on");
+/// device_event!(log::Level::Warn, device, "update", "no response, retrying");
+/// ```
+#[macro_export]
+macro_rules! device_event {
+    ($device:expr, $action:expr, $msg:expr) => {
+        device_event!(log::Level::Info, $device, $action, $msg)
+    };
+    ($level:expr, $device:expr, $action:expr, $msg:expr) => {
+        log::log!(
+            $level,
+            "{}",
+            $crate::logging_utils::format_device_event(&$device, $action, &$msg)
+        );
+    };
+}
+
 #[macro_export]
 macro_rules! device_trace {
     ($device:expr) => {
@@ -104,7 +201,55 @@ macro_rules! device_error {
 // except warn, which conflicts with the #[warn] builtin
 pub use device_debug;
 pub use device_error;
+pub use device_event;
 pub use device_info;
 pub use device_trace;
 pub use device_warn;
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pump() -> Device {
+        serde_yaml::from_str(
+            r#"
+            id: pump
+            name: Pump
+            conn: { port: /dev/ttyUSB0, baudrate: 9600, timeout: 100, controller: STR1, controller_addr: 254 }
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[cfg(not(feature = "json-logs"))]
+    #[test]
+    fn test_device_event_text() {
+        let line = format_device_event(&pump(), "update", "failed, retrying");
+        assert_eq!(
+            line,
+            "[`pump` -> relay_state: None] update: failed, retrying"
+        );
+    }
+
+    #[cfg(feature = "json-logs")]
+    #[test]
+    fn test_device_event_json() {
+        let line = format_device_event(&pump(), "update", "failed, \"retrying\"");
+        assert!(!line.contains('\n'));
+
+        let event: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(event["device_id"], "pump");
+        assert_eq!(event["controller"], "STR1");
+        assert_eq!(event["action"], "update");
+        assert_eq!(event["message"], "failed, \"retrying\"");
+    }
+
+    #[test]
+    fn test_init_default_logging_twice() {
+        // Keep the level low, this logger is global and other tests
+        // running alongside this one would flood the output
+        init_default_logging(LevelFilter::Error);
+        init_default_logging(LevelFilter::Off);
+        log::trace!("Logging still works");
+    }
+}
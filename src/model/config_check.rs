@@ -0,0 +1,224 @@
+//! Checks a configuration file and reports every problem in it, instead of stopping at the first one
+//!
+//! [`RTU::generate`](crate::model::RTU::generate) fails on the first bad key or value, which makes fixing a
+//! hand-edited config a slow loop. [`validate_config_file`](crate::model::validate_config_file) lists them all.
+use std::fmt;
+use std::fs;
+
+use super::{validators, ModelError, RTU};
+
+/// One problem in a configuration file, see [`validate_config_file`](crate::model::validate_config_file)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigIssue {
+    /// The key the problem is in, like `pump.baudrate` or `devices.0.colour`, if it's known
+    pub field: Option<String>,
+    /// The line in the file (starting at 1), if it's known
+    pub line: Option<usize>,
+    /// What's wrong
+    pub message: String,
+}
+
+impl ConfigIssue {
+    fn new(field: Option<String>, line: Option<usize>, message: String) -> Self {
+        Self {
+            field,
+            line,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {line}: ")?;
+        }
+        if let Some(field) = &self.field {
+            write!(f, "{field}: ")?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Reads a configuration file and checks it like [`RTU::generate`](crate::model::RTU::generate) does, but collects
+/// every problem it finds: unknown keys, and every validator that fails. If the file can't be deserialized at all
+/// (ie. a missing key or bad YAML), that's the only issue, since there's nothing to validate.
+///
+/// ```rust,no_run
+/// if let Err(issues) = brewdrivers::model::validate_config_file("/etc/NavasotaBrewing/rtu_conf.yaml") {
+///     for issue in issues {
+///         println!("{issue}");
+///     }
+/// }
+/// ```
+pub fn validate_config_file(path: &str) -> Result<(), Vec<ConfigIssue>> {
+    let contents = fs::read_to_string(path)
+        .map_err(|err| vec![ConfigIssue::new(None, None, err.to_string())])?;
+    let contents = RTU::interpolate_env(&contents, |var| std::env::var(var).ok())
+        .map_err(|err| vec![ConfigIssue::new(None, None, err.to_string())])?;
+
+    let (rtu, unknown_fields) =
+        RTU::from_yaml_with_unknown_fields(&contents).map_err(|err| vec![issue(&contents, err)])?;
+
+    let mut issues: Vec<ConfigIssue> = unknown_fields
+        .into_iter()
+        .map(|field| {
+            let key = field.rsplit('.').next().unwrap_or(&field).to_string();
+            let line = find_line(&contents, &key, None);
+            ConfigIssue::new(Some(field), line, format!("unknown key `{key}`"))
+        })
+        .collect();
+
+    issues.extend(
+        validators::all_validator_errors(&rtu)
+            .into_iter()
+            .map(|err| issue(&contents, err)),
+    );
+
+    match issues.is_empty() {
+        true => Ok(()),
+        false => Err(issues),
+    }
+}
+
+// Turns an error into an issue, finding where it is in the file if we can
+fn issue(contents: &str, err: ModelError) -> ConfigIssue {
+    match err {
+        ModelError::ValidationError {
+            item_id,
+            key,
+            value,
+            rule,
+        } => ConfigIssue::new(
+            Some(format!("{item_id}.{key}")),
+            find_line(contents, "id", Some(&item_id)),
+            format!("`{value}`: {rule}"),
+        ),
+        ModelError::SerdeParseError(err) => {
+            let line = err.location().map(|location| location.line());
+            ConfigIssue::new(None, line, err.to_string())
+        }
+        other => ConfigIssue::new(None, None, other.to_string()),
+    }
+}
+
+// The first line with `key:` on it, or `key: value` if a value is given
+fn find_line(contents: &str, key: &str, value: Option<&str>) -> Option<usize> {
+    contents
+        .lines()
+        .position(|line| {
+            let line = line.trim_start().trim_start_matches("- ");
+            let Some(rest) = line
+                .strip_prefix(key)
+                .and_then(|rest| rest.strip_prefix(':'))
+            else {
+                return false;
+            };
+            value.is_none_or(|value| rest.trim() == value)
+        })
+        .map(|i| i + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(name: &str, contents: &str) -> Result<(), Vec<ConfigIssue>> {
+        let path = std::env::temp_dir().join(format!("brewdrivers_test_{name}.yaml"));
+        fs::write(&path, contents).unwrap();
+        let result = validate_config_file(path.to_str().unwrap());
+        fs::remove_file(path).unwrap();
+        result
+    }
+
+    #[test]
+    fn test_validate_config_file_collects_issues() {
+        let issues = check(
+            "broken_conf",
+            r#"
+name: Test RTU
+id: test-rtu
+ip_addr: 0.0.0.0
+devices:
+  - id: pump
+    name: Pump
+    colour: red
+    conn:
+      port: /dev/ttyUSB0
+      baudrate: 1234
+      timeout: 100
+      controller: STR1
+      controller_addr: 254
+  - id: pump
+    name: Other pump
+    conn:
+      port: /dev/ttyUSB0
+      baudrate: 9600
+      timeout: 100
+      controller: STR1
+      controller_addr: 254
+"#,
+        )
+        .unwrap_err();
+
+        assert_eq!(issues.len(), 3, "{issues:?}");
+        assert!(issues
+            .iter()
+            .any(|issue| issue.message.contains("colour") && issue.line == Some(8)));
+        assert!(issues
+            .iter()
+            .any(|issue| issue.field.as_deref() == Some("pump.id")));
+        assert!(issues
+            .iter()
+            .any(|issue| issue.field.as_deref() == Some("pump.baudrate") && issue.line == Some(6)));
+    }
+
+    #[test]
+    fn test_validate_config_file_reports_every_device() {
+        let issues = check(
+            "two_bad_baudrates_conf",
+            r#"
+name: Test RTU
+id: test-rtu
+ip_addr: 0.0.0.0
+devices:
+  - id: pump
+    name: Pump
+    conn: { port: /dev/ttyUSB0, baudrate: 1234, timeout: 100, controller: STR1, controller_addr: 254 }
+  - id: valve
+    name: Valve
+    conn: { port: /dev/ttyUSB0, baudrate: 4321, timeout: 100, controller: STR1, controller_addr: 254, addr: 1 }
+"#,
+        )
+        .unwrap_err();
+
+        // One issue for each device, not just the first one the validator saw
+        let fields: Vec<Option<&str>> = issues.iter().map(|issue| issue.field.as_deref()).collect();
+        assert_eq!(
+            fields,
+            vec![Some("pump.baudrate"), Some("valve.baudrate")],
+            "{issues:?}"
+        );
+        assert_eq!(issues[1].line, Some(9));
+    }
+
+    #[test]
+    fn test_validate_config_file_parse_error() {
+        let issues = check(
+            "unparseable_conf",
+            "name: Test RTU\nid: test-rtu\nip_addr: not an ip\ndevices: []\n",
+        )
+        .unwrap_err();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].line, Some(3));
+    }
+
+    #[test]
+    fn test_validate_config_file_ok() {
+        assert!(check(
+            "good_conf",
+            "name: Test RTU\nid: test-rtu\nip_addr: 0.0.0.0\ndevices: []\n"
+        )
+        .is_ok());
+    }
+}
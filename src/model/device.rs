@@ -1,29 +1,95 @@
 //! This model is a high level abstraction of a device. It is serializable and meant to be
 //! sent through the network between web servers. It contains an implementation to talk with the hardware
 //! through the drivers also provided by this crate.
+use std::any::Any;
+use std::future::Future;
 use std::path::PathBuf;
+use std::sync::Mutex;
 use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 
+use crate::controllers::analog_output::AnalogOutputConfig;
+use crate::controllers::waveshare::WAVESHARE_DEFAULT_RELAY_COUNT;
 use crate::controllers::*;
-use crate::defaults::{default_command_retries, default_retry_delay};
+use crate::defaults::{default_command_retries, default_enabled, default_retry_delay};
+use crate::drivers::mock::{MockModbusInstrument, MockSerialInstrument, MockWaveshareInstrument};
+use crate::drivers::serial::bytestring::MasterBytes;
 use crate::drivers::InstrumentError;
-use crate::logging_utils::device_info;
+use crate::logging_utils::{device_event, device_info};
 use crate::model::SCADADevice;
-use crate::state::DeviceState;
+use crate::state::{DeviceState, StateError};
 
 type Result<T> = std::result::Result<T, InstrumentError>;
 
+/// How a timeout can be written in the config file: a plain number of milliseconds (`50`), or a number
+/// with a unit (`50ms`, `1s`)
+#[derive(Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(untagged)]
+enum TimeoutRepr {
+    Millis(u64),
+    WithUnit(String),
+}
+
+impl TimeoutRepr {
+    fn to_duration(&self) -> std::result::Result<Duration, String> {
+        let text = match self {
+            TimeoutRepr::Millis(ms) => return Ok(Duration::from_millis(*ms)),
+            TimeoutRepr::WithUnit(text) => text.trim(),
+        };
+
+        let split = text
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(text.len());
+        let (number, unit) = text.split_at(split);
+        let number: u64 = number.parse().map_err(|_| {
+            format!(
+                "`{text}` isn't a valid timeout, use milliseconds (`50`) or a unit (`50ms`, `1s`)"
+            )
+        })?;
+
+        match unit.trim() {
+            "" | "ms" => Ok(Duration::from_millis(number)),
+            "s" => Ok(Duration::from_secs(number)),
+            other => Err(format!(
+                "`{other}` isn't a timeout unit in `{text}`, use `ms` or `s`"
+            )),
+        }
+    }
+}
+
+// Timeouts are read as `TimeoutRepr`, and always written back out as plain milliseconds
+mod timeout_serde {
+    use super::TimeoutRepr;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(timeout: &Duration, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_u64(timeout.as_millis() as u64)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+        TimeoutRepr::deserialize(d)?
+            .to_duration()
+            .map_err(D::Error::custom)
+    }
+}
+
 /// Holds the connection details for a device
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Connection {
     /// The serial port the device runs on.
     ///
     /// This will probably be `/dev/ttyUSB0`
     pub port: PathBuf,
     pub baudrate: usize,
-    pub timeout: u64,
+    /// How long to wait for the controller to respond. Either a number of milliseconds, or a number
+    /// with a unit (`50ms`, `1s`).
+    #[serde(with = "timeout_serde")]
+    #[cfg_attr(feature = "schema", schemars(with = "TimeoutRepr"))]
+    pub timeout: Duration,
     /// The devices specific address (ie. relay number, etc.)
     ///
     /// If the device has no specific address within the controller, set to 0
@@ -33,6 +99,23 @@ pub struct Connection {
     pub controller_addr: u8,
     /// The type of controller the device runs on
     pub controller: Controller,
+    /// Register settings, only used for [`AnalogOutput`](crate::controllers::AnalogOutput) devices
+    #[serde(default)]
+    pub analog_output: Option<AnalogOutputConfig>,
+    /// How many relays the board has, only used for [`Waveshare`](crate::controllers::Waveshare) and
+    /// [`WaveshareV2`](crate::controllers::WaveshareV2) devices. STR1 boards report their own relay count.
+    /// Defaults to 8.
+    #[serde(default)]
+    pub relay_count: Option<u8>,
+    /// The master bytes the board has been programmed with, only used for [`STR1`](crate::controllers::STR1)
+    /// devices. Defaults to the standard `0x55`, `0xAA`, `0x77`.
+    #[serde(default)]
+    pub master_bytes: Option<MasterBytes>,
+    /// How many times to read again if the board doesn't answer while connecting, only used for
+    /// [`Waveshare`](crate::controllers::Waveshare) and [`WaveshareV2`](crate::controllers::WaveshareV2) devices.
+    /// Defaults to 0.
+    #[serde(default)]
+    pub probe_retries: Option<u8>,
 }
 
 impl Connection {
@@ -57,22 +140,223 @@ impl Connection {
         &self.controller
     }
 
+    /// Gets the Waveshare relay count, or the default of 8 if it isn't set
+    pub fn relay_count(&self) -> u8 {
+        self.relay_count.unwrap_or(WAVESHARE_DEFAULT_RELAY_COUNT)
+    }
+
+    /// Gets the STR1 master bytes, or the standard ones if they aren't set
+    pub fn master_bytes(&self) -> MasterBytes {
+        self.master_bytes.unwrap_or_default()
+    }
+
+    /// Gets the Waveshare probe retries, or 0 if they aren't set
+    pub fn probe_retries(&self) -> u8 {
+        self.probe_retries.unwrap_or(0)
+    }
+
     /// Gets the baudrate
     pub fn baudrate(&self) -> &usize {
         &self.baudrate
     }
 
+    /// Gets the timeout
     pub fn timeout(&self) -> Duration {
-        Duration::from_millis(self.timeout)
+        self.timeout
+    }
+
+    /// True if both connections point to the same physical controller: the same port, controller type, and
+    /// controller address. The device address (ie. relay number) is ignored.
+    pub fn same_board(&self, other: &Connection) -> bool {
+        self.same_port(other)
+            && self.controller == other.controller
+            && self.controller_addr == other.controller_addr
+    }
+
+    /// True if both connections are on the same serial port
+    pub fn same_port(&self, other: &Connection) -> bool {
+        self.port == other.port
+    }
+}
+
+// What a cached connection was opened with. If any of these change, the connection is stale.
+#[derive(Debug, Clone, PartialEq)]
+struct ConnectionKey {
+    port: PathBuf,
+    controller_addr: u8,
+    baudrate: usize,
+    relay_count: u8,
+    master_bytes: MasterBytes,
+    probe_retries: u8,
+}
+
+impl ConnectionKey {
+    fn new(conn: &Connection) -> Self {
+        Self {
+            port: conn.port.clone(),
+            controller_addr: conn.controller_addr,
+            baudrate: conn.baudrate,
+            relay_count: conn.relay_count(),
+            master_bytes: conn.master_bytes(),
+            probe_retries: conn.probe_retries(),
+        }
+    }
+}
+type OpenConnection = (ConnectionKey, Mutex<Box<dyn Any + Send>>);
+
+/// An open controller connection kept by a [`Device`](crate::model::Device) between calls, see
+/// [`Device::keep_connection_open`](crate::model::Device::keep_connection_open).
+///
+/// This isn't part of the device's configuration, so it isn't serialized. Cloning a device doesn't clone
+/// the connection, the clone starts disconnected.
+#[derive(Default)]
+pub struct ConnectionCache {
+    // The Mutex is only here so the cache is `Sync` for any `Send` controller. It's boxed to keep `Device` small.
+    open: Option<Box<OpenConnection>>,
+    // A mock controller is kept no matter what `keep_connection_open` says
+    mock: bool,
+}
+
+impl ConnectionCache {
+    /// True if there's a connection in the cache
+    pub fn is_open(&self) -> bool {
+        self.open.is_some()
+    }
+
+    // Takes the cached connection out, if it was opened with the same details and is the right type.
+    // A stale connection is dropped, which closes it. A connection of another type is left where it is.
+    fn take<T: Any + Send>(&mut self, key: &ConnectionKey) -> Option<T> {
+        let (cached_key, conn) = *self.open.take()?;
+        if cached_key != *key {
+            self.mock = false;
+            return None;
+        }
+        match conn.into_inner().ok()?.downcast::<T>() {
+            Ok(conn) => Some(*conn),
+            Err(conn) => {
+                self.open = Some(Box::new((cached_key, Mutex::new(conn))));
+                None
+            }
+        }
+    }
+
+    fn store<T: Any + Send>(&mut self, key: ConnectionKey, conn: T) {
+        self.open = Some(Box::new((key, Mutex::new(Box::new(conn)))));
+    }
+
+    fn clear(&mut self) {
+        self.open = None;
+        self.mock = false;
+    }
+}
+
+impl Clone for ConnectionCache {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+// The cache isn't part of what makes two devices the same
+impl PartialEq for ConnectionCache {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl std::fmt::Debug for ConnectionCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConnectionCache")
+            .field("open", &self.is_open())
+            .finish()
+    }
+}
+
+/// How long to wait between retries when an update or enact fails.
+/// See [`Device::retry_backoff`](crate::model::Device::retry_backoff).
+///
+/// ```yaml
+/// retry_backoff:
+///   strategy: Exponential
+///   base_ms: 100
+///   max_ms: 1000
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(tag = "strategy")]
+pub enum RetryBackoff {
+    /// Wait the device's `retry_delay` before every retry
+    Constant,
+    /// Wait `base_ms` before the first retry, doubling each time after, but never more than `max_ms`
+    Exponential { base_ms: u64, max_ms: u64 },
+}
+
+impl RetryBackoff {
+    /// The delay before the given retry, starting at 1. `retry_delay` is the device's constant delay.
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use brewdrivers::model::device::RetryBackoff;
+    /// let backoff = RetryBackoff::Exponential { base_ms: 100, max_ms: 500 };
+    /// assert_eq!(backoff.delay(150, 1), Duration::from_millis(100));
+    /// assert_eq!(backoff.delay(150, 3), Duration::from_millis(400));
+    /// assert_eq!(backoff.delay(150, 4), Duration::from_millis(500));
+    /// ```
+    pub fn delay(&self, retry_delay: u64, retry: u8) -> Duration {
+        match *self {
+            Self::Constant => Duration::from_millis(retry_delay),
+            Self::Exponential { base_ms, max_ms } => {
+                let factor = 1u64.checked_shl(retry.saturating_sub(1) as u32);
+                let ms = factor
+                    .and_then(|f| base_ms.checked_mul(f))
+                    .unwrap_or(max_ms);
+                Duration::from_millis(ms.min(max_ms))
+            }
+        }
+    }
+}
+
+// Which way we're talking to the hardware, so update and enact can share a retry loop
+#[derive(Debug, Clone, Copy)]
+enum Operation {
+    Update,
+    Enact,
+}
+
+impl Operation {
+    fn verb(&self) -> &'static str {
+        match self {
+            Self::Update => "updating",
+            Self::Enact => "enacting",
+        }
+    }
+
+    // The action the operation is logged as, see `device_event!`
+    fn action(&self) -> &'static str {
+        match self {
+            Self::Update => "update",
+            Self::Enact => "enact",
+        }
     }
 }
 
+/// Another device that has to be in a certain state before a device can be turned on.
+/// See [`Device::requires`](crate::model::Device::requires).
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Requirement {
+    /// The ID of the required device
+    pub id: String,
+    /// The relay state that device has to be in
+    pub state: BinaryState,
+}
+
 /// A digital representation of a device
 ///
 /// Devices are not controllers. They belong to controllers, and sometimes there is 1 device for 1 controller.
 /// And example is that each relay on a relay board is it's own device, so 1 controller -> 8 devices (or similar).
 /// Or we could have 1 PID controller that controls 1 Thermometer device.
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Device {
     /// The ID of the device, must be unique among all devices on all RTUs
     pub id: String,
@@ -86,79 +370,427 @@ pub struct Device {
     /// Should be less than 2000, and >= the devices timeout
     #[serde(default = "default_retry_delay")]
     pub retry_delay: u64,
+    /// How the delay between retries grows. If this isn't set, every retry waits `retry_delay`.
+    #[serde(default)]
+    pub retry_backoff: Option<RetryBackoff>,
+    /// Disabled devices are skipped when the RTU updates or enacts, so a broken device can be
+    /// taken out of the cycle without removing it from the config. Defaults to `true`.
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
     /// Connection details for the device
     pub conn: Connection,
+    /// Labels for grouping devices, like the vessel they're on (`HLT`, `Mash`) or what they
+    /// do (`pumps`, `heaters`). Tags can't contain whitespace.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// How often (ms) this device should be polled. Slow devices like thermometers don't
+    /// need to be polled as often as a safety interlock. If this isn't set, the device is polled
+    /// at whatever default rate the caller uses. Should be >= the devices timeout.
+    #[serde(default)]
+    pub poll_interval_ms: Option<u64>,
+    /// Devices that have to be put in a certain state first, in order, before this device is turned on.
+    /// For example, a pump that requires the upstream valve to be open:
+    ///
+    /// ```yaml
+    /// requires:
+    ///   - id: mash-valve
+    ///     state: On
+    /// ```
+    ///
+    /// These are only enforced by [`RTU::enact_device`](crate::model::RTU::enact_device).
+    #[serde(default)]
+    pub requires: Vec<Requirement>,
     /// The state of the device. Different devices use different types of state.
     ///
     /// Default deserialization is used here so we don't have to specify state
     /// in the config file
     #[serde(default)]
     pub state: DeviceState,
+    /// Keep the controller connection open between updates and enacts instead of reopening the port every time.
+    /// Defaults to `false`.
+    ///
+    /// Serial ports are opened exclusively, so only turn this on for a device that has its port to itself.
+    /// Another device on the same port won't be able to connect while this one holds it open.
+    /// Call [`Device::disconnect`](crate::model::Device::disconnect) to close it.
+    #[serde(default)]
+    pub keep_connection_open: bool,
+    #[serde(skip)]
+    connection: ConnectionCache,
+}
+
+// Holds a device's staged state while its state is borrowed for a read, and puts it back when it's dropped. That
+// way the staged state isn't lost if the read is cancelled part way through.
+struct StagedState<'a> {
+    device: &'a mut Device,
+    staged: DeviceState,
+}
+
+impl Drop for StagedState<'_> {
+    fn drop(&mut self) {
+        self.device.state = std::mem::take(&mut self.staged);
+    }
 }
 
 impl Device {
+    fn connection_key(&self) -> ConnectionKey {
+        ConnectionKey::new(&self.conn)
+    }
+
+    /// Takes this device's cached controller connection, if it has one that matches its current connection details.
+    /// After using it, the controller should hand it back with [`Device::store_connection`](crate::model::Device::store_connection).
+    pub(crate) fn take_connection<T: Any + Send>(&mut self) -> Option<T> {
+        let key = self.connection_key();
+        self.connection.take(&key)
+    }
+
+    /// Caches a controller connection for next time, if [`keep_connection_open`](crate::model::Device::keep_connection_open)
+    /// is set. Otherwise the connection is dropped and the port is closed.
+    pub(crate) fn store_connection<T: Any + Send>(&mut self, conn: T) {
+        if self.keep_connection_open || self.connection.mock {
+            let key = self.connection_key();
+            self.connection.store(key, conn);
+        }
+    }
+
+    /// Gives the device a mock controller, like [`STR1::connect_mock`](crate::controllers::STR1::connect_mock), to
+    /// use instead of opening its port. It's kept between updates and enacts until
+    /// [`Device::disconnect`](crate::model::Device::disconnect) is called, or the connection details change.
+    pub fn attach_mock<T: Any + Send>(&mut self, mock: T) {
+        let key = self.connection_key();
+        self.connection.store(key, mock);
+        self.connection.mock = true;
+    }
+
+    // A copy of this device that takes its cached connection with it, for working on the device in another task
+    pub(crate) fn detach(&mut self) -> Device {
+        let mut copy = self.clone();
+        copy.connection = std::mem::take(&mut self.connection);
+        copy
+    }
+
+    /// Closes the cached controller connection, if there is one
+    pub fn disconnect(&mut self) {
+        self.connection.clear();
+    }
+
+    /// True if this device is holding a controller connection open
+    pub fn is_connected(&self) -> bool {
+        self.connection.is_open()
+    }
+
+    /// How often this device should be polled, falling back to `default` if it doesn't have a `poll_interval_ms`
+    pub fn poll_interval(&self, default: Duration) -> Duration {
+        self.poll_interval_ms
+            .map(Duration::from_millis)
+            .unwrap_or(default)
+    }
+
+    /// The delay before the given retry (starting at 1), using the device's [`retry_backoff`](crate::model::Device::retry_backoff)
+    pub fn retry_delay_for(&self, retry: u8) -> Duration {
+        self.retry_backoff
+            .unwrap_or(RetryBackoff::Constant)
+            .delay(self.retry_delay, retry)
+    }
+
     pub async fn update(&mut self) -> Result<()> {
-        let total_attempts = self.command_retries + 1;
-        for i in 1..=total_attempts {
+        self.with_retries(Operation::Update, tokio::time::sleep)
+            .await
+    }
+
+    /// Reads the device's current state from the hardware and returns it, without touching
+    /// [`state`](crate::model::Device::state). This goes through the same code (and retries) as
+    /// [`update`](crate::model::Device::update), so a desired state staged on the device isn't clobbered by polling.
+    ///
+    /// The returned state only has the fields the controller reads, like `relay_state` for a relay board.
+    pub async fn read_state(&mut self) -> Result<DeviceState> {
+        let staged = std::mem::take(&mut self.state);
+        let reading = StagedState {
+            device: self,
+            staged,
+        };
+        let result = reading.device.update().await;
+        let read = std::mem::take(&mut reading.device.state);
+        // Dropping this puts the staged state back
+        drop(reading);
+        result.map(|_| read)
+    }
+
+    pub async fn enact(&mut self) -> Result<()> {
+        // Don't send anything to the hardware if the state is pathological
+        self.state.validate().map_err(InstrumentError::StateError)?;
+
+        self.with_retries(Operation::Enact, tokio::time::sleep)
+            .await
+    }
+
+    /// Goes through an enact without talking to the hardware. The port isn't opened. Instead, the real
+    /// [`enact`](crate::model::Device::enact) runs on a copy of the device with a mock controller in its place
+    /// (see [`crate::drivers::mock`]), and each write it made is logged with `device_info!()` and returned, like
+    /// `"write [FE, 53, ...]"` or `"write 1500 to register 0x1001"`.
+    ///
+    /// This fails wherever `enact` would fail before writing anything, like a bad state.
+    pub async fn enact_dry_run(&self) -> Result<Vec<String>> {
+        let addr = self.conn.controller_addr();
+        let actions: Vec<String> = match self.conn.controller {
+            Controller::STR1 => self
+                // The mock has every relay the board could have, since we don't know how many it has
+                .enact_on_mock(STR1::connect_mock(addr, u8::MAX)?, |board| {
+                    board.mock().written().to_vec()
+                })
+                .await?
+                .iter()
+                .map(|bytes| format!("write {bytes:02X?}"))
+                .collect(),
+            Controller::Waveshare => self
+                .enact_on_mock(
+                    Waveshare::connect_mock(addr, self.conn.relay_count()),
+                    |board| board.mock().written().to_vec(),
+                )
+                .await?
+                .iter()
+                .map(|bytes| format!("write {bytes:02X?}"))
+                .collect(),
+            Controller::WaveshareV2 => self
+                .enact_on_mock(
+                    WaveshareV2::connect_mock(addr, self.conn.relay_count()),
+                    |board| board.mock().written().to_vec(),
+                )
+                .await?
+                .iter()
+                .map(|bytes| format!("write {bytes:02X?}"))
+                .collect(),
+            Controller::CN7500 => self
+                .enact_on_mock(CN7500::connect_mock(addr).await?, |cn| {
+                    cn.mock().writes().to_vec()
+                })
+                .await?
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+            Controller::AnalogOutput => {
+                let config = self.conn.analog_output.clone().ok_or_else(|| {
+                    InstrumentError::modbusError(
+                        format!(
+                            "device `{}` is an AnalogOutput but has no `analog_output` connection settings",
+                            self.id
+                        ),
+                        Some(addr),
+                    )
+                })?;
+                self.enact_on_mock(AnalogOutput::connect_mock(addr, config), |ao| {
+                    ao.mock().writes().to_vec()
+                })
+                .await?
+                .iter()
+                .map(ToString::to_string)
+                .collect()
+            }
+        };
+
+        for action in &actions {
             device_info!(
                 &self,
-                &format!("updating (attempt {i} of {})", total_attempts)
+                &format!(
+                    "dry run, would {action} on {} {} ({})",
+                    self.conn.controller,
+                    self.conn.controller_addr,
+                    self.conn.port()
+                )
             );
+        }
+        Ok(actions)
+    }
 
-            let result = match self.conn.controller {
-                Controller::STR1 => STR1::update(self).await,
-                Controller::CN7500 => CN7500::update(self).await,
-                Controller::Waveshare => Waveshare::update(self).await,
-                Controller::WaveshareV2 => WaveshareV2::update(self).await,
-            };
+    // Enacts a copy of the device on the given mock controller, and returns what was written to the mock
+    // during the enact. `writes` gets everything that's been written to the mock.
+    async fn enact_on_mock<T: Any + Send, W>(
+        &self,
+        mut mock: T,
+        writes: fn(&mut T) -> Vec<W>,
+    ) -> Result<Vec<W>> {
+        let connecting = writes(&mut mock).len();
+        // A copy doesn't carry the device's connection, so the mock is the only thing written to
+        let mut dry = self.clone();
+        dry.attach_mock(mock);
+        dry.enact().await?;
 
-            match result {
-                Ok(_) => return Ok(()),
-                Err(e) => {
-                    // If we're on the last iteration of the loop
-                    // ie. the last retry and we still fail, then return the error
-                    if i == total_attempts {
-                        return Err(e);
+        let written = dry
+            .take_connection::<T>()
+            .map(|mut mock| writes(&mut mock))
+            .unwrap_or_default();
+        Ok(written.into_iter().skip(connecting).collect())
+    }
+
+    /// Checks if the device's controller answers, with the cheapest command it has. That's the relay count
+    /// for an STR1, the software revision for a Waveshare or CN7500, and a read of the output register for an
+    /// analog output.
+    ///
+    /// Anything going wrong, a timeout, a bad response, or a port that can't be opened, returns `false`.
+    /// This isn't retried like [`update`](crate::model::Device::update), and a CN7500 is only probed once.
+    pub async fn is_reachable(&mut self) -> bool {
+        // A mock controller given to the device (see `Device::attach_mock`) is checked instead of the port
+        let reachable = match self.conn.controller {
+            Controller::STR1 => match self.take_connection::<STR1<MockSerialInstrument>>() {
+                Some(mut mock) => mock.connected().map(|_| self.store_connection(mock)),
+                None => match self.take_connection::<STR1>() {
+                    Some(mut board) => board.connected().map(|_| self.store_connection(board)),
+                    None => STR1::try_from(&*self).map(|board| self.store_connection(board)),
+                },
+            },
+            Controller::Waveshare => {
+                match self.take_connection::<Waveshare<MockWaveshareInstrument>>() {
+                    Some(mut mock) => mock.connected().map(|_| self.store_connection(mock)),
+                    None => match self.take_connection::<Waveshare>() {
+                        Some(mut ws) => ws.connected().map(|_| self.store_connection(ws)),
+                        None => Waveshare::try_from(&*self).map(|ws| self.store_connection(ws)),
+                    },
+                }
+            }
+            Controller::WaveshareV2 => {
+                match self.take_connection::<WaveshareV2<MockWaveshareInstrument>>() {
+                    Some(mut mock) => mock.connected().map(|_| self.store_connection(mock)),
+                    None => match self.take_connection::<WaveshareV2>() {
+                        Some(mut ws) => ws.connected().map(|_| self.store_connection(ws)),
+                        None => WaveshareV2::try_from(&*self).map(|ws| self.store_connection(ws)),
+                    },
+                }
+            }
+            Controller::CN7500 => match self.take_connection::<CN7500<MockModbusInstrument>>() {
+                Some(mut mock) => mock.connected().await.map(|_| self.store_connection(mock)),
+                None => match self.take_connection::<CN7500>() {
+                    Some(mut cn) => cn.connected().await.map(|_| self.store_connection(cn)),
+                    None => CN7500::connect_with_retries(
+                        self.conn.controller_addr(),
+                        &self.conn.port(),
+                        *self.conn.baudrate() as u64,
+                        self.conn.timeout(),
+                        1,
+                        Duration::ZERO,
+                    )
+                    .await
+                    .map(|cn| self.store_connection(cn)),
+                },
+            },
+            Controller::AnalogOutput => {
+                match self.take_connection::<AnalogOutput<MockModbusInstrument>>() {
+                    Some(mut mock) => mock
+                        .get_output_pct()
+                        .await
+                        .map(|_| self.store_connection(mock)),
+                    None => {
+                        let ao = match self.take_connection::<AnalogOutput>() {
+                            Some(ao) => Ok(ao),
+                            None => AnalogOutput::from_device(self).await,
+                        };
+                        match ao {
+                            Ok(mut ao) => {
+                                ao.get_output_pct().await.map(|_| self.store_connection(ao))
+                            }
+                            Err(e) => Err(e),
+                        }
                     }
-                    device_info!(&self, &format!("updating failed, but attempts remain. Waiting for retry_delay = {} ms before trying again.", self.retry_delay));
-                    std::thread::sleep(Duration::from_millis(self.retry_delay));
                 }
             }
+        };
+
+        if let Err(e) = &reachable {
+            device_info!(&self, &format!("not reachable: {e}"));
         }
+        reachable.is_ok()
+    }
 
-        panic!("Reached some code that shouldn't be reachable. Ran through all iterations of a device update loop without Ok() or Err()");
+    /// Flips a relay device. The relay is read with [`update`](crate::model::Device::update), then the opposite
+    /// state is [`enact`](crate::model::Device::enact)ed.
+    ///
+    /// Only devices on relay boards (STR1, Waveshare, WaveshareV2) can be toggled. Anything else returns
+    /// [`InstrumentError::NotARelay`](crate::drivers::InstrumentError::NotARelay) without talking to the controller.
+    pub async fn toggle(&mut self) -> Result<()> {
+        match self.conn.controller {
+            Controller::STR1 | Controller::Waveshare | Controller::WaveshareV2 => {}
+            controller => {
+                return Err(InstrumentError::NotARelay {
+                    device_id: self.id.clone(),
+                    controller,
+                })
+            }
+        }
+
+        self.update().await?;
+        let current = self
+            .state
+            .relay_state
+            .ok_or_else(|| InstrumentError::StateError(StateError::BadValue(self.state.clone())))?;
+        device_info!(
+            &self,
+            &format!("toggling relay from {current} to {}", !current)
+        );
+        self.state.relay_state = Some(!current);
+        self.enact().await
     }
 
-    pub async fn enact(&mut self) -> Result<()> {
+    async fn attempt(&mut self, op: Operation) -> Result<()> {
+        match (op, self.conn.controller) {
+            (Operation::Update, Controller::STR1) => STR1::update(self).await,
+            (Operation::Update, Controller::CN7500) => CN7500::update(self).await,
+            (Operation::Update, Controller::Waveshare) => Waveshare::update(self).await,
+            (Operation::Update, Controller::WaveshareV2) => WaveshareV2::update(self).await,
+            (Operation::Update, Controller::AnalogOutput) => AnalogOutput::update(self).await,
+            (Operation::Enact, Controller::STR1) => STR1::enact(self).await,
+            (Operation::Enact, Controller::CN7500) => CN7500::enact(self).await,
+            (Operation::Enact, Controller::Waveshare) => Waveshare::enact(self).await,
+            (Operation::Enact, Controller::WaveshareV2) => WaveshareV2::enact(self).await,
+            (Operation::Enact, Controller::AnalogOutput) => AnalogOutput::enact(self).await,
+        }
+    }
+
+    // Tries the operation up to `command_retries + 1` times. `sleep` is called with the delay before each retry,
+    // it's only swapped out by tests so they don't have to actually wait. This is `tokio::time::sleep` normally,
+    // never `std::thread::sleep`, so a retrying device doesn't block every other task on the thread.
+    async fn with_retries<S, F>(&mut self, op: Operation, mut sleep: S) -> Result<()>
+    where
+        S: FnMut(Duration) -> F,
+        F: Future<Output = ()>,
+    {
         let total_attempts = self.command_retries + 1;
         for i in 1..=total_attempts {
             device_info!(
                 &self,
-                &format!("enacting (attempt {i} of {})", total_attempts)
+                &format!("{} (attempt {i} of {})", op.verb(), total_attempts)
             );
 
-            let result = match self.conn.controller {
-                Controller::STR1 => STR1::enact(self).await,
-                Controller::CN7500 => CN7500::enact(self).await,
-                Controller::Waveshare => Waveshare::enact(self).await,
-                Controller::WaveshareV2 => WaveshareV2::enact(self).await,
-            };
-
-            match result {
-                Ok(_) => return Ok(()),
+            match self.attempt(op).await {
+                Ok(_) => {
+                    device_event!(self, op.action(), format!("succeeded on attempt {i}"));
+                    return Ok(());
+                }
                 Err(e) => {
                     // If we're on the last iteration of the loop
                     // ie. the last retry and we still fail, then return the error
                     if i == total_attempts {
+                        device_event!(
+                            log::Level::Warn,
+                            self,
+                            op.action(),
+                            format!("failed after {total_attempts} attempts: {e}")
+                        );
                         return Err(e);
                     }
-                    device_info!(&self, &format!("enacting failed, but attempts remain. Waiting for retry_delay = {} ms before trying again.", self.retry_delay));
-                    std::thread::sleep(Duration::from_millis(self.retry_delay));
+                    let delay = self.retry_delay_for(i);
+                    device_event!(
+                        log::Level::Warn,
+                        self,
+                        op.action(),
+                        format!(
+                            "attempt {i} failed, retrying in {} ms: {e}",
+                            delay.as_millis()
+                        )
+                    );
+                    sleep(delay).await;
                 }
             }
         }
 
-        panic!("Reached some code that shouldn't be reachable. Ran through all iterations of a device enact loop without Ok() or Err()");
+        panic!("Reached some code that shouldn't be reachable. Ran through all iterations of a device {} loop without Ok() or Err()", op.verb());
     }
 }
 
@@ -172,13 +804,596 @@ mod tests {
         let conn = Connection {
             port: PathBuf::from("/dev/ttyUSB0"),
             baudrate: 19200,
-            timeout: 200,
+            timeout: Duration::from_millis(200),
             controller: Controller::CN7500,
             addr: 0,
             controller_addr: 22,
+            analog_output: None,
+            relay_count: None,
+            master_bytes: None,
+            probe_retries: None,
         };
 
         assert_eq!("/dev/ttyUSB0", conn.port());
         assert_ne!(r#""/dev/ttyUSB0""#, conn.port());
     }
+
+    fn relay_conn(port: &str, controller_addr: u8, addr: u8) -> Connection {
+        Connection {
+            port: PathBuf::from(port),
+            baudrate: 9600,
+            timeout: Duration::from_millis(40),
+            controller: Controller::STR1,
+            addr,
+            controller_addr,
+            analog_output: None,
+            relay_count: None,
+            master_bytes: None,
+            probe_retries: None,
+        }
+    }
+
+    #[test]
+    fn test_deserialize_timeout() {
+        let conn = |timeout: &str| {
+            serde_yaml::from_str::<Connection>(&format!(
+                "{{ port: /dev/ttyUSB0, baudrate: 9600, timeout: {timeout}, controller: STR1, controller_addr: 254 }}"
+            ))
+        };
+
+        assert_eq!(conn("50").unwrap().timeout(), Duration::from_millis(50));
+        assert_eq!(conn("50ms").unwrap().timeout(), Duration::from_millis(50));
+        assert_eq!(
+            conn("\"50 ms\"").unwrap().timeout(),
+            Duration::from_millis(50)
+        );
+        assert_eq!(conn("1s").unwrap().timeout(), Duration::from_secs(1));
+        assert!(conn("50us").is_err());
+        assert!(conn("fast").is_err());
+        assert!(conn("-50").is_err());
+
+        // Always written back as milliseconds
+        let yaml = serde_yaml::to_string(&conn("1s").unwrap()).unwrap();
+        assert!(yaml.contains("timeout: 1000"));
+    }
+
+    #[test]
+    fn test_connection_same_board() {
+        let relay0 = relay_conn("/dev/ttyUSB0", 254, 0);
+        let relay1 = relay_conn("/dev/ttyUSB0", 254, 1);
+        assert!(relay0.same_board(&relay1));
+        assert!(relay0.same_port(&relay1));
+
+        // Same port, different board
+        let other_board = relay_conn("/dev/ttyUSB0", 2, 0);
+        assert!(!relay0.same_board(&other_board));
+        assert!(relay0.same_port(&other_board));
+
+        let mut other_controller = relay_conn("/dev/ttyUSB0", 254, 0);
+        other_controller.controller = Controller::Waveshare;
+        assert!(!relay0.same_board(&other_controller));
+
+        // Different ports
+        let other_port = relay_conn("/dev/ttyUSB1", 254, 0);
+        assert!(!relay0.same_board(&other_port));
+        assert!(!relay0.same_port(&other_port));
+    }
+
+    #[test]
+    fn test_deserialize_analog_output_device() {
+        let device: Device = serde_yaml::from_str(
+            r#"
+            id: valve
+            name: Proportional Valve
+            conn:
+                port: /dev/ttyUSB0
+                baudrate: 19200
+                timeout: 50
+                controller: AnalogOutput
+                controller_addr: 3
+                analog_output:
+                    register: 16
+                    min: 0
+                    max: 4095
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(device.conn.controller, Controller::AnalogOutput);
+        assert_eq!(
+            device.conn.analog_output,
+            Some(AnalogOutputConfig {
+                register: 16,
+                min: 0,
+                max: 4095
+            })
+        );
+        assert_eq!(device.state.output_pct, None);
+    }
+
+    #[test]
+    fn test_deserialize_device_tags() {
+        let device: Device = serde_yaml::from_str(
+            r#"
+            id: mash-pump
+            name: Mash Pump
+            tags: [Mash, pumps]
+            conn:
+                port: /dev/ttyUSB0
+                baudrate: 9600
+                timeout: 100
+                controller: STR1
+                controller_addr: 254
+                addr: 0
+            "#,
+        )
+        .unwrap();
+        assert_eq!(device.tags, vec!["Mash", "pumps"]);
+
+        // Tags are optional
+        let device: Device = serde_yaml::from_str(
+            r#"
+            id: pump
+            name: Pump
+            conn:
+                port: /dev/ttyUSB0
+                baudrate: 9600
+                timeout: 100
+                controller: STR1
+                controller_addr: 254
+            "#,
+        )
+        .unwrap();
+        assert!(device.tags.is_empty());
+    }
+
+    #[test]
+    fn test_deserialize_retry_backoff() {
+        let device: Device = serde_yaml::from_str(
+            r#"
+            id: pump
+            name: Pump
+            retry_backoff:
+                strategy: Exponential
+                base_ms: 100
+                max_ms: 1000
+            conn: { port: /dev/ttyUSB0, baudrate: 9600, timeout: 40, controller: STR1, controller_addr: 254 }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            device.retry_backoff,
+            Some(RetryBackoff::Exponential {
+                base_ms: 100,
+                max_ms: 1000
+            })
+        );
+
+        let device: Device = serde_yaml::from_str(
+            r#"
+            id: pump
+            name: Pump
+            retry_backoff: { strategy: Constant }
+            conn: { port: /dev/ttyUSB0, baudrate: 9600, timeout: 40, controller: STR1, controller_addr: 254 }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(device.retry_backoff, Some(RetryBackoff::Constant));
+        assert_eq!(device.retry_delay_for(3), Duration::from_millis(150));
+
+        // Optional, and falls back to a constant retry_delay
+        let device: Device = serde_yaml::from_str(
+            r#"
+            id: pump
+            name: Pump
+            retry_delay: 300
+            conn: { port: /dev/ttyUSB0, baudrate: 9600, timeout: 40, controller: STR1, controller_addr: 254 }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(device.retry_backoff, None);
+        assert_eq!(device.retry_delay_for(1), Duration::from_millis(300));
+        assert_eq!(device.retry_delay_for(4), Duration::from_millis(300));
+    }
+
+    #[test]
+    fn test_exponential_backoff_caps() {
+        let backoff = RetryBackoff::Exponential {
+            base_ms: 100,
+            max_ms: 1000,
+        };
+        assert_eq!(backoff.delay(150, 5), Duration::from_millis(1000));
+        // Big shifts don't overflow
+        assert_eq!(backoff.delay(150, 200), Duration::from_millis(1000));
+    }
+
+    #[tokio::test]
+    async fn test_exponential_backoff_sleeps() {
+        // The port doesn't exist, so every attempt fails right away
+        let mut dev: Device = serde_yaml::from_str(
+            r#"
+            id: pump
+            name: Pump
+            command_retries: 4
+            retry_backoff: { strategy: Exponential, base_ms: 100, max_ms: 500 }
+            conn: { port: /dev/doesntexist0, baudrate: 9600, timeout: 40, controller: STR1, controller_addr: 254 }
+            "#,
+        )
+        .unwrap();
+
+        let mut sleeps = vec![];
+        let result = dev
+            .with_retries(Operation::Update, |delay| {
+                sleeps.push(delay);
+                async {}
+            })
+            .await;
+        assert!(result.is_err());
+
+        // No sleep after the last attempt
+        let expected: Vec<Duration> = [100, 200, 400, 500]
+            .into_iter()
+            .map(Duration::from_millis)
+            .collect();
+        assert_eq!(sleeps, expected);
+    }
+
+    #[tokio::test]
+    async fn test_retry_delay_doesnt_block_other_devices() {
+        // Both fail right away. `slow` waits 500ms before retrying, `fast` doesn't retry.
+        let mut slow: Device = serde_yaml::from_str(
+            r#"
+            id: slow
+            name: Slow
+            command_retries: 1
+            retry_delay: 500
+            conn: { port: /dev/doesntexist0, baudrate: 9600, timeout: 40, controller: STR1, controller_addr: 254 }
+            "#,
+        )
+        .unwrap();
+        let mut fast: Device = serde_yaml::from_str(
+            r#"
+            id: fast
+            name: Fast
+            command_retries: 0
+            conn: { port: /dev/doesntexist1, baudrate: 9600, timeout: 40, controller: STR1, controller_addr: 254 }
+            "#,
+        )
+        .unwrap();
+
+        // tokio::test is single threaded, so a blocking sleep in `slow` would hold up `fast` too
+        let start = std::time::Instant::now();
+        let (slow_done, fast_done) = tokio::join!(
+            async {
+                let _ = slow.update().await;
+                start.elapsed()
+            },
+            async {
+                let _ = fast.update().await;
+                start.elapsed()
+            }
+        );
+
+        assert!(slow_done >= Duration::from_millis(500));
+        assert!(fast_done < Duration::from_millis(250));
+    }
+
+    #[tokio::test]
+    async fn test_enact_dry_run() {
+        // The port doesn't exist, so anything that tried to write would fail
+        let mut relay: Device = serde_yaml::from_str(
+            r#"
+            id: pump
+            name: Pump
+            command_retries: 0
+            conn: { port: /dev/doesntexist0, baudrate: 9600, timeout: 40, controller: STR1, controller_addr: 254, addr: 3 }
+            "#,
+        )
+        .unwrap();
+
+        // Nothing to enact yet
+        assert!(relay.enact_dry_run().await.is_err());
+
+        relay.state.relay_state = Some(BinaryState::On);
+        assert_eq!(
+            relay.enact_dry_run().await.unwrap(),
+            vec!["write [55, AA, 08, 17, FE, 03, 01, 01, 22, 77]"]
+        );
+        assert!(!relay.is_connected());
+        assert!(relay.enact().await.is_err());
+
+        // A dry run doesn't write to the device's own board either, only connecting to it was recorded
+        relay.attach_mock(STR1::connect_mock(254, 8).unwrap());
+        assert_eq!(relay.enact_dry_run().await.unwrap().len(), 1);
+        let board = relay
+            .take_connection::<STR1<MockSerialInstrument>>()
+            .unwrap();
+        assert_eq!(board.mock().written().len(), 1);
+        assert!(!board.mock().relays()[3]);
+
+        let mut pid: Device = serde_yaml::from_str(
+            r#"
+            id: rims
+            name: RIMS
+            conn: { port: /dev/doesntexist0, baudrate: 19200, timeout: 40, controller: CN7500, controller_addr: 22 }
+            "#,
+        )
+        .unwrap();
+        pid.state.relay_state = Some(BinaryState::Off);
+        pid.state.sv = Some(152.5);
+        assert_eq!(
+            pid.enact_dry_run().await.unwrap(),
+            vec![
+                "write false to coil 0x0814",
+                "write 1525 to register 0x1001"
+            ]
+        );
+
+        // Bad states are caught the same way as enact
+        pid.state.sv = Some(f64::NAN);
+        assert!(pid.enact_dry_run().await.is_err());
+
+        let mut valve: Device = serde_yaml::from_str(
+            r#"
+            id: valve
+            name: Valve
+            conn:
+              { port: /dev/doesntexist0, baudrate: 19200, timeout: 40, controller: AnalogOutput, controller_addr: 3,
+                analog_output: { register: 0, min: 0, max: 4000 } }
+            "#,
+        )
+        .unwrap();
+        valve.state.output_pct = Some(50.0);
+        assert_eq!(
+            valve.enact_dry_run().await.unwrap(),
+            vec!["write 2000 to register 0x0000"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_is_reachable_without_responder() {
+        for controller in ["STR1", "Waveshare", "WaveshareV2", "CN7500"] {
+            let mut dev: Device = serde_yaml::from_str(&format!(
+                r#"
+                id: pump
+                name: Pump
+                keep_connection_open: true
+                conn: {{ port: /dev/doesntexist0, baudrate: 9600, timeout: 40, controller: {controller}, controller_addr: 1 }}
+                "#
+            ))
+            .unwrap();
+            assert!(
+                !dev.is_reachable().await,
+                "{controller} shouldn't be reachable"
+            );
+            assert!(!dev.is_connected());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_is_reachable_mock() {
+        let mut relay: Device = serde_yaml::from_str(
+            r#"
+            id: pump
+            name: Pump
+            conn: { port: /dev/doesntexist0, baudrate: 9600, timeout: 40, controller: STR1, controller_addr: 254, addr: 3 }
+            "#,
+        )
+        .unwrap();
+        relay.attach_mock(STR1::connect_mock(254, 8).unwrap());
+        assert!(relay.is_reachable().await);
+        // The board is kept for next time
+        assert!(relay.is_connected());
+
+        let mut pid: Device = serde_yaml::from_str(
+            r#"
+            id: rims
+            name: RIMS
+            conn: { port: /dev/doesntexist0, baudrate: 19200, timeout: 40, controller: CN7500, controller_addr: 22 }
+            "#,
+        )
+        .unwrap();
+        pid.attach_mock(CN7500::connect_mock(22).await.unwrap());
+        assert!(pid.is_reachable().await);
+    }
+
+    #[tokio::test]
+    async fn test_toggle() {
+        let mut pid: Device = serde_yaml::from_str(
+            r#"
+            id: rims
+            name: RIMS
+            conn: { port: /dev/doesntexist0, baudrate: 19200, timeout: 40, controller: CN7500, controller_addr: 22 }
+            "#,
+        )
+        .unwrap();
+        pid.state.relay_state = Some(BinaryState::On);
+        // Refused before the port is touched, so this isn't a port error
+        assert!(matches!(
+            pid.toggle().await,
+            Err(InstrumentError::NotARelay {
+                controller: Controller::CN7500,
+                ..
+            })
+        ));
+        assert_eq!(pid.state.relay_state, Some(BinaryState::On));
+
+        let mut relay: Device = serde_yaml::from_str(
+            r#"
+            id: pump
+            name: Pump
+            command_retries: 0
+            conn: { port: /dev/doesntexist0, baudrate: 9600, timeout: 40, controller: STR1, controller_addr: 254, addr: 3 }
+            "#,
+        )
+        .unwrap();
+        relay.state.relay_state = Some(BinaryState::Off);
+        // The relay couldn't be read, so it's left alone
+        assert!(matches!(
+            relay.toggle().await,
+            Err(InstrumentError::SerialError { .. })
+        ));
+        assert_eq!(relay.state.relay_state, Some(BinaryState::Off));
+    }
+
+    #[tokio::test]
+    async fn test_toggle_mock_relay() {
+        let mut relay: Device = serde_yaml::from_str(
+            r#"
+            id: pump
+            name: Pump
+            conn: { port: /dev/doesntexist0, baudrate: 9600, timeout: 40, controller: STR1, controller_addr: 254, addr: 3 }
+            "#,
+        )
+        .unwrap();
+        relay.attach_mock(STR1::connect_mock(254, 8).unwrap());
+        // The staged state doesn't matter, the relay is read first
+        relay.state.relay_state = Some(BinaryState::On);
+
+        relay.toggle().await.unwrap();
+        assert_eq!(relay.state.relay_state, Some(BinaryState::On));
+        relay.toggle().await.unwrap();
+        assert_eq!(relay.state.relay_state, Some(BinaryState::Off));
+
+        let board = relay
+            .take_connection::<STR1<MockSerialInstrument>>()
+            .unwrap();
+        assert_eq!(board.mock().relays()[3], false);
+        assert!(board.mock().relays().iter().all(|relay| !relay));
+        // Connect, then a read and a write for each toggle
+        assert_eq!(board.mock().written().len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_read_state_keeps_staged_state() {
+        let mut relay: Device = serde_yaml::from_str(
+            r#"
+            id: pump
+            name: Pump
+            command_retries: 0
+            conn: { port: /dev/doesntexist0, baudrate: 9600, timeout: 40, controller: STR1, controller_addr: 254, addr: 3 }
+            "#,
+        )
+        .unwrap();
+        relay.state.relay_state = Some(BinaryState::On);
+        assert!(relay.read_state().await.is_err());
+        assert_eq!(relay.state.relay_state, Some(BinaryState::On));
+
+        // Cancelled while it waits to retry
+        relay.command_retries = 1;
+        relay.retry_delay = 10_000;
+        let read = tokio::time::timeout(Duration::from_millis(10), relay.read_state()).await;
+        assert!(read.is_err());
+        assert_eq!(relay.state.relay_state, Some(BinaryState::On));
+
+        // The board's relay is off, but the staged state is still on afterwards
+        relay.attach_mock(STR1::connect_mock(254, 8).unwrap());
+        let read = relay.read_state().await.unwrap();
+        assert_eq!(read.relay_state, Some(BinaryState::Off));
+        assert_eq!(relay.state.relay_state, Some(BinaryState::On));
+    }
+
+    #[test]
+    fn test_connection_cache_opens_once() {
+        let key = ConnectionKey::new(&relay_conn("/dev/ttyUSB0", 254, 0));
+        let mut cache = ConnectionCache::default();
+        let mut opens = 0;
+
+        // The same thing the controllers do on every update
+        for _ in 0..10 {
+            let conn = match cache.take::<String>(&key) {
+                Some(conn) => conn,
+                None => {
+                    opens += 1;
+                    String::from("open port")
+                }
+            };
+            cache.store(key.clone(), conn);
+        }
+        assert_eq!(opens, 1);
+        assert!(cache.is_open());
+
+        // Different connection details mean a new connection
+        let moved = ConnectionKey::new(&relay_conn("/dev/ttyUSB1", 254, 0));
+        assert!(cache.take::<String>(&moved).is_none());
+        assert!(!cache.is_open());
+
+        // The wrong type doesn't get it, but it's kept for the right one
+        cache.store(key.clone(), String::from("open port"));
+        assert!(cache.take::<u32>(&key).is_none());
+        assert_eq!(cache.take::<String>(&key).unwrap(), "open port");
+    }
+
+    #[test]
+    fn test_device_keep_connection_open() {
+        let mut dev: Device = serde_yaml::from_str(
+            r#"
+            id: pump
+            name: Pump
+            conn: { port: /dev/ttyUSB0, baudrate: 9600, timeout: 100, controller: STR1, controller_addr: 254 }
+            "#,
+        )
+        .unwrap();
+
+        // Off by default, so the connection is just dropped
+        assert!(!dev.keep_connection_open);
+        dev.store_connection(String::from("open port"));
+        assert!(!dev.is_connected());
+
+        dev.keep_connection_open = true;
+        dev.store_connection(String::from("open port"));
+        assert!(dev.is_connected());
+        // Clones and comparisons ignore the connection
+        assert!(!dev.clone().is_connected());
+        assert_eq!(dev.clone(), dev);
+
+        assert_eq!(
+            dev.take_connection::<String>().as_deref(),
+            Some("open port")
+        );
+        dev.store_connection(String::from("open port"));
+        dev.disconnect();
+        assert!(!dev.is_connected());
+        assert!(dev.take_connection::<String>().is_none());
+    }
+
+    #[test]
+    fn test_connection_settings_change_invalidates_cache() {
+        let mut dev: Device = serde_yaml::from_str(
+            r#"
+            id: pump
+            name: Pump
+            keep_connection_open: true
+            conn: { port: /dev/ttyUSB0, baudrate: 9600, timeout: 100, controller: Waveshare, controller_addr: 1 }
+            "#,
+        )
+        .unwrap();
+
+        // Each of these is baked into the controller when it connects
+        let changes: [fn(&mut Connection); 3] = [
+            |conn| conn.relay_count = Some(16),
+            |conn| {
+                conn.master_bytes = Some(MasterBytes {
+                    ma0: 0x5A,
+                    ma1: 0xA5,
+                    mae: 0x7E,
+                })
+            },
+            |conn| conn.probe_retries = Some(2),
+        ];
+        for change in changes {
+            dev.store_connection(String::from("open port"));
+            change(&mut dev.conn);
+            assert!(dev.take_connection::<String>().is_none());
+        }
+
+        // Setting the default explicitly is the same connection
+        let mut dev = Device {
+            conn: relay_conn("/dev/ttyUSB0", 254, 0),
+            ..dev
+        };
+        dev.store_connection(String::from("open port"));
+        dev.conn.relay_count = Some(WAVESHARE_DEFAULT_RELAY_COUNT);
+        assert!(dev.take_connection::<String>().is_some());
+    }
 }
@@ -0,0 +1,162 @@
+//! Renders device state as Prometheus metrics. Needs the `metrics` feature.
+//!
+//! This only serializes each device's current [`DeviceState`](crate::state::DeviceState), it doesn't poll any
+//! hardware. Call [`RTU::update`](crate::model::RTU::update) first if the state should be fresh.
+//!
+//! ```rust,no_run
+//! use brewdrivers::model::{metrics, RTU};
+//!
+//! let rtu = RTU::generate(None).unwrap();
+//! // ie. brewdrivers_device_pv{device_id="omega1"} 152.3
+//! println!("{}", metrics::render(&rtu));
+//! ```
+use std::fmt::Write;
+
+use crate::model::{Device, RTU};
+
+// A gauge's name, help text, and how to read it from a device, if the device has it
+type Gauge = (&'static str, &'static str, fn(&Device) -> Option<f64>);
+
+const GAUGES: [Gauge; 4] = [
+    (
+        "brewdrivers_device_pv",
+        "Process value (ie. the current temperature)",
+        |device| device.state.pv,
+    ),
+    (
+        "brewdrivers_device_sv",
+        "Setpoint value (ie. the target temperature)",
+        |device| device.state.sv,
+    ),
+    (
+        "brewdrivers_relay_state",
+        "Relay state, 1 if on and 0 if off",
+        |device| {
+            device
+                .state
+                .relay_state
+                .map(|state| state.as_bool() as u8 as f64)
+        },
+    ),
+    (
+        "brewdrivers_device_output_pct",
+        "Output percentage (0-100) of an analog output",
+        |device| device.state.output_pct,
+    ),
+];
+
+/// Renders the state of every enabled device on the RTU in the Prometheus text format, one gauge per value the
+/// device has. Devices without a value (ie. a relay has no PV) are left out of that gauge. Disabled devices
+/// aren't updated, so their stale state is left out entirely.
+pub fn render(rtu: &RTU) -> String {
+    let mut out = String::new();
+    for (name, help, value) in GAUGES {
+        let samples: Vec<(&str, f64)> = rtu
+            .devices
+            .iter()
+            .filter(|device| device.enabled)
+            .filter_map(|device| Some((device.id.as_str(), value(device)?)))
+            .collect();
+        if samples.is_empty() {
+            continue;
+        }
+
+        // Writing to a String can't fail
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} gauge");
+        for (device_id, value) in samples {
+            let _ = writeln!(
+                out,
+                "{name}{{device_id=\"{}\"}} {value}",
+                escape_label(device_id)
+            );
+        }
+    }
+    out
+}
+
+// Label values escape backslashes, quotes, and newlines
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let rtu: RTU = serde_yaml::from_str(
+            r#"
+            name: Test RTU
+            id: test-rtu
+            ip_addr: 0.0.0.0
+            devices:
+              - id: omega1
+                name: Omega 1
+                conn:
+                  port: /dev/ttyUSB0
+                  baudrate: 19200
+                  timeout: 100
+                  controller: CN7500
+                  controller_addr: 22
+                state:
+                  relay_state: Off
+                  pv: 152.3
+                  sv: 150.0
+              - id: relay1
+                name: Relay 1
+                conn:
+                  port: /dev/ttyUSB0
+                  baudrate: 9600
+                  timeout: 100
+                  controller: STR1
+                  controller_addr: 254
+                  addr: 0
+                state:
+                  relay_state: On
+              - id: relay2
+                name: Relay 2
+                enabled: false
+                conn:
+                  port: /dev/ttyUSB0
+                  baudrate: 9600
+                  timeout: 100
+                  controller: STR1
+                  controller_addr: 254
+                  addr: 1
+                state:
+                  relay_state: On
+            "#,
+        )
+        .unwrap();
+
+        let rendered = render(&rtu);
+        let lines: Vec<&str> = rendered.lines().collect();
+        for expected in [
+            "# TYPE brewdrivers_device_pv gauge",
+            r#"brewdrivers_device_pv{device_id="omega1"} 152.3"#,
+            r#"brewdrivers_device_sv{device_id="omega1"} 150"#,
+            r#"brewdrivers_relay_state{device_id="omega1"} 0"#,
+            r#"brewdrivers_relay_state{device_id="relay1"} 1"#,
+        ] {
+            assert!(
+                lines.contains(&expected),
+                "missing `{expected}` in:\n{rendered}"
+            );
+        }
+        // The relay has no PV, and nothing has an output percentage
+        assert!(!rendered.contains(r#"brewdrivers_device_pv{device_id="relay1"}"#));
+        assert!(!rendered.contains("brewdrivers_device_output_pct"));
+        // Disabled devices aren't rendered
+        assert!(!rendered.contains("relay2"));
+    }
+
+    #[test]
+    fn test_escape_label() {
+        assert_eq!(escape_label(r#"a"b\c"#), r#"a\"b\\c"#);
+    }
+}
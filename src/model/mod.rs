@@ -1,14 +1,33 @@
 use crate::drivers::InstrumentError;
 use async_trait::async_trait;
 
+mod config_check;
 pub mod device;
+#[cfg(feature = "metrics")]
+pub mod metrics;
 pub mod model_error;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
 pub mod rtu;
 mod validators;
 
+pub use config_check::{validate_config_file, ConfigIssue};
 pub use device::Device;
 pub use model_error::ModelError;
 pub use rtu::RTU;
+pub use validators::ValidatorOptions;
+
+/// Returns a JSON Schema for the RTU configuration file, including the devices in it. This is meant for
+/// configuration editors, so they can validate and autocomplete configs. Needs the `schema` feature.
+///
+/// ```rust
+/// let schema = brewdrivers::model::json_schema();
+/// println!("{}", serde_yaml::to_string(&schema).unwrap());
+/// ```
+#[cfg(feature = "schema")]
+pub fn json_schema() -> schemars::schema::RootSchema {
+    schemars::schema_for!(RTU)
+}
 
 /// An abstraction of a field device that can be polled and set
 ///
@@ -19,3 +38,19 @@ pub trait SCADADevice {
     async fn update(device: &mut Device) -> Result<(), InstrumentError>;
     async fn enact(device: &mut Device) -> Result<(), InstrumentError>;
 }
+
+#[cfg(all(test, feature = "schema"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_schema() {
+        let schema = serde_yaml::to_string(&json_schema()).unwrap();
+        for definition in ["Device", "Connection", "Controller", "DeviceState"] {
+            assert!(schema.contains(definition), "schema is missing `{}`", definition);
+        }
+        for controller in ["STR1", "CN7500", "Waveshare", "WaveshareV2", "AnalogOutput"] {
+            assert!(schema.contains(controller), "schema is missing `{}`", controller);
+        }
+    }
+}
@@ -7,13 +7,25 @@ pub enum ModelError {
     FileNotFound,
 
     #[error("IO error: {0}")]
-    IOError(std::io::Error),
+    IOError(#[source] std::io::Error),
 
     #[error("Permission error, cannot access configuration file")]
     PermissionError,
 
     #[error("Serde parse error: {0}")]
-    SerdeParseError(serde_yaml::Error),
+    SerdeParseError(#[source] serde_yaml::Error),
+
+    #[error("Unknown configuration key(s): {}", .0.join(", "))]
+    UnknownFields(Vec<String>),
+
+    #[error("Configuration references environment variable `{0}`, but it isn't set")]
+    EnvVarNotSet(String),
+
+    #[error("Device with id `{0}` not found")]
+    DeviceNotFound(String),
+
+    #[error("Device with id `{0}` was requested more than once")]
+    DuplicateDevice(String),
 
     #[error("Validation Error: {item_id}.{key} = `{value}` (Rule: {rule})")]
     ValidationError {
@@ -0,0 +1,209 @@
+//! Publishes device state to an MQTT broker. Needs the `mqtt` feature.
+//!
+//! Each device is published as JSON on `{prefix}/{rtu_id}/{device_id}`, the same JSON that's sent
+//! to the front-end. [`RTU::update_and_publish`](crate::model::RTU::update_and_publish) and
+//! [`RTU::enact_and_publish`](crate::model::RTU::enact_and_publish) publish every device after it's
+//! successfully updated or enacted.
+//!
+//! ```rust,no_run
+//! use brewdrivers::model::{mqtt::StatePublisher, RTU};
+//!
+//! # async fn run() -> brewdrivers::Result<()> {
+//! let publisher = StatePublisher::connect("mqtt://localhost:1883", "brewery")?;
+//! let mut rtu = RTU::generate(None)?;
+//! // Publishes on brewery/<rtu id>/<device id>
+//! rtu.update_and_publish(&publisher).await?;
+//! # Ok(())
+//! # }
+//! ```
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use async_trait::async_trait;
+use log::*;
+use rumqttc::{AsyncClient, MqttOptions, QoS};
+use thiserror::Error;
+use tokio::task::JoinHandle;
+
+use crate::model::Device;
+
+// The port brokers listen on if the URL doesn't have one
+const DEFAULT_MQTT_PORT: u16 = 1883;
+
+// How many publishers this process has connected, so each one gets its own client id
+static PUBLISHERS: AtomicU32 = AtomicU32::new(0);
+
+/// Errors publishing to the broker
+#[derive(Error, Debug)]
+pub enum MqttError {
+    #[error("Invalid broker URL `{0}`, expected something like `mqtt://localhost:1883`")]
+    BadUrl(String),
+
+    #[error("Couldn't serialize device `{0}`: {1}")]
+    Serialize(String, #[source] serde_json::Error),
+
+    #[error("Couldn't publish to `{topic}`: {message}")]
+    Publish { topic: String, message: String },
+}
+
+/// Something that can publish a payload on a topic. This is implemented for `rumqttc`'s client, and
+/// can be implemented on anything else, like a mock client for tests.
+#[async_trait]
+pub trait MqttClient: Send + Sync {
+    async fn publish(&self, topic: &str, payload: Vec<u8>) -> Result<(), MqttError>;
+}
+
+#[async_trait]
+impl MqttClient for AsyncClient {
+    async fn publish(&self, topic: &str, payload: Vec<u8>) -> Result<(), MqttError> {
+        AsyncClient::publish(self, topic, QoS::AtLeastOnce, false, payload)
+            .await
+            .map_err(|e| MqttError::Publish {
+                topic: topic.to_string(),
+                message: e.to_string(),
+            })
+    }
+}
+
+/// Publishes devices to an MQTT broker under a topic prefix
+pub struct StatePublisher {
+    client: Box<dyn MqttClient>,
+    prefix: String,
+    // The task driving the connection, if we started one. It's stopped when the publisher is dropped.
+    eventloop: Option<JoinHandle<()>>,
+}
+
+impl std::fmt::Debug for StatePublisher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StatePublisher")
+            .field("prefix", &self.prefix)
+            .finish()
+    }
+}
+
+impl StatePublisher {
+    /// Connects to the broker at `broker_url`, like `mqtt://localhost:1883`. The port defaults to 1883.
+    ///
+    /// The connection is driven by a background task, so this has to be called inside a tokio runtime.
+    /// Connection errors are logged and retried by that task, they don't show up here. The task is
+    /// stopped when the publisher is dropped.
+    pub fn connect(broker_url: &str, prefix: &str) -> Result<Self, MqttError> {
+        let (host, port) = parse_broker_url(broker_url)?;
+        let client_id = client_id();
+        let (client, mut eventloop) = AsyncClient::new(MqttOptions::new(client_id, host, port), 10);
+
+        let eventloop = tokio::spawn(async move {
+            loop {
+                if let Err(e) = eventloop.poll().await {
+                    warn!("[MQTT] connection error: {e}");
+                    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                }
+            }
+        });
+
+        let mut publisher = Self::with_client(client, prefix);
+        publisher.eventloop = Some(eventloop);
+        Ok(publisher)
+    }
+
+    /// Uses an already set up client instead of connecting
+    pub fn with_client<C: MqttClient + 'static>(client: C, prefix: &str) -> Self {
+        Self {
+            client: Box::new(client),
+            prefix: prefix.trim_end_matches('/').to_string(),
+            eventloop: None,
+        }
+    }
+
+    /// The topic a device is published on, `{prefix}/{rtu_id}/{device_id}`
+    pub fn topic(&self, rtu_id: &str, device_id: &str) -> String {
+        format!("{}/{}/{}", self.prefix, rtu_id, device_id)
+    }
+
+    /// Publishes the device as JSON on its topic
+    pub async fn publish(&self, rtu_id: &str, device: &Device) -> Result<(), MqttError> {
+        let payload =
+            serde_json::to_vec(device).map_err(|e| MqttError::Serialize(device.id.clone(), e))?;
+        let topic = self.topic(rtu_id, &device.id);
+        trace!("[MQTT] publishing `{}` on {topic}", device.id);
+        self.client.publish(&topic, payload).await
+    }
+}
+
+impl Drop for StatePublisher {
+    fn drop(&mut self) {
+        if let Some(eventloop) = self.eventloop.take() {
+            eventloop.abort();
+        }
+    }
+}
+
+// Brokers drop the older connection when two clients share an id, so every publisher gets its own
+fn client_id() -> String {
+    let publisher = PUBLISHERS.fetch_add(1, Ordering::Relaxed);
+    format!("brewdrivers-{}-{}", std::process::id(), publisher)
+}
+
+// Splits `mqtt://host:port` into its host and port
+fn parse_broker_url(url: &str) -> Result<(String, u16), MqttError> {
+    let bad_url = || MqttError::BadUrl(url.to_string());
+    let rest = url
+        .strip_prefix("mqtt://")
+        .or_else(|| url.strip_prefix("tcp://"))
+        .unwrap_or(url)
+        .trim_end_matches('/');
+
+    let (host, port) = match rest.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().map_err(|_| bad_url())?),
+        None => (rest, DEFAULT_MQTT_PORT),
+    };
+    if host.is_empty() || host.contains('/') {
+        return Err(bad_url());
+    }
+    Ok((host.to_string(), port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_broker_url() {
+        assert_eq!(
+            parse_broker_url("mqtt://localhost:1884").unwrap(),
+            (String::from("localhost"), 1884)
+        );
+        assert_eq!(
+            parse_broker_url("broker.local").unwrap(),
+            (String::from("broker.local"), 1883)
+        );
+        assert!(parse_broker_url("mqtt://localhost:port").is_err());
+        assert!(parse_broker_url("mqtt://").is_err());
+    }
+
+    #[test]
+    fn test_client_id_unique() {
+        let first = client_id();
+        let second = client_id();
+        assert_ne!(first, second);
+        assert!(first.starts_with(&format!("brewdrivers-{}-", std::process::id())));
+    }
+
+    #[tokio::test]
+    async fn test_drop_stops_eventloop() {
+        // Nothing listens on port 1, so the task keeps retrying until it's stopped
+        let mut publisher = StatePublisher::connect("mqtt://127.0.0.1:1", "brewery").unwrap();
+        let eventloop = publisher.eventloop.take().unwrap();
+        let abort = eventloop.abort_handle();
+        publisher.eventloop = Some(eventloop);
+        assert!(!abort.is_finished());
+
+        drop(publisher);
+        tokio::time::timeout(std::time::Duration::from_secs(1), async {
+            while !abort.is_finished() {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("the eventloop task should stop when the publisher is dropped");
+    }
+}
@@ -1,18 +1,25 @@
 use std::fs;
+use std::future::Future;
 use std::net::Ipv4Addr;
+use std::path::PathBuf;
+use std::time::Duration;
 
 use log::*;
 use serde::{Deserialize, Serialize};
 
+use crate::controllers::{Controller, Waveshare, WaveshareV2, STR1};
 use crate::drivers::InstrumentError;
+use crate::state::{BinaryState, DeviceState};
 
-use super::{validators, Device, ModelError};
+use super::device::Connection;
+use super::{validators, Device, ModelError, ValidatorOptions};
 
 /// A digital representation of an RTU.
 ///
 /// This is meant to be serialized from a configuration file. This is
 /// also the data structure that is sent between the iris server and the front-end
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct RTU {
     /// The RTU name, for display purposes
     pub name: String,
@@ -25,35 +32,549 @@ pub struct RTU {
     pub devices: Vec<Device>,
 }
 
+/// One row of an RTU's [`wiring_table`](crate::model::RTU::wiring_table), showing where a device is wired
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct WiringRow {
+    pub id: String,
+    pub name: String,
+    pub controller: Controller,
+    pub port: String,
+    pub controller_addr: u8,
+    /// The relay number or similar, 0 if the controller doesn't have one
+    pub addr: u8,
+}
+
+impl WiringRow {
+    /// The header line for [`to_csv`](crate::model::rtu::WiringRow::to_csv)
+    pub const CSV_HEADER: &'static str = "id,name,controller,port,controller_addr,addr";
+
+    /// Formats the row as a line of CSV, in the same order as [`CSV_HEADER`](crate::model::rtu::WiringRow::CSV_HEADER)
+    pub fn to_csv(&self) -> String {
+        // Names are free text, so they might need quoting
+        let escape = |field: &str| {
+            if field.contains([',', '"', '\n']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        };
+
+        format!(
+            "{},{},{},{},{},{}",
+            escape(&self.id),
+            escape(&self.name),
+            self.controller,
+            escape(&self.port),
+            self.controller_addr,
+            self.addr
+        )
+    }
+}
+
 impl RTU {
-    /// This calls [`Device::enact`](crate::model::Device::enact) on each device in the RTU.
-    /// Returns the first Err() encountered.
-    //
-    // TODO: Maybe collect errors and return a list of errors, if any?
+    /// This calls [`Device::enact`](crate::model::Device::enact) on each enabled device in the RTU.
+    /// Returns the first Err() encountered. See [`RTU::enact_all`](crate::model::RTU::enact_all) to
+    /// get every device's result instead.
     pub async fn enact(&mut self) -> Result<(), InstrumentError> {
         info!("[RTU `{}`] enacting...", self.id);
         for dev in self.devices.iter_mut() {
+            if !dev.enabled {
+                info!(
+                    "[RTU `{}`] device `{}` is disabled, not enacting",
+                    self.id, dev.id
+                );
+                continue;
+            }
             dev.enact().await?;
         }
         info!("[RTU `{}`] enacted.", self.id);
         Ok(())
     }
 
-    /// This calls [`Device::update`](crate::model::Device::update) on each device in the RTU
-    //
-    // TODO: Same as above, return a list off all errors, if any
+    /// Enacts only the devices whose state in `desired` is different from their current state, matching devices
+    /// by id. A changed device takes its state from `desired` before it's enacted. Devices missing from `desired`
+    /// and disabled devices are left alone.
+    ///
+    /// This compares against the state the RTU already has, so [`update`](crate::model::RTU::update) first if that
+    /// might be stale. Returns the first Err() encountered, like [`enact`](crate::model::RTU::enact).
+    pub async fn enact_changed(&mut self, desired: &RTU) -> Result<(), InstrumentError> {
+        info!("[RTU `{}`] enacting changed devices...", self.id);
+        for dev in self.devices.iter_mut() {
+            let Some(wanted) = desired.devices.iter().find(|d| d.id == dev.id) else {
+                continue;
+            };
+            if !dev.enabled || dev.state == wanted.state {
+                continue;
+            }
+
+            info!("[RTU `{}`] device `{}` changed, enacting", self.id, dev.id);
+            dev.state = wanted.state.clone();
+            dev.enact().await?;
+        }
+        info!("[RTU `{}`] enacted changed devices.", self.id);
+        Ok(())
+    }
+
+    /// This calls [`Device::update`](crate::model::Device::update) on each enabled device in the RTU.
+    /// Disabled devices keep whatever state they had. Returns the first Err() encountered, see
+    /// [`RTU::update_all`](crate::model::RTU::update_all) to get every device's result instead.
     pub async fn update(&mut self) -> Result<(), InstrumentError> {
         info!("[RTU `{}`] updating...", self.id);
         for dev in self.devices.iter_mut() {
+            if !dev.enabled {
+                info!(
+                    "[RTU `{}`] device `{}` is disabled, not updating",
+                    self.id, dev.id
+                );
+                continue;
+            }
             dev.update().await?;
         }
         info!("[RTU `{}`] updated.", self.id);
         Ok(())
     }
 
-    /// Returns an optional mutable borrow to a `Device`
-    pub fn device(&mut self, device_id: &str) -> Option<&mut Device> {
-        self.devices.iter_mut().find(|dev| dev.id == device_id)
+    /// Like [`RTU::update`](crate::model::RTU::update), but devices on different serial ports are updated at the
+    /// same time. Devices that share a port are still updated one at a time, in order, since the bus can't have
+    /// two conversations at once.
+    ///
+    /// Every enabled device is updated even if some fail. The first error, in device order, is returned.
+    pub async fn update_concurrent(&mut self) -> Result<(), InstrumentError> {
+        info!("[RTU `{}`] updating each port concurrently...", self.id);
+
+        let results = RTU::run_per_port(&mut self.devices, |mut dev: Device| async move {
+            if !dev.enabled {
+                info!("device `{}` is disabled, not updating", dev.id);
+                return (dev, Ok(()));
+            }
+            let result = dev.update().await;
+            (dev, result)
+        })
+        .await;
+
+        info!("[RTU `{}`] updated.", self.id);
+        results.into_iter().collect()
+    }
+
+    // STR1 and Waveshare boards do blocking serial I/O, so they need a thread of their own
+    fn blocks_thread(controller: Controller) -> bool {
+        matches!(
+            controller,
+            Controller::STR1 | Controller::Waveshare | Controller::WaveshareV2
+        )
+    }
+
+    // Runs `op` on a copy of every device, with one task per serial port. Devices on the same port go one
+    // after another. A port with a blocking controller on it runs on the blocking thread pool. Each copy is
+    // written back over its device when its port is done, and the results come back in device order.
+    //
+    // Nothing is taken out of `devices` while the tasks run. If this future is dropped, or a task panics,
+    // the devices keep the state they had. They only lose a cached connection.
+    async fn run_per_port<F, Fut>(devices: &mut [Device], op: F) -> Vec<Result<(), InstrumentError>>
+    where
+        F: Fn(Device) -> Fut + Clone + Send + 'static,
+        Fut: Future<Output = (Device, Result<(), InstrumentError>)> + Send + 'static,
+    {
+        let mut ports: Vec<Vec<(usize, Device)>> = vec![];
+        for (i, dev) in devices.iter_mut().enumerate() {
+            let copy = dev.detach();
+            match ports
+                .iter_mut()
+                .find(|port| port[0].1.conn.same_port(&copy.conn))
+            {
+                Some(port) => port.push((i, copy)),
+                None => ports.push(vec![(i, copy)]),
+            }
+        }
+
+        let handle = tokio::runtime::Handle::current();
+        let tasks: Vec<_> = ports
+            .into_iter()
+            .map(|port| {
+                let blocking = port
+                    .iter()
+                    .any(|(_, dev)| RTU::blocks_thread(dev.conn.controller));
+                let op = op.clone();
+                let work = async move {
+                    let mut results = Vec::with_capacity(port.len());
+                    for (i, dev) in port {
+                        results.push((i, op(dev).await));
+                    }
+                    results
+                };
+                match blocking {
+                    true => {
+                        let handle = handle.clone();
+                        tokio::task::spawn_blocking(move || handle.block_on(work))
+                    }
+                    false => tokio::spawn(work),
+                }
+            })
+            .collect();
+
+        let mut results: Vec<Option<Result<(), InstrumentError>>> =
+            devices.iter().map(|_| None).collect();
+        let mut panic = None;
+        for task in tasks {
+            match task.await {
+                Ok(port_results) => {
+                    for (i, (dev, result)) in port_results {
+                        devices[i] = dev;
+                        results[i] = Some(result);
+                    }
+                }
+                Err(e) => {
+                    panic.get_or_insert(e);
+                }
+            }
+        }
+        // The other ports are written back first, then the panic goes through
+        if let Some(e) = panic {
+            std::panic::resume_unwind(e.into_panic());
+        }
+        results.into_iter().flatten().collect()
+    }
+
+    /// Like [`RTU::update`](crate::model::RTU::update), but doesn't stop at the first error. Every device is
+    /// tried, and the result for each one is returned with its ID, in the same order as the devices in the
+    /// configuration. Disabled devices aren't updated, and get an `Ok(())`.
+    pub async fn update_all(&mut self) -> Vec<(String, Result<(), InstrumentError>)> {
+        info!("[RTU `{}`] updating all devices...", self.id);
+        let mut results = Vec::with_capacity(self.devices.len());
+        for dev in self.devices.iter_mut() {
+            let result = if dev.enabled {
+                dev.update().await
+            } else {
+                info!(
+                    "[RTU `{}`] device `{}` is disabled, not updating",
+                    self.id, dev.id
+                );
+                Ok(())
+            };
+            results.push((dev.id.clone(), result));
+        }
+        info!("[RTU `{}`] updated all devices.", self.id);
+        results
+    }
+
+    /// The same as [`RTU::update_all`](crate::model::RTU::update_all), but enacts each device
+    pub async fn enact_all(&mut self) -> Vec<(String, Result<(), InstrumentError>)> {
+        info!("[RTU `{}`] enacting all devices...", self.id);
+        let mut results = Vec::with_capacity(self.devices.len());
+        for dev in self.devices.iter_mut() {
+            let result = if dev.enabled {
+                dev.enact().await
+            } else {
+                info!(
+                    "[RTU `{}`] device `{}` is disabled, not enacting",
+                    self.id, dev.id
+                );
+                Ok(())
+            };
+            results.push((dev.id.clone(), result));
+        }
+        info!("[RTU `{}`] enacted all devices.", self.id);
+        results
+    }
+
+    /// Like [`RTU::update`](crate::model::RTU::update), but each device is published with the
+    /// [`StatePublisher`](crate::model::mqtt::StatePublisher) right after it's updated. A device that fails to
+    /// update isn't published, and stops the update like it normally would. Needs the `mqtt` feature.
+    #[cfg(feature = "mqtt")]
+    pub async fn update_and_publish(
+        &mut self,
+        publisher: &super::mqtt::StatePublisher,
+    ) -> crate::Result<()> {
+        info!("[RTU `{}`] updating and publishing...", self.id);
+        for dev in self.devices.iter_mut().filter(|dev| dev.enabled) {
+            dev.update().await?;
+            publisher.publish(&self.id, dev).await?;
+        }
+        info!("[RTU `{}`] updated and published.", self.id);
+        Ok(())
+    }
+
+    /// The same as [`RTU::update_and_publish`](crate::model::RTU::update_and_publish), but enacts each device
+    #[cfg(feature = "mqtt")]
+    pub async fn enact_and_publish(
+        &mut self,
+        publisher: &super::mqtt::StatePublisher,
+    ) -> crate::Result<()> {
+        info!("[RTU `{}`] enacting and publishing...", self.id);
+        for dev in self.devices.iter_mut().filter(|dev| dev.enabled) {
+            dev.enact().await?;
+            publisher.publish(&self.id, dev).await?;
+        }
+        info!("[RTU `{}`] enacted and published.", self.id);
+        Ok(())
+    }
+
+    /// Updates every relay device on one board with a single read of the whole board, instead of one
+    /// connection and read per device.
+    ///
+    /// The board is identified by its port and controller address. All devices on it must be on a relay board
+    /// (`STR1`, `Waveshare`, or `WaveshareV2`).
+    /// Disabled devices on the board are left alone.
+    pub fn update_board(&mut self, controller_addr: u8, port: &str) -> Result<(), InstrumentError> {
+        let devices: Vec<&mut Device> = self
+            .devices
+            .iter_mut()
+            .filter(|dev| {
+                dev.enabled
+                    && dev.conn.port() == port
+                    && dev.conn.controller_addr() == controller_addr
+            })
+            .collect();
+
+        let first = match devices.first() {
+            Some(dev) => dev,
+            None => {
+                warn!(
+                    "[RTU `{}`] no enabled devices on board at {} (addr {}), nothing to update",
+                    self.id, port, controller_addr
+                );
+                return Ok(());
+            }
+        };
+
+        info!(
+            "[RTU `{}`] updating {} devices on board at {} (addr {})",
+            self.id,
+            devices.len(),
+            port,
+            controller_addr
+        );
+
+        let states = match first.conn.controller() {
+            Controller::STR1 => STR1::try_from(&**first)?.get_all_relays()?,
+            Controller::Waveshare => Waveshare::try_from(&**first)?.get_all_relays()?,
+            Controller::WaveshareV2 => WaveshareV2::try_from(&**first)?.get_all_relays()?,
+            other => {
+                return Err(InstrumentError::serialError(
+                    format!("can't update a whole `{}` board at once", other),
+                    Some(controller_addr),
+                ))
+            }
+        };
+
+        RTU::distribute_relay_states(devices, &states)
+    }
+
+    /// Calls [`RTU::update_board`](crate::model::RTU::update_board) once for every relay board in the RTU.
+    /// Devices on other types of controllers aren't updated.
+    pub fn update_boards(&mut self) -> Result<(), InstrumentError> {
+        let mut boards: Vec<&Connection> = Vec::new();
+        for dev in &self.devices {
+            if dev.enabled
+                && matches!(
+                    dev.conn.controller(),
+                    Controller::STR1 | Controller::Waveshare | Controller::WaveshareV2
+                )
+                && !boards.iter().any(|board| board.same_board(&dev.conn))
+            {
+                boards.push(&dev.conn);
+            }
+        }
+
+        let boards: Vec<(u8, String)> = boards
+            .into_iter()
+            .map(|conn| (conn.controller_addr(), conn.port()))
+            .collect();
+        for (controller_addr, port) in boards {
+            self.update_board(controller_addr, &port)?;
+        }
+        Ok(())
+    }
+
+    // Sets each device's relay state from the statuses of the whole board it's on
+    fn distribute_relay_states(
+        devices: Vec<&mut Device>,
+        states: &[BinaryState],
+    ) -> Result<(), InstrumentError> {
+        for dev in devices {
+            match states.get(dev.conn.addr() as usize) {
+                Some(&state) => dev.state.relay_state = Some(state),
+                None => {
+                    return Err(InstrumentError::serialError(
+                        format!(
+                            "device `{}` is on relay {}, but the board only returned {} relays",
+                            dev.id,
+                            dev.conn.addr(),
+                            states.len()
+                        ),
+                        Some(dev.conn.controller_addr()),
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns a mutable borrow to a `Device`, or `ModelError::DeviceNotFound` if there
+    /// isn't one with the given id
+    pub fn device(&mut self, device_id: &str) -> Result<&mut Device, ModelError> {
+        self.devices
+            .iter_mut()
+            .find(|dev| dev.id == device_id)
+            .ok_or(ModelError::DeviceNotFound(device_id.to_string()))
+    }
+
+    /// Returns every device that uses the given controller type, in the order they're configured
+    pub fn devices_by_controller(&self, controller: Controller) -> Vec<&Device> {
+        self.devices
+            .iter()
+            .filter(|dev| dev.conn.controller == controller)
+            .collect()
+    }
+
+    /// The same as [`devices_by_controller`](crate::model::RTU::devices_by_controller), but the devices can be changed
+    pub fn devices_by_controller_mut(&mut self, controller: Controller) -> Vec<&mut Device> {
+        self.devices
+            .iter_mut()
+            .filter(|dev| dev.conn.controller == controller)
+            .collect()
+    }
+
+    /// Returns a row per device showing which controller, port, and address it's wired to.
+    /// This is meant for wiring documentation, see
+    /// [`wiring_csv`](crate::model::RTU::wiring_csv) to get it as a CSV table.
+    pub fn wiring_table(&self) -> Vec<WiringRow> {
+        self.devices
+            .iter()
+            .map(|dev| WiringRow {
+                id: dev.id.clone(),
+                name: dev.name.clone(),
+                controller: *dev.conn.controller(),
+                port: dev.conn.port(),
+                controller_addr: dev.conn.controller_addr(),
+                addr: dev.conn.addr(),
+            })
+            .collect()
+    }
+
+    /// The [`wiring_table`](crate::model::RTU::wiring_table) as CSV, with a header line
+    pub fn wiring_csv(&self) -> String {
+        let mut csv = String::from(WiringRow::CSV_HEADER);
+        for row in self.wiring_table() {
+            csv.push('\n');
+            csv.push_str(&row.to_csv());
+        }
+        csv.push('\n');
+        csv
+    }
+
+    /// Enacts a device, but first enacts everything it [`requires`](crate::model::Device::requires) if the
+    /// device is being turned on (`relay_state` is `On`). Requirements are enacted in order, and their own
+    /// requirements before them. If any of them fail, this stops and returns the error without enacting the device.
+    pub async fn enact_device(&mut self, device_id: &str) -> crate::Result<()> {
+        for (id, state) in self.enact_plan(device_id)? {
+            info!(
+                "[RTU `{}`] `{}` requires `{}` to be {}, enacting it first",
+                self.id, device_id, id, state
+            );
+            let required = self.device(&id)?;
+            required.state.relay_state = Some(state);
+            required.enact().await?;
+        }
+
+        self.device(device_id)?.enact().await?;
+        Ok(())
+    }
+
+    /// Returns the `(device id, state)` pairs that [`RTU::enact_device`](crate::model::RTU::enact_device) would
+    /// enact before the given device, in the order it would enact them. This is empty if the device isn't being
+    /// turned on or doesn't require anything.
+    pub fn enact_plan(&self, device_id: &str) -> Result<Vec<(String, BinaryState)>, ModelError> {
+        let device = self
+            .devices
+            .iter()
+            .find(|dev| dev.id == device_id)
+            .ok_or(ModelError::DeviceNotFound(device_id.to_string()))?;
+
+        let mut plan = Vec::new();
+        if device.state.relay_state == Some(BinaryState::On) {
+            self.plan_requirements(device, &mut vec![device_id], &mut plan)?;
+        }
+        Ok(plan)
+    }
+
+    // Depth first, so a requirement's own requirements come before it. `path` is the chain of
+    // devices we're in the middle of planning, if we see one of them again there's a cycle.
+    pub(crate) fn plan_requirements<'a>(
+        &'a self,
+        device: &'a Device,
+        path: &mut Vec<&'a str>,
+        plan: &mut Vec<(String, BinaryState)>,
+    ) -> Result<(), ModelError> {
+        for req in &device.requires {
+            if path.contains(&req.id.as_str()) {
+                return Err(ModelError::validation_error(
+                    &device.id,
+                    ("requires", &req.id),
+                    "device requirements cannot be circular",
+                ));
+            }
+
+            let required = self
+                .devices
+                .iter()
+                .find(|dev| dev.id == req.id)
+                .ok_or(ModelError::DeviceNotFound(req.id.clone()))?;
+
+            // Only turning something on needs its requirements
+            if req.state == BinaryState::On {
+                path.push(&required.id);
+                self.plan_requirements(required, path, plan)?;
+                path.pop();
+            }
+
+            if !plan.iter().any(|(id, _)| *id == req.id) {
+                plan.push((req.id.clone(), req.state));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns every device that has the given tag
+    pub fn devices_with_tag(&self, tag: &str) -> Vec<&Device> {
+        self.devices
+            .iter()
+            .filter(|dev| dev.tags.iter().any(|t| t == tag))
+            .collect()
+    }
+
+    /// Returns each device's id along with how often it should be polled.
+    /// Devices without a `poll_interval_ms` get `default`.
+    pub fn poll_intervals(&self, default: Duration) -> Vec<(&str, Duration)> {
+        self.devices
+            .iter()
+            .map(|dev| (dev.id.as_str(), dev.poll_interval(default)))
+            .collect()
+    }
+
+    /// Returns mutable borrows to several devices at once, in the same order as `ids`.
+    ///
+    /// This fails if any of the ids can't be found, or if an id is given more than once
+    /// (we can't hand out two mutable borrows to the same device).
+    pub fn devices_mut<'a>(&'a mut self, ids: &[&str]) -> Result<Vec<&'a mut Device>, ModelError> {
+        for (i, id) in ids.iter().enumerate() {
+            if ids[..i].contains(id) {
+                return Err(ModelError::DuplicateDevice(id.to_string()));
+            }
+        }
+
+        let mut found: Vec<Option<&'a mut Device>> = ids.iter().map(|_| None).collect();
+        for dev in self.devices.iter_mut() {
+            if let Some(pos) = ids.iter().position(|&id| id == dev.id) {
+                found[pos] = Some(dev);
+            }
+        }
+
+        found
+            .into_iter()
+            .zip(ids)
+            .map(|(dev, id)| dev.ok_or(ModelError::DeviceNotFound(id.to_string())))
+            .collect()
     }
 
     /// Reads the configuration file and builds an RTU from that. Note that while this method
@@ -63,9 +584,31 @@ impl RTU {
     ///
     /// This will fail if the RTU cannot be deserialized from the configuration file.
     ///
+    /// The file is read strictly, so a misspelled key is an error rather than being silently ignored.
+    /// See [`RTU::from_yaml`](crate::model::RTU::from_yaml).
+    ///
+    /// Before it's deserialized, any `${VAR}` in the file is replaced with the environment variable `VAR`,
+    /// so one config template can be used on several hosts (ie. `port: ${BREW_PORT}`). This fails if a
+    /// variable isn't set. See [`RTU::interpolate_env`](crate::model::RTU::interpolate_env).
+    ///
     /// This method calls [`RTU::validate()`](crate::model::RTU::validate) and returns an error if any of
     /// them don't succeed.
     pub fn generate(conf_path: Option<&str>) -> Result<RTU, ModelError> {
+        RTU::generate_with_options(conf_path, ValidatorOptions::default())
+    }
+
+    /// The same as [`RTU::generate`](crate::model::RTU::generate), but the validators turned off in `options`
+    /// are skipped. This is for development setups, like a virtual serial port outside of `/dev/`.
+    ///
+    /// ```rust,no_run
+    /// # use brewdrivers::model::{ValidatorOptions, RTU};
+    /// let options = ValidatorOptions { skip_dev_prefix: true, ..Default::default() };
+    /// let rtu = RTU::generate_with_options(None, options).unwrap();
+    /// ```
+    pub fn generate_with_options(
+        conf_path: Option<&str>,
+        options: ValidatorOptions,
+    ) -> Result<RTU, ModelError> {
         let file_path = conf_path.or(Some(crate::defaults::config_file()));
         info!("Generating RTU. Using config file: {:?}", file_path);
         // TODO: Get IPv4 here programatically instead of writing it in the file
@@ -77,20 +620,123 @@ impl RTU {
         )
         .map_err(|err| ModelError::IOError(err))?;
 
+        let file_contents = RTU::interpolate_env(&file_contents, |var| std::env::var(var).ok())?;
+
         // Deserialize the file. Return an Err if it doesn't succeed
-        let rtu = serde_yaml::from_str::<RTU>(&file_contents)
-            .map_err(|err| ModelError::SerdeParseError(err))?;
+        let rtu = RTU::from_yaml(&file_contents, true)?;
 
         info!("[RTU `{}`] generated.", rtu.id);
-        rtu.validate()?;
+        rtu.validate_with_options(options)?;
         Ok(rtu)
     }
 
+    /// Writes the RTU back to a configuration file as YAML, so changes (like a new controller number) can be saved.
+    /// Pass `None` to write to the default configuration file at [crate::defaults](crate::defaults).
+    ///
+    /// The whole file is replaced, so any comments or `${VAR}`s in it are lost. Device states aren't written,
+    /// they're read from the hardware at runtime and don't belong in the configuration.
+    pub fn write_to_file(&self, path: Option<PathBuf>) -> Result<(), ModelError> {
+        let path = path.unwrap_or_else(|| PathBuf::from(crate::defaults::config_file()));
+        info!("[RTU `{}`] writing to file: {:?}", self.id, path);
+
+        let mut config = self.clone();
+        for dev in config.devices.iter_mut() {
+            dev.state = DeviceState::default();
+        }
+        let contents = serde_yaml::to_string(&config).map_err(ModelError::SerdeParseError)?;
+        fs::write(&path, contents).map_err(ModelError::IOError)
+    }
+
+    /// Replaces every `${VAR}` in `contents` with `lookup(VAR)`. Returns `ModelError::EnvVarNotSet` if
+    /// `lookup` returns `None` for any of them. A `${` without a closing `}` on the same line is left alone,
+    /// and so are comments, so a commented out `${VAR}` doesn't have to be set.
+    ///
+    /// [`RTU::generate`](crate::model::RTU::generate) uses this with the environment as `lookup`.
+    ///
+    /// ```rust
+    /// # use brewdrivers::model::RTU;
+    /// let lookup = |var: &str| (var == "PORT").then(|| String::from("/dev/ttyUSB1"));
+    ///
+    /// let contents = RTU::interpolate_env("port: ${PORT}", lookup).unwrap();
+    /// assert_eq!(contents, "port: /dev/ttyUSB1");
+    ///
+    /// assert!(RTU::interpolate_env("port: ${NOT_SET}", lookup).is_err());
+    /// ```
+    pub fn interpolate_env<F>(contents: &str, lookup: F) -> Result<String, ModelError>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let mut output = String::with_capacity(contents.len());
+
+        for line in contents.split_inclusive('\n') {
+            let (mut rest, comment) = line.split_at(comment_start(line).unwrap_or(line.len()));
+
+            while let Some(start) = rest.find("${") {
+                let end = match rest[start..].find('}') {
+                    Some(end) => start + end,
+                    None => break,
+                };
+
+                let var = &rest[start + 2..end];
+                let value = lookup(var).ok_or(ModelError::EnvVarNotSet(var.to_string()))?;
+                output.push_str(&rest[..start]);
+                output.push_str(&value);
+                rest = &rest[end + 1..];
+            }
+
+            output.push_str(rest);
+            output.push_str(comment);
+        }
+        Ok(output)
+    }
+
+    /// Deserializes an RTU from a YAML string. This does not run the validators.
+    ///
+    /// If `strict` is `true`, any key that doesn't belong to the RTU model (like `timout` instead of `timeout`)
+    /// is an error, naming each bad key. Otherwise unknown keys are ignored, which tolerates configs written by a
+    /// newer version of this crate. Config files on disk should be read strictly, configs received over the
+    /// network usually shouldn't.
+    pub fn from_yaml(contents: &str, strict: bool) -> Result<RTU, ModelError> {
+        let (rtu, unknown_fields) = RTU::from_yaml_with_unknown_fields(contents)?;
+
+        if !unknown_fields.is_empty() {
+            if strict {
+                error!(
+                    "Unknown keys found in RTU configuration: {:?}",
+                    unknown_fields
+                );
+                return Err(ModelError::UnknownFields(unknown_fields));
+            }
+            warn!(
+                "Ignoring unknown keys in RTU configuration: {:?}",
+                unknown_fields
+            );
+        }
+
+        Ok(rtu)
+    }
+
+    // Deserializes an RTU, returning the path of every key that doesn't belong to the model along with it
+    pub(crate) fn from_yaml_with_unknown_fields(
+        contents: &str,
+    ) -> Result<(RTU, Vec<String>), ModelError> {
+        let deserializer = serde_yaml::Deserializer::from_str(contents);
+        let mut unknown_fields: Vec<String> = Vec::new();
+
+        let rtu: RTU =
+            serde_ignored::deserialize(deserializer, |path| unknown_fields.push(path.to_string()))
+                .map_err(|err| ModelError::SerdeParseError(err))?;
+        Ok((rtu, unknown_fields))
+    }
+
     /// Run all the [`validators`](crate::model::validators). Return an error if any of them don't succeed.
     pub fn validate(&self) -> Result<(), ModelError> {
-        use validators::*;
+        self.validate_with_options(ValidatorOptions::default())
+    }
 
-        if let Err(e) = all_validators(&self) {
+    /// Runs the validators like [`RTU::validate`](crate::model::RTU::validate), except the ones turned off in `options`
+    pub fn validate_with_options(&self, options: ValidatorOptions) -> Result<(), ModelError> {
+        if let Err(e) = validators::validators_with_options(&self, options) {
             error!("{e}");
             return Err(e);
         }
@@ -100,14 +746,757 @@ impl RTU {
     }
 }
 
+// Where the YAML comment on a line starts, if it has one. That's a `#` at the start of the line or after
+// whitespace, that isn't in a quoted string.
+fn comment_start(line: &str) -> Option<usize> {
+    let mut quote = None;
+    let mut prev: Option<char> = None;
+    for (i, c) in line.char_indices() {
+        let after_space = prev.is_none_or(|p| p.is_whitespace() || "[{,".contains(p));
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') if after_space => quote = Some(c),
+            (None, '#') if after_space => return Some(i),
+            _ => {}
+        }
+        prev = Some(c);
+    }
+    None
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     use tokio::test;
 
+    fn test_rtu() -> RTU {
+        serde_yaml::from_str(
+            r#"
+            name: Test RTU
+            id: test-rtu
+            ip_addr: 0.0.0.0
+            devices:
+              - id: pump
+                name: Pump
+                tags: [Mash, pumps]
+                poll_interval_ms: 250
+                conn:
+                  port: /dev/ttyUSB0
+                  baudrate: 9600
+                  timeout: 100
+                  controller: STR1
+                  controller_addr: 254
+                  addr: 0
+              - id: valve
+                name: Valve
+                tags: [Mash, valves]
+                conn:
+                  port: /dev/ttyUSB0
+                  baudrate: 9600
+                  timeout: 100
+                  controller: STR1
+                  controller_addr: 254
+                  addr: 1
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    async fn test_distribute_relay_states() {
+        let mut rtu = test_rtu();
+        // This is what one read of the board would give us
+        let states = vec![BinaryState::Off, BinaryState::On, BinaryState::Off];
+
+        let devices = rtu.devices.iter_mut().collect();
+        RTU::distribute_relay_states(devices, &states).unwrap();
+
+        assert_eq!(
+            rtu.device("pump").unwrap().state.relay_state,
+            Some(BinaryState::Off)
+        );
+        assert_eq!(
+            rtu.device("valve").unwrap().state.relay_state,
+            Some(BinaryState::On)
+        );
+
+        // The valve is on relay 1, which isn't in this response
+        let devices = rtu.devices.iter_mut().collect();
+        assert!(RTU::distribute_relay_states(devices, &[BinaryState::On]).is_err());
+    }
+
+    #[test]
+    async fn test_devices_with_tag() {
+        let rtu = test_rtu();
+
+        let ids =
+            |devices: Vec<&Device>| devices.iter().map(|dev| dev.id.clone()).collect::<Vec<_>>();
+        assert_eq!(ids(rtu.devices_with_tag("Mash")), vec!["pump", "valve"]);
+        assert_eq!(ids(rtu.devices_with_tag("pumps")), vec!["pump"]);
+        assert!(rtu.devices_with_tag("Boil").is_empty());
+        // Tags are case sensitive
+        assert!(rtu.devices_with_tag("mash").is_empty());
+    }
+
+    #[test]
+    async fn test_poll_intervals() {
+        let rtu = test_rtu();
+        let default = Duration::from_millis(1000);
+
+        assert_eq!(
+            rtu.poll_intervals(default),
+            vec![
+                ("pump", Duration::from_millis(250)),
+                // The valve doesn't set an interval, so it gets the default
+                ("valve", default),
+            ]
+        );
+    }
+
+    #[test]
+    async fn test_distribute_waveshare_states() {
+        let mut rtu: RTU = serde_yaml::from_str(
+            r#"
+            name: Test RTU
+            id: test-rtu
+            ip_addr: 0.0.0.0
+            devices:
+              - id: relay0
+                name: Relay 0
+                conn: { port: /dev/ttyUSB1, baudrate: 38400, timeout: 50, controller: WaveshareV2, controller_addr: 1, addr: 0 }
+              - id: relay3
+                name: Relay 3
+                conn: { port: /dev/ttyUSB1, baudrate: 38400, timeout: 50, controller: WaveshareV2, controller_addr: 1, addr: 3 }
+              - id: relay7
+                name: Relay 7
+                conn: { port: /dev/ttyUSB1, baudrate: 38400, timeout: 50, controller: WaveshareV2, controller_addr: 1, addr: 7 }
+            "#,
+        )
+        .unwrap();
+
+        // One get_all_relays() on the whole board, relays 3 and 7 on
+        let mut states = vec![BinaryState::Off; 8];
+        states[3] = BinaryState::On;
+        states[7] = BinaryState::On;
+
+        let devices = rtu.devices.iter_mut().collect();
+        RTU::distribute_relay_states(devices, &states).unwrap();
+
+        let relay_states: Vec<Option<BinaryState>> = rtu
+            .devices
+            .iter()
+            .map(|dev| dev.state.relay_state)
+            .collect();
+        assert_eq!(
+            relay_states,
+            vec![
+                Some(BinaryState::Off),
+                Some(BinaryState::On),
+                Some(BinaryState::On)
+            ]
+        );
+    }
+
+    #[test]
+    async fn test_disabled_devices_are_skipped() {
+        let mut rtu = test_rtu();
+        for dev in rtu.devices.iter_mut() {
+            dev.enabled = false;
+            dev.state.relay_state = Some(BinaryState::On);
+        }
+
+        // Nothing is talked to, so these succeed even without hardware
+        assert!(rtu.update().await.is_ok());
+        assert!(rtu.enact().await.is_ok());
+        assert!(rtu.update_boards().is_ok());
+
+        // and the state isn't refreshed
+        for dev in &rtu.devices {
+            assert_eq!(dev.state.relay_state, Some(BinaryState::On));
+        }
+    }
+
+    #[test]
+    async fn test_devices_enabled_by_default() {
+        let rtu = test_rtu();
+        assert!(rtu.devices.iter().all(|dev| dev.enabled));
+    }
+
+    #[test]
+    async fn test_wiring_table() {
+        let rtu = test_rtu();
+        let table = rtu.wiring_table();
+
+        assert_eq!(table.len(), 2);
+        assert_eq!(
+            table[1],
+            WiringRow {
+                id: String::from("valve"),
+                name: String::from("Valve"),
+                controller: Controller::STR1,
+                port: String::from("/dev/ttyUSB0"),
+                controller_addr: 254,
+                addr: 1,
+            }
+        );
+
+        assert_eq!(
+            rtu.wiring_csv(),
+            "id,name,controller,port,controller_addr,addr\n\
+             pump,Pump,STR1,/dev/ttyUSB0,254,0\n\
+             valve,Valve,STR1,/dev/ttyUSB0,254,1\n"
+        );
+    }
+
+    #[test]
+    async fn test_wiring_row_csv_quoting() {
+        let mut row = test_rtu().wiring_table().remove(0);
+        row.name = String::from("Pump, \"main\"");
+        assert_eq!(
+            row.to_csv(),
+            r#"pump,"Pump, ""main""",STR1,/dev/ttyUSB0,254,0"#
+        );
+    }
+
+    fn sequenced_rtu() -> RTU {
+        serde_yaml::from_str(
+            r#"
+            name: Test RTU
+            id: test-rtu
+            ip_addr: 0.0.0.0
+            devices:
+              - id: pump
+                name: Pump
+                requires:
+                  - id: valve
+                    state: On
+                conn: { port: /dev/ttyUSB0, baudrate: 9600, timeout: 100, controller: STR1, controller_addr: 254, addr: 0 }
+              - id: valve
+                name: Valve
+                requires:
+                  - id: drain
+                    state: Off
+                conn: { port: /dev/ttyUSB0, baudrate: 9600, timeout: 100, controller: STR1, controller_addr: 254, addr: 1 }
+              - id: drain
+                name: Drain
+                conn: { port: /dev/ttyUSB0, baudrate: 9600, timeout: 100, controller: STR1, controller_addr: 254, addr: 2 }
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    async fn test_enact_plan_opens_required_valve_first() {
+        let mut rtu = sequenced_rtu();
+        rtu.device("pump").unwrap().state.relay_state = Some(BinaryState::On);
+
+        assert_eq!(
+            rtu.enact_plan("pump").unwrap(),
+            vec![
+                (String::from("drain"), BinaryState::Off),
+                (String::from("valve"), BinaryState::On),
+            ]
+        );
+
+        // Turning the pump off doesn't need anything
+        rtu.device("pump").unwrap().state.relay_state = Some(BinaryState::Off);
+        assert!(rtu.enact_plan("pump").unwrap().is_empty());
+
+        assert!(matches!(
+            rtu.enact_plan("nothing"),
+            Err(ModelError::DeviceNotFound(_))
+        ));
+    }
+
+    #[test]
+    async fn test_enact_plan_rejects_cycles() {
+        let mut rtu = sequenced_rtu();
+        rtu.device("drain").unwrap().requires = vec![crate::model::device::Requirement {
+            id: String::from("pump"),
+            state: BinaryState::On,
+        }];
+        // The drain only needs to be off, so this isn't a cycle yet
+        rtu.device("pump").unwrap().state.relay_state = Some(BinaryState::On);
+        assert!(rtu.enact_plan("pump").is_ok());
+
+        rtu.device("valve").unwrap().requires[0].state = BinaryState::On;
+        assert!(rtu.enact_plan("pump").is_err());
+    }
+
+    // Three devices on ports that don't exist, the middle one disabled
+    fn unplugged_rtu() -> RTU {
+        serde_yaml::from_str(
+            r#"
+            name: Test RTU
+            id: test-rtu
+            ip_addr: 0.0.0.0
+            devices:
+              - id: first
+                name: First
+                command_retries: 0
+                conn: { port: /dev/doesntexist0, baudrate: 9600, timeout: 100, controller: STR1, controller_addr: 254, addr: 0 }
+              - id: second
+                name: Second
+                enabled: false
+                conn: { port: /dev/doesntexist1, baudrate: 9600, timeout: 100, controller: STR1, controller_addr: 254, addr: 0 }
+              - id: third
+                name: Third
+                command_retries: 0
+                conn: { port: /dev/doesntexist2, baudrate: 9600, timeout: 100, controller: STR1, controller_addr: 254, addr: 0 }
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    async fn test_update_all_collects_every_result() {
+        let mut rtu = unplugged_rtu();
+
+        // The regular update stops at the first device
+        assert!(rtu.update().await.is_err());
+
+        let results = rtu.update_all().await;
+        let ids: Vec<&str> = results.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, vec!["first", "second", "third"]);
+        assert!(results[0].1.is_err());
+        assert!(results[1].1.is_ok());
+        assert!(results[2].1.is_err());
+    }
+
+    #[test]
+    async fn test_enact_all_collects_every_result() {
+        let mut rtu = unplugged_rtu();
+        for dev in rtu.devices.iter_mut() {
+            dev.state.relay_state = Some(BinaryState::On);
+        }
+
+        let results = rtu.enact_all().await;
+        let ids: Vec<&str> = results.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, vec!["first", "second", "third"]);
+        assert!(results[0].1.is_err());
+        assert!(results[1].1.is_ok());
+        assert!(results[2].1.is_err());
+    }
+
+    #[test]
+    async fn test_enact_changed_only_enacts_differences() {
+        use crate::controllers::STR1;
+        use crate::drivers::mock::MockSerialInstrument;
+
+        // Everything written to the device's mock board after it connected
+        fn writes(dev: &mut Device) -> Vec<Vec<u8>> {
+            let board = dev.take_connection::<STR1<MockSerialInstrument>>().unwrap();
+            let written = board.mock().written()[1..].to_vec();
+            dev.attach_mock(board);
+            written
+        }
+
+        let mut rtu = unplugged_rtu();
+        for dev in rtu.devices.iter_mut() {
+            dev.attach_mock(STR1::connect_mock(254, 8).unwrap());
+        }
+
+        // Nothing changed, so nothing is written (and the empty states aren't enacted)
+        let mut desired = rtu.clone();
+        rtu.enact_changed(&desired).await.unwrap();
+        for dev in rtu.devices.iter_mut() {
+            assert!(writes(dev).is_empty(), "{}", dev.id);
+        }
+
+        // Disabled devices are skipped even if they changed
+        desired.device("second").unwrap().state.relay_state = Some(BinaryState::On);
+        rtu.enact_changed(&desired).await.unwrap();
+        assert!(writes(rtu.device("second").unwrap()).is_empty());
+
+        // Only the third device is enacted, and its relay is turned on
+        desired.device("third").unwrap().state.relay_state = Some(BinaryState::On);
+        rtu.enact_changed(&desired).await.unwrap();
+        assert!(writes(rtu.device("first").unwrap()).is_empty());
+        assert!(writes(rtu.device("second").unwrap()).is_empty());
+        let third = rtu.device("third").unwrap();
+        assert_eq!(third.state.relay_state, Some(BinaryState::On));
+        assert_eq!(writes(third).len(), 1);
+        let board = third
+            .take_connection::<STR1<MockSerialInstrument>>()
+            .unwrap();
+        assert!(board.mock().relays()[0]);
+
+        // Without its mock board the third device fails on its own port
+        desired.device("third").unwrap().state.relay_state = Some(BinaryState::Off);
+        let err = rtu.enact_changed(&desired).await.unwrap_err();
+        assert!(err.to_string().contains("/dev/doesntexist2"), "{err}");
+        assert!(writes(rtu.device("first").unwrap()).is_empty());
+    }
+
+    // Two devices on each of two ports
+    fn two_port_rtu() -> RTU {
+        let mut rtu = test_rtu();
+        let mut other_port = rtu.devices.clone();
+        for (i, dev) in other_port.iter_mut().enumerate() {
+            dev.id = format!("other-{}", i);
+            dev.conn.port = "/dev/ttyUSB1".into();
+        }
+        rtu.devices.extend(other_port);
+        rtu
+    }
+
+    #[test]
+    async fn test_run_per_port_overlaps_ports() {
+        use std::sync::{Arc, Mutex};
+        use std::time::Instant;
+
+        let mut rtu = two_port_rtu();
+
+        // Stands in for talking to the device, records when each one was busy. This blocks the
+        // thread like the serial drivers do, on a single threaded runtime.
+        let windows = Arc::new(Mutex::new(vec![]));
+        let recorded = windows.clone();
+        let results = RTU::run_per_port(&mut rtu.devices, move |mut dev: Device| {
+            let recorded = recorded.clone();
+            async move {
+                let start = Instant::now();
+                std::thread::sleep(Duration::from_millis(50));
+                recorded.lock().unwrap().push((
+                    dev.id.clone(),
+                    dev.conn.port(),
+                    start,
+                    Instant::now(),
+                ));
+                dev.state.relay_state = Some(BinaryState::On);
+                (dev, Ok(()))
+            }
+        })
+        .await;
+
+        // Everything comes back in order, with what the task did to it
+        assert_eq!(results.len(), 4);
+        let ids: Vec<&str> = rtu.devices.iter().map(|dev| dev.id.as_str()).collect();
+        assert_eq!(ids, vec!["pump", "valve", "other-0", "other-1"]);
+        assert!(rtu
+            .devices
+            .iter()
+            .all(|dev| dev.state.relay_state == Some(BinaryState::On)));
+
+        let windows = windows.lock().unwrap();
+        let window = |id: &str| {
+            let (_, _, start, end) = windows.iter().find(|w| w.0 == id).unwrap();
+            (*start, *end)
+        };
+        let overlap = |a: &str, b: &str| {
+            let (a, b) = (window(a), window(b));
+            a.0 < b.1 && b.0 < a.1
+        };
+        // Same port is one at a time
+        assert!(!overlap("pump", "valve"));
+        assert!(!overlap("other-0", "other-1"));
+        // Different ports run together
+        assert!(overlap("pump", "other-0"));
+        assert!(overlap("valve", "other-1"));
+    }
+
+    #[test]
+    async fn test_run_per_port_cancelled_keeps_devices() {
+        let mut rtu = two_port_rtu();
+        let before = rtu.devices.clone();
+
+        let slow = RTU::run_per_port(&mut rtu.devices, |mut dev: Device| async move {
+            std::thread::sleep(Duration::from_millis(100));
+            dev.state.relay_state = Some(BinaryState::On);
+            (dev, Ok(()))
+        });
+        assert!(tokio::time::timeout(Duration::from_millis(10), slow)
+            .await
+            .is_err());
+        assert_eq!(rtu.devices, before);
+    }
+
+    #[test]
+    async fn test_run_per_port_panic_keeps_devices() {
+        use futures::FutureExt;
+        use std::panic::AssertUnwindSafe;
+
+        let mut rtu = two_port_rtu();
+        let before = rtu.devices.clone();
+
+        let panicking = RTU::run_per_port(&mut rtu.devices, |mut dev: Device| async move {
+            if dev.id == "other-0" {
+                panic!("lost the port");
+            }
+            dev.state.relay_state = Some(BinaryState::On);
+            (dev, Ok(()))
+        });
+        assert!(AssertUnwindSafe(panicking).catch_unwind().await.is_err());
+
+        // The port that panicked is left alone, the other one is written back
+        assert_eq!(rtu.devices.len(), 4);
+        assert_eq!(rtu.devices[2..], before[2..]);
+        assert!(rtu.devices[..2]
+            .iter()
+            .all(|dev| dev.state.relay_state == Some(BinaryState::On)));
+    }
+
+    #[test]
+    async fn test_update_concurrent_keeps_devices() {
+        let mut rtu = unplugged_rtu();
+        let before = rtu.devices.clone();
+
+        assert!(rtu.update_concurrent().await.is_err());
+        assert_eq!(rtu.devices, before);
+    }
+
+    #[test]
+    async fn test_write_to_file_round_trip() {
+        let path =
+            std::env::temp_dir().join(format!("brewdrivers-rtu-{}.yaml", std::process::id()));
+
+        let mut rtu = test_rtu();
+        rtu.devices[1].conn.controller_addr = 2;
+        rtu.devices[1].state.relay_state = Some(BinaryState::On);
+        rtu.write_to_file(Some(path.clone())).unwrap();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let written = RTU::generate(path.to_str());
+        fs::remove_file(&path).unwrap();
+
+        // Everything but the state makes it back
+        assert!(!contents.contains("relay_state: On"), "{contents}");
+        rtu.devices[1].state = DeviceState::default();
+        assert_eq!(written.unwrap(), rtu);
+    }
+
+    #[test]
+    async fn test_device_lookup() {
+        let mut rtu = test_rtu();
+        assert!(rtu.device("pump").is_ok());
+        assert!(matches!(
+            rtu.device("doesnt-exist"),
+            Err(ModelError::DeviceNotFound(_))
+        ));
+    }
+
+    #[test]
+    async fn test_devices_by_controller() {
+        let mut rtu = test_rtu();
+
+        let ids: Vec<&str> = rtu
+            .devices_by_controller(Controller::STR1)
+            .iter()
+            .map(|dev| dev.id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["pump", "valve"]);
+        assert!(rtu.devices_by_controller(Controller::CN7500).is_empty());
+
+        for dev in rtu.devices_by_controller_mut(Controller::STR1) {
+            dev.state.relay_state = Some(BinaryState::On);
+        }
+        assert_eq!(
+            rtu.device("valve").unwrap().state.relay_state,
+            Some(BinaryState::On)
+        );
+
+        if crate::tests::test_config_available() {
+            let rtu = RTU::generate(Some(crate::defaults::test_config_file())).unwrap();
+            for controller in [Controller::STR1, Controller::CN7500, Controller::Waveshare] {
+                let devices = rtu.devices_by_controller(controller);
+                assert!(devices.iter().all(|dev| dev.conn.controller == controller));
+                assert_eq!(
+                    devices.len(),
+                    rtu.devices
+                        .iter()
+                        .filter(|dev| dev.conn.controller == controller)
+                        .count()
+                );
+            }
+        }
+    }
+
+    #[test]
+    async fn test_devices_mut() {
+        let mut rtu = test_rtu();
+
+        let mut devices = rtu.devices_mut(&["valve", "pump"]).unwrap();
+        assert_eq!(devices[0].id, "valve");
+        assert_eq!(devices[1].id, "pump");
+        devices[0].state.relay_state = Some(BinaryState::On);
+        devices[1].state.relay_state = Some(BinaryState::Off);
+
+        assert_eq!(
+            rtu.device("valve").unwrap().state.relay_state,
+            Some(BinaryState::On)
+        );
+        assert_eq!(
+            rtu.device("pump").unwrap().state.relay_state,
+            Some(BinaryState::Off)
+        );
+
+        assert!(matches!(
+            rtu.devices_mut(&["pump", "missing"]),
+            Err(ModelError::DeviceNotFound(_))
+        ));
+        assert!(matches!(
+            rtu.devices_mut(&["pump", "pump"]),
+            Err(ModelError::DuplicateDevice(_))
+        ));
+    }
+
+    #[test]
+    async fn test_from_yaml_strict_rejects_misspelled_field() {
+        let yaml = r#"
+            name: Test RTU
+            id: test-rtu
+            ip_addr: 0.0.0.0
+            devices:
+              - id: pump
+                name: Pump
+                comand_retries: 3
+                conn:
+                  port: /dev/ttyUSB0
+                  baudrate: 9600
+                  timeout: 100
+                  controller: STR1
+                  controller_addr: 254
+                  addr: 0
+            "#;
+
+        match RTU::from_yaml(yaml, true) {
+            Err(ModelError::UnknownFields(fields)) => {
+                assert_eq!(fields, vec!["devices.0.comand_retries"]);
+            }
+            other => panic!("expected an unknown field error, got {:?}", other),
+        }
+
+        // Lenient mode ignores it and uses the default
+        let rtu = RTU::from_yaml(yaml, false).unwrap();
+        assert_eq!(
+            rtu.devices[0].command_retries,
+            crate::defaults::default_command_retries()
+        );
+    }
+
+    #[test]
+    async fn test_generate_interpolates_env_vars() {
+        let conf = std::env::temp_dir().join("brewdrivers_test_env_conf.yaml");
+        fs::write(
+            &conf,
+            r#"
+            name: Test RTU
+            id: test-rtu
+            ip_addr: 0.0.0.0
+            devices:
+              - id: pump
+                name: Pump
+                conn:
+                  port: ${BREWDRIVERS_TEST_PORT}
+                  baudrate: 9600
+                  timeout: 100
+                  controller: STR1
+                  controller_addr: 254
+            "#,
+        )
+        .unwrap();
+
+        std::env::set_var("BREWDRIVERS_TEST_PORT", "/dev/ttyUSB3");
+        let rtu = RTU::generate(conf.to_str()).unwrap();
+        assert_eq!(rtu.devices[0].conn.port(), "/dev/ttyUSB3");
+
+        std::env::remove_var("BREWDRIVERS_TEST_PORT");
+        assert!(matches!(
+            RTU::generate(conf.to_str()),
+            Err(ModelError::EnvVarNotSet(var)) if var == "BREWDRIVERS_TEST_PORT"
+        ));
+
+        fs::remove_file(conf).unwrap();
+    }
+
+    #[test]
+    async fn test_generate_with_options() {
+        let conf = std::env::temp_dir().join("brewdrivers_test_options_conf.yaml");
+        fs::write(
+            &conf,
+            r#"
+            name: Test RTU
+            id: test-rtu
+            ip_addr: 0.0.0.0
+            devices:
+              - id: pump
+                name: Pump
+                conn:
+                  port: /tmp/ttyV0
+                  baudrate: 9600
+                  timeout: 100
+                  controller: STR1
+                  controller_addr: 254
+            "#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            RTU::generate(conf.to_str()),
+            Err(ModelError::ValidationError { key, .. }) if key == "port"
+        ));
+        let options = ValidatorOptions {
+            skip_dev_prefix: true,
+            ..Default::default()
+        };
+        let rtu = RTU::generate_with_options(conf.to_str(), options).unwrap();
+        assert_eq!(rtu.devices[0].conn.port(), "/tmp/ttyV0");
+
+        fs::remove_file(conf).unwrap();
+    }
+
+    #[test]
+    async fn test_interpolate_env() {
+        let lookup = |var: &str| match var {
+            "HOST" => Some(String::from("rtu1")),
+            "PORT" => Some(String::from("/dev/ttyUSB0")),
+            _ => None,
+        };
+
+        assert_eq!(
+            RTU::interpolate_env("id: ${HOST}-rtu\nport: ${PORT}", lookup).unwrap(),
+            "id: rtu1-rtu\nport: /dev/ttyUSB0"
+        );
+        // Nothing to replace
+        assert_eq!(
+            RTU::interpolate_env("port: /dev/ttyUSB0", lookup).unwrap(),
+            "port: /dev/ttyUSB0"
+        );
+        // Unclosed, left as is
+        assert_eq!(
+            RTU::interpolate_env("id: ${HOST", lookup).unwrap(),
+            "id: ${HOST"
+        );
+        assert!(RTU::interpolate_env("id: ${MISSING}", lookup).is_err());
+
+        // Comments aren't touched, even with a variable that isn't set
+        assert_eq!(
+            RTU::interpolate_env(
+                "# port: ${MISSING}\nport: ${PORT} # was ${MISSING}\n",
+                lookup
+            )
+            .unwrap(),
+            "# port: ${MISSING}\nport: /dev/ttyUSB0 # was ${MISSING}\n"
+        );
+        // A `#` in a quoted string or a word isn't a comment
+        assert_eq!(
+            RTU::interpolate_env("name: \"Pump #1 on ${HOST}\"\nid: pump#${HOST}", lookup).unwrap(),
+            "name: \"Pump #1 on rtu1\"\nid: pump#rtu1"
+        );
+    }
+
+    #[test]
+    async fn test_from_yaml_strict_accepts_valid_config() {
+        let rtu = RTU::from_yaml(&serde_yaml::to_string(&test_rtu()).unwrap(), true);
+        assert!(rtu.is_ok());
+    }
+
     #[test]
     async fn test_generate_rtu() {
+        if !crate::tests::test_config_available() {
+            return;
+        }
         let rtu = RTU::generate(Some(crate::defaults::test_config_file()));
         assert!(rtu.is_ok());
         assert!(rtu.unwrap().devices.len() > 0);
@@ -1,38 +1,110 @@
 //! Validators for when the RTU is deserialized from the config file
 //!
-//! These are called on the RTU and return an Err([ModelError](crate::model::ModelError)) if
-//! the RTU doesn't pass the test. It's another layer of validation on top of `serde_yaml`. This ensures
+//! These are called on the RTU and return a [ModelError](crate::model::ModelError) for each problem
+//! they find, so an RTU passes the test if there are none. It's another layer of validation on top of `serde_yaml`. This ensures
 //! the values in the RTU are actually correct, not just that it's valid YAML syntax.
 //!
 //! `serde` takes care of making sure the proper values are present; only values in an `Option<>` or that provide a default can be missing.
 
 use log::{error, info, warn};
 use std::collections::HashMap;
+use std::time::Duration;
 
+use crate::controllers::str1::STR1_MAX_RELAYS;
+use crate::controllers::waveshare::WAVESHARE_RELAY_COUNTS;
 use crate::controllers::Controller;
 
+use super::device::RetryBackoff;
 use super::{ModelError, RTU};
 
 // Note that when an RTU generates, if it recieves an error from one of these methods,
 // it will call log::error!() on it, then bubble up the error.
 
-pub fn all_validators(rtu: &RTU) -> Result<(), ModelError> {
-    devices_have_unique_ids(&rtu)?;
-    id_has_no_whitespace(&rtu)?;
-    serial_port_is_valid(&rtu)?;
-    controller_baudrate_is_valid(&rtu)?;
-    timeout_valid(&rtu)?;
-    command_retries_valid(&rtu)?;
-    retry_delay_valid(&rtu)?;
+/// Checks that can be turned off when an RTU is validated, see
+/// [`RTU::generate_with_options`](crate::model::RTU::generate_with_options). The default runs every check.
+///
+/// These are meant for development setups, like a virtual serial port from `socat`. Production configs
+/// should pass every check.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ValidatorOptions {
+    /// Allow serial ports outside of `/dev/`. Ports still can't be empty.
+    pub skip_dev_prefix: bool,
+    /// Don't check baudrates against the controller's valid baudrates
+    pub skip_baudrate: bool,
+    /// Don't check the minimum timeout
+    pub skip_timeout: bool,
+}
+
+type Validator = fn(&RTU) -> Vec<ModelError>;
+
+/// Runs every validator that isn't turned off in `options`, and returns the first problem found
+pub fn validators_with_options(rtu: &RTU, options: ValidatorOptions) -> Result<(), ModelError> {
+    for validator in validators(options) {
+        first_error(validator(rtu))?;
+    }
     Ok(())
 }
 
-/// Returns `Ok(())` if each device in the RTU has a unique ID
-pub fn devices_have_unique_ids(rtu: &RTU) -> Result<(), ModelError> {
+/// Runs every validator, but doesn't stop at the first one that fails. Every problem with every device
+/// is returned, in the order the validators run.
+pub fn all_validator_errors(rtu: &RTU) -> Vec<ModelError> {
+    validators(ValidatorOptions::default())
+        .into_iter()
+        .flat_map(|validator| validator(rtu))
+        .collect()
+}
+
+// The first of a validator's errors, for when one is enough
+fn first_error(errors: Vec<ModelError>) -> Result<(), ModelError> {
+    match errors.into_iter().next() {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+// Every validator that isn't turned off in `options`, in the order they run
+fn validators(options: ValidatorOptions) -> Vec<Validator> {
+    let mut validators: Vec<Validator> = vec![
+        devices_have_unique_ids as Validator,
+        device_names_unique,
+        id_has_no_whitespace,
+    ];
+    if options.skip_dev_prefix {
+        validators.push(|rtu| serial_ports_valid(rtu, false));
+    } else {
+        validators.push(serial_port_is_valid);
+    }
+    if options.skip_baudrate {
+        warn!("Skipping controller_baudrate_is_valid() validator");
+    } else {
+        validators.push(controller_baudrate_is_valid);
+    }
+    if options.skip_timeout {
+        warn!("Skipping timeout_valid() validator");
+    } else {
+        validators.push(timeout_valid);
+    }
+    validators.extend([
+        command_retries_valid as Validator,
+        retry_delay_valid,
+        retry_backoff_valid,
+        analog_output_config_valid,
+        waveshare_relay_count_valid,
+        device_addr_in_range,
+        tags_have_no_whitespace,
+        poll_interval_valid,
+        requirements_valid,
+    ]);
+    validators
+}
+
+/// Returns no errors if each device in the RTU has a unique ID
+pub fn devices_have_unique_ids(rtu: &RTU) -> Vec<ModelError> {
+    let mut errors = vec![];
     let mut seen: HashMap<&String, bool> = HashMap::new();
     for device in &rtu.devices {
         if seen.get(&device.id).is_some() {
-            return Err(ModelError::validation_error(
+            errors.push(ModelError::validation_error(
                 &device.id,
                 ("id", device.id.as_str()),
                 "devices must have unique IDs across all RTUs",
@@ -41,14 +113,46 @@ pub fn devices_have_unique_ids(rtu: &RTU) -> Result<(), ModelError> {
         seen.insert(&device.id, true);
     }
 
-    info!("RTU passed devices_have_unique_ids() validator");
-    Ok(())
+    if errors.is_empty() {
+        info!("RTU passed devices_have_unique_ids() validator");
+    }
+    errors
 }
 
-/// Returns `Ok(())` if the RTU ID and every device ID does not contain whitespace
-pub fn id_has_no_whitespace(rtu: &RTU) -> Result<(), ModelError> {
+/// Never returns an error, since names are only for display, but prints a `warn!()` for each pair of devices
+/// that share a name. Two devices both named "Pump" are confusing on a dashboard.
+pub fn device_names_unique(rtu: &RTU) -> Vec<ModelError> {
+    for (name, first, second) in duplicate_names(rtu) {
+        warn!(
+            "Devices `{}` and `{}` are both named \"{}\". Consider renaming one of them.",
+            first, second, name
+        );
+    }
+
+    info!("RTU passed device_names_unique() validator");
+    vec![]
+}
+
+// Returns `(name, first id, duplicate id)` for each device whose name was already used by an earlier device
+fn duplicate_names(rtu: &RTU) -> Vec<(&str, &str, &str)> {
+    let mut seen: HashMap<&str, &str> = HashMap::new();
+    let mut duplicates = Vec::new();
+    for device in &rtu.devices {
+        match seen.get(device.name.as_str()) {
+            Some(first) => duplicates.push((device.name.as_str(), *first, device.id.as_str())),
+            None => {
+                seen.insert(&device.name, &device.id);
+            }
+        }
+    }
+    duplicates
+}
+
+/// Returns no errors if the RTU ID and every device ID does not contain whitespace
+pub fn id_has_no_whitespace(rtu: &RTU) -> Vec<ModelError> {
+    let mut errors = vec![];
     if rtu.id.contains(char::is_whitespace) {
-        return Err(ModelError::validation_error(
+        errors.push(ModelError::validation_error(
             "RTU",
             ("id", &rtu.id),
             "RTU ID cannot contain whitespace",
@@ -57,7 +161,7 @@ pub fn id_has_no_whitespace(rtu: &RTU) -> Result<(), ModelError> {
 
     for dev in &rtu.devices {
         if dev.id.contains(char::is_whitespace) {
-            return Err(ModelError::validation_error(
+            errors.push(ModelError::validation_error(
                 &dev.id,
                 ("id", &dev.id),
                 "device ID cannot contain whitespace",
@@ -65,8 +169,10 @@ pub fn id_has_no_whitespace(rtu: &RTU) -> Result<(), ModelError> {
         }
     }
 
-    info!("RTU passed id_has_no_whitespace() validator");
-    Ok(())
+    if errors.is_empty() {
+        info!("RTU passed id_has_no_whitespace() validator");
+    }
+    errors
 }
 
 /// This will actually *not* fail if the serial port doesn't exist. Sometimes we disconnect
@@ -75,22 +181,29 @@ pub fn id_has_no_whitespace(rtu: &RTU) -> Result<(), ModelError> {
 ///
 /// This will however print a `warn!()` statement if the port doesn't exist, if a logger is configured.
 /// That will help if the brewer configures the wrong port or there's an electrical error.
-pub fn serial_port_is_valid(rtu: &RTU) -> Result<(), ModelError> {
+pub fn serial_port_is_valid(rtu: &RTU) -> Vec<ModelError> {
+    serial_ports_valid(rtu, true)
+}
+
+// `serial_port_is_valid`, but the `/dev/` requirement can be turned off
+fn serial_ports_valid(rtu: &RTU, require_dev: bool) -> Vec<ModelError> {
+    let mut errors = vec![];
     for dev in &rtu.devices {
         // If they somehow pass an empty string
         // maybe with port: "" in the config file
         if dev.conn.port().len() == 0 {
-            return Err(ModelError::validation_error(
+            errors.push(ModelError::validation_error(
                 &dev.id,
                 ("port", &dev.conn.port()),
                 "serial port cannot be empty",
             ));
+            continue;
         }
 
         let path = &dev.conn.port;
 
-        if !path.starts_with("/dev") {
-            return Err(ModelError::validation_error(
+        if require_dev && !path.starts_with("/dev") {
+            errors.push(ModelError::validation_error(
                 &dev.id,
                 ("port", &dev.conn.port()),
                 "port path must be in /dev/*",
@@ -109,127 +222,308 @@ pub fn serial_port_is_valid(rtu: &RTU) -> Result<(), ModelError> {
         }
     }
 
-    info!("RTU passed serial_port_is_valid() validator");
-    Ok(())
+    if errors.is_empty() {
+        info!("RTU passed serial_port_is_valid() validator");
+    }
+    errors
 }
 
-pub fn controller_baudrate_is_valid(rtu: &RTU) -> Result<(), ModelError> {
-    use crate::controllers::{
-        cn7500::CN7500_BAUDRATES, str1::STR1_BAUDRATES, wavesharev2::WAVESHAREV2_BAUDRATES,
-    };
+pub fn controller_baudrate_is_valid(rtu: &RTU) -> Vec<ModelError> {
+    let mut errors = vec![];
     for dev in &rtu.devices {
-        match dev.conn.controller() {
-            Controller::STR1 => {
-                if !STR1_BAUDRATES.contains(dev.conn.baudrate()) {
-                    return Err(ModelError::validation_error(
-                        &dev.id,
-                        ("baudrate", &format!("{}", dev.conn.baudrate())),
-                        "invalid baudrate for STR1 controller",
-                    ));
-                }
-            }
-            Controller::CN7500 => {
-                if !CN7500_BAUDRATES.contains(dev.conn.baudrate()) {
-                    return Err(ModelError::validation_error(
-                        &dev.id,
-                        ("baudrate", &format!("{}", dev.conn.baudrate())),
-                        "invalid baudrate for CN7500 controller",
-                    ));
-                }
-            }
-            Controller::Waveshare => {
-                // This uses the same baudrates as Version 2
-                if !WAVESHAREV2_BAUDRATES.contains(dev.conn.baudrate()) {
-                    return Err(ModelError::validation_error(
-                        &dev.id,
-                        ("baudrate", &format!("{}", dev.conn.baudrate())),
-                        "invalid baudrate for WaveshareV2 controller",
-                    ));
-                }
-            }
-            Controller::WaveshareV2 => {
-                if !WAVESHAREV2_BAUDRATES.contains(dev.conn.baudrate()) {
-                    return Err(ModelError::validation_error(
-                        &dev.id,
-                        ("baudrate", &format!("{}", dev.conn.baudrate())),
-                        "invalid baudrate for WaveshareV2 controller",
-                    ));
-                }
-            }
+        let controller = dev.conn.controller();
+        if !controller.valid_baudrates().contains(dev.conn.baudrate()) {
+            errors.push(ModelError::validation_error(
+                &dev.id,
+                ("baudrate", &format!("{}", dev.conn.baudrate())),
+                &format!("invalid baudrate for {} controller", controller),
+            ));
         }
     }
 
-    info!("RTU passed controller_baudrate_is_valid() validator");
-    Ok(())
+    if errors.is_empty() {
+        info!("RTU passed controller_baudrate_is_valid() validator");
+    }
+    errors
 }
 
-pub fn timeout_valid(rtu: &RTU) -> Result<(), ModelError> {
+pub fn timeout_valid(rtu: &RTU) -> Vec<ModelError> {
+    let mut errors = vec![];
     for dev in &rtu.devices {
-        match dev.conn.timeout {
-            // Not allowed
-            (0..=15) => {
-                return Err(ModelError::validation_error(
-                    &dev.id,
-                    ("timeout", &format!("{}ms", dev.conn.timeout)),
-                    "Timeout cannot be lower than 16 ms",
-                ));
-            }
-            // Allowed, but warn the user
-            (16..=35) => {
-                warn!(
-                    "Timeout for device `{}` with controller type `{}` is low. This *might* work,
-                    but you may experience device instability, especially under load.
-                    Consider raising your timeout to 30ms or 40ms",
-                    dev.name,
-                    dev.conn.controller()
-                );
-                info!("I've tested the following to be reasonbly stable:");
-                info!("STR1:\t\t17ms at baud 38400");
-                info!("CN7500:\t\t36ms at baud 19200");
-                info!("WaveshareV2:\t41ms at baud 38400");
-            }
-            _ => {}
+        // Not allowed
+        if dev.conn.timeout() <= Duration::from_millis(15) {
+            errors.push(ModelError::validation_error(
+                &dev.id,
+                ("timeout", &format!("{}ms", dev.conn.timeout().as_millis())),
+                "Timeout cannot be lower than 16 ms",
+            ));
+        }
+
+        // Allowed, but warn the user
+        let recommended = dev
+            .conn
+            .controller()
+            .recommended_timeout(*dev.conn.baudrate());
+        if dev.conn.timeout() < recommended {
+            warn!(
+                "Timeout for device `{}` with controller type `{}` is low. This *might* work,
+                but you may experience device instability, especially under load.
+                Consider raising your timeout to at least {}ms at baudrate {}",
+                dev.name,
+                dev.conn.controller(),
+                recommended.as_millis(),
+                dev.conn.baudrate()
+            );
         }
     }
 
-    info!("RTU passed timeout_valid() validator");
-    Ok(())
+    if errors.is_empty() {
+        info!("RTU passed timeout_valid() validator");
+    }
+    errors
 }
 
-pub fn command_retries_valid(rtu: &RTU) -> Result<(), ModelError> {
+pub fn command_retries_valid(rtu: &RTU) -> Vec<ModelError> {
+    let mut errors = vec![];
     for device in &rtu.devices {
         match device.command_retries {
             0..=5 => {}
-            _ => {
-                return Err(ModelError::validation_error(
-                    &device.id,
-                    ("command_retries", &format!("{}", device.command_retries)),
-                    "command retries must be in range [0, 6]",
-                ))
-            }
+            _ => errors.push(ModelError::validation_error(
+                &device.id,
+                ("command_retries", &format!("{}", device.command_retries)),
+                "command retries must be in range [0, 6]",
+            )),
         }
     }
 
-    info!("RTU passed command_retries_valid() validator");
-    Ok(())
+    if errors.is_empty() {
+        info!("RTU passed command_retries_valid() validator");
+    }
+    errors
 }
 
-pub fn retry_delay_valid(rtu: &RTU) -> Result<(), ModelError> {
+pub fn retry_delay_valid(rtu: &RTU) -> Vec<ModelError> {
+    let mut errors = vec![];
     for device in &rtu.devices {
-        if device.retry_delay <= device.conn.timeout || device.retry_delay >= 2000 {
-            return Err(ModelError::validation_error(
+        let retry_delay = Duration::from_millis(device.retry_delay);
+        if retry_delay <= device.conn.timeout() || device.retry_delay >= 2000 {
+            errors.push(ModelError::validation_error(
                 &device.id,
                 ("retry_delay", &format!("{}", device.retry_delay)),
                 &format!(
                     "retry delay for this device must be in the range [{}, 2000] (units in ms)",
-                    device.conn.timeout
+                    device.conn.timeout().as_millis()
                 ),
             ));
         }
     }
 
-    info!("RTU passed retry_delay_valid() validator");
-    Ok(())
+    if errors.is_empty() {
+        info!("RTU passed retry_delay_valid() validator");
+    }
+    errors
+}
+
+/// Returns no errors if every exponential `retry_backoff` starts above the device's timeout and
+/// stays under 2000ms, like `retry_delay`
+pub fn retry_backoff_valid(rtu: &RTU) -> Vec<ModelError> {
+    let mut errors = vec![];
+    for device in &rtu.devices {
+        if let Some(RetryBackoff::Exponential { base_ms, max_ms }) = device.retry_backoff {
+            if Duration::from_millis(base_ms) <= device.conn.timeout() || base_ms >= 2000 {
+                errors.push(ModelError::validation_error(
+                    &device.id,
+                    ("retry_backoff.base_ms", &format!("{}", base_ms)),
+                    &format!(
+                        "retry backoff base for this device must be in the range [{}, 2000] (units in ms)",
+                        device.conn.timeout().as_millis()
+                    ),
+                ));
+            }
+
+            if max_ms < base_ms || max_ms >= 2000 {
+                errors.push(ModelError::validation_error(
+                    &device.id,
+                    ("retry_backoff.max_ms", &format!("{}", max_ms)),
+                    &format!(
+                        "retry backoff max for this device must be in the range [{}, 2000] (units in ms)",
+                        base_ms
+                    ),
+                ));
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        info!("RTU passed retry_backoff_valid() validator");
+    }
+    errors
+}
+
+/// Returns no errors if every `AnalogOutput` device has `analog_output` settings with a usable range
+pub fn analog_output_config_valid(rtu: &RTU) -> Vec<ModelError> {
+    let mut errors = vec![];
+    for dev in &rtu.devices {
+        if *dev.conn.controller() != Controller::AnalogOutput {
+            continue;
+        }
+
+        match &dev.conn.analog_output {
+            None => errors.push(ModelError::validation_error(
+                &dev.id,
+                ("analog_output", "None"),
+                "AnalogOutput devices must provide analog_output connection settings",
+            )),
+            Some(config) if config.min >= config.max => errors.push(ModelError::validation_error(
+                &dev.id,
+                (
+                    "analog_output",
+                    &format!("min: {}, max: {}", config.min, config.max),
+                ),
+                "analog output min must be less than max",
+            )),
+            Some(_) => {}
+        }
+    }
+
+    if errors.is_empty() {
+        info!("RTU passed analog_output_config_valid() validator");
+    }
+    errors
+}
+
+/// Returns no errors if every Waveshare device's `relay_count` is one the boards come in
+pub fn waveshare_relay_count_valid(rtu: &RTU) -> Vec<ModelError> {
+    let mut errors = vec![];
+    for dev in &rtu.devices {
+        if !matches!(
+            dev.conn.controller(),
+            Controller::Waveshare | Controller::WaveshareV2
+        ) {
+            continue;
+        }
+
+        if !WAVESHARE_RELAY_COUNTS.contains(&dev.conn.relay_count()) {
+            errors.push(ModelError::validation_error(
+                &dev.id,
+                ("relay_count", &format!("{}", dev.conn.relay_count())),
+                &format!(
+                    "Waveshare relay count must be one of {:?}",
+                    WAVESHARE_RELAY_COUNTS
+                ),
+            ));
+        }
+    }
+
+    if errors.is_empty() {
+        info!("RTU passed waveshare_relay_count_valid() validator");
+    }
+    errors
+}
+
+/// Returns no errors if every relay device's `addr` is a relay on its board. Otherwise a device
+/// past the last relay would just read `Off` forever.
+///
+/// STR1 boards don't say how many relays they have until we talk to them, so this allows up to 16
+/// (an STR116) and prints a `warn!()` above 8, in case the board is an STR108.
+pub fn device_addr_in_range(rtu: &RTU) -> Vec<ModelError> {
+    let mut errors = vec![];
+    for dev in &rtu.devices {
+        let max = match dev.conn.controller() {
+            Controller::STR1 => STR1_MAX_RELAYS,
+            Controller::Waveshare | Controller::WaveshareV2 => dev.conn.relay_count(),
+            Controller::CN7500 | Controller::AnalogOutput => continue,
+        };
+
+        if dev.conn.addr() >= max {
+            errors.push(ModelError::validation_error(
+                &dev.id,
+                ("addr", &format!("{}", dev.conn.addr())),
+                &format!(
+                    "relay number must be in the range [0, {}) for a {} with {} relays",
+                    max,
+                    dev.conn.controller(),
+                    max
+                ),
+            ));
+            continue;
+        }
+
+        if *dev.conn.controller() == Controller::STR1 && dev.conn.addr() >= 8 {
+            warn!(
+                "Device `{}` is on relay {}, which only exists if the board is an STR116",
+                dev.id,
+                dev.conn.addr()
+            );
+        }
+    }
+
+    if errors.is_empty() {
+        info!("RTU passed device_addr_in_range() validator");
+    }
+    errors
+}
+
+/// Returns no errors if no device tag is empty or contains whitespace, same as IDs
+pub fn tags_have_no_whitespace(rtu: &RTU) -> Vec<ModelError> {
+    let mut errors = vec![];
+    for dev in &rtu.devices {
+        for tag in &dev.tags {
+            if tag.is_empty() || tag.contains(char::is_whitespace) {
+                errors.push(ModelError::validation_error(
+                    &dev.id,
+                    ("tags", tag),
+                    "device tags cannot be empty or contain whitespace",
+                ));
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        info!("RTU passed tags_have_no_whitespace() validator");
+    }
+    errors
+}
+
+/// Returns no errors if every device's poll interval, if it has one, is at least as long as its timeout.
+/// Polling faster than the device can respond would just pile up requests on the bus.
+pub fn poll_interval_valid(rtu: &RTU) -> Vec<ModelError> {
+    let mut errors = vec![];
+    for dev in &rtu.devices {
+        if let Some(interval) = dev.poll_interval_ms {
+            if Duration::from_millis(interval) < dev.conn.timeout() {
+                errors.push(ModelError::validation_error(
+                    &dev.id,
+                    ("poll_interval_ms", &interval.to_string()),
+                    &format!(
+                        "poll interval must be >= the device timeout ({} ms)",
+                        dev.conn.timeout().as_millis()
+                    ),
+                ));
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        info!("RTU passed poll_interval_valid() validator");
+    }
+    errors
+}
+
+/// Returns no errors if every device that a device [`requires`](crate::model::Device::requires) exists,
+/// and no device ends up requiring itself to be turned on.
+pub fn requirements_valid(rtu: &RTU) -> Vec<ModelError> {
+    let mut errors = vec![];
+    for dev in &rtu.devices {
+        if let Err(e) = rtu.plan_requirements(dev, &mut vec![dev.id.as_str()], &mut Vec::new()) {
+            errors.push(e);
+        }
+    }
+
+    if errors.is_empty() {
+        info!("RTU passed requirements_valid() validator");
+    }
+    errors
 }
 
 #[cfg(test)]
@@ -302,9 +596,9 @@ mod test_validators {
 
         let mut rtu = rtu("Testing RTU", "testing-id", devices);
 
-        assert_err!(devices_have_unique_ids(&rtu));
+        assert_err!(first_error(devices_have_unique_ids(&rtu)));
         rtu.devices.remove(1);
-        assert_ok!(devices_have_unique_ids(&rtu));
+        assert_ok!(first_error(devices_have_unique_ids(&rtu)));
     }
 
     #[test]
@@ -325,12 +619,37 @@ mod test_validators {
 
         let mut rtu = rtu("Testing RTU", "testing id with whitespace", devices);
 
-        assert_err!(id_has_no_whitespace(&rtu));
+        assert_err!(first_error(id_has_no_whitespace(&rtu)));
         rtu.devices[0].id = String::from("something-without-whitespace");
         // Still an error because the RTU id has whitespace
-        assert_err!(id_has_no_whitespace(&rtu));
+        assert_err!(first_error(id_has_no_whitespace(&rtu)));
         rtu.id = String::from("no-whitespace");
-        assert_ok!(id_has_no_whitespace(&rtu));
+        assert_ok!(first_error(id_has_no_whitespace(&rtu)));
+    }
+
+    #[test]
+    fn test_tags_have_no_whitespace() {
+        let devices = vec![device(
+            r#"
+                id: pump
+                name: Pump
+                tags: [Mash, mash pumps]
+                conn:
+                    port: /dev/ttyUSB0
+                    baudrate: 9600
+                    timeout: 100
+                    controller: STR1
+                    controller_addr: 254
+                    addr: 2
+            "#,
+        )];
+
+        let mut rtu = rtu("Testing RTU", "testing-rtu", devices);
+        assert_err!(first_error(tags_have_no_whitespace(&rtu)));
+        rtu.devices[0].tags[1] = String::new();
+        assert_err!(first_error(tags_have_no_whitespace(&rtu)));
+        rtu.devices[0].tags[1] = String::from("mash-pumps");
+        assert_ok!(first_error(tags_have_no_whitespace(&rtu)));
     }
 
     #[test]
@@ -352,7 +671,7 @@ mod test_validators {
 
         let mut rtu = rtu("testing RTU", "test-id", devices);
 
-        assert_ok!(serial_port_is_valid(&rtu));
+        assert_ok!(first_error(serial_port_is_valid(&rtu)));
 
         // This port definitely doesn't exist, but it's still valid
         rtu.devices.push(device(
@@ -369,7 +688,7 @@ mod test_validators {
         "#,
         ));
 
-        assert_ok!(serial_port_is_valid(&rtu));
+        assert_ok!(first_error(serial_port_is_valid(&rtu)));
 
         // This port is not valid (not in /dev)
         rtu.devices.push(device(
@@ -386,7 +705,42 @@ mod test_validators {
         "#,
         ));
 
-        assert_err!(serial_port_is_valid(&rtu));
+        assert_err!(first_error(serial_port_is_valid(&rtu)));
+    }
+
+    #[test]
+    fn test_validator_options() {
+        let devices = vec![device(
+            r#"
+                id: pump
+                name: Pump
+                conn:
+                    port: /tmp/ttyV0
+                    baudrate: 1234
+                    timeout: 10
+                    controller: STR1
+                    controller_addr: 254
+                    addr: 2
+            "#,
+        )];
+        let rtu = rtu("testing RTU", "test-id", devices);
+
+        assert_err!(validators_with_options(&rtu, ValidatorOptions::default()));
+        let mut options = ValidatorOptions {
+            skip_dev_prefix: true,
+            ..Default::default()
+        };
+        // Each check fails on its own until they're all off
+        assert_err!(validators_with_options(&rtu, options));
+        options.skip_baudrate = true;
+        assert_err!(validators_with_options(&rtu, options));
+        options.skip_timeout = true;
+        assert_ok!(validators_with_options(&rtu, options));
+
+        // An empty port is still caught
+        let mut empty_port = rtu.clone();
+        empty_port.devices[0].conn.port = std::path::PathBuf::new();
+        assert_err!(validators_with_options(&empty_port, options));
     }
 
     #[test]
@@ -407,7 +761,7 @@ mod test_validators {
 
         let mut rtu = rtu("testing RTU", "test-id", devices);
 
-        assert_ok!(controller_baudrate_is_valid(&rtu));
+        assert_ok!(first_error(controller_baudrate_is_valid(&rtu)));
 
         rtu.devices.push(device(
             r#"
@@ -423,7 +777,7 @@ mod test_validators {
         "#,
         ));
 
-        assert_err!(controller_baudrate_is_valid(&rtu));
+        assert_err!(first_error(controller_baudrate_is_valid(&rtu)));
     }
 
     #[test]
@@ -444,7 +798,7 @@ mod test_validators {
 
         let mut rtu = rtu("testing RTU", "test-id", devices);
 
-        assert_ok!(timeout_valid(&rtu));
+        assert_ok!(first_error(timeout_valid(&rtu)));
 
         // Timeout less than 20
         rtu.devices.push(device(
@@ -461,7 +815,7 @@ mod test_validators {
         "#,
         ));
 
-        assert_err!(timeout_valid(&rtu));
+        assert_err!(first_error(timeout_valid(&rtu)));
     }
 
     #[test]
@@ -512,13 +866,13 @@ mod test_validators {
         );
 
         let rtu1 = rtu("Valid RTU", "testing-id", vec![valid_device]);
-        assert_ok!(command_retries_valid(&rtu1));
+        assert_ok!(first_error(command_retries_valid(&rtu1)));
 
         let rtu2 = rtu("Invalid RTU", "testing-id", vec![invalid_device]);
-        assert_err!(command_retries_valid(&rtu2));
+        assert_err!(first_error(command_retries_valid(&rtu2)));
 
         let rtu3 = rtu("Invalid RTU", "testing-id", vec![undeserializable_device]);
-        assert_err!(command_retries_valid(&rtu3));
+        assert_err!(first_error(command_retries_valid(&rtu3)));
     }
 
     #[test]
@@ -554,9 +908,296 @@ mod test_validators {
         );
 
         let rtu1 = rtu("Valid RTU", "testing-id", vec![valid_device]);
-        assert_ok!(retry_delay_valid(&rtu1));
+        assert_ok!(first_error(retry_delay_valid(&rtu1)));
 
         let rtu2 = rtu("Invalid RTU", "testing-id", vec![invalid_device]);
-        assert_err!(retry_delay_valid(&rtu2));
+        assert_err!(first_error(retry_delay_valid(&rtu2)));
+    }
+
+    #[test]
+    fn test_retry_backoff_valid() {
+        let device_with = |backoff: &str| {
+            device(&format!(
+                r#"
+                id: pump
+                name: pump
+                retry_backoff: {backoff}
+                conn:
+                    port: /dev/ttyUSB0
+                    baudrate: 9600
+                    timeout: 15
+                    controller: STR1
+                    controller_addr: 254
+                    addr: 2
+                "#
+            ))
+        };
+
+        let valid = [
+            "{ strategy: Constant }",
+            "{ strategy: Exponential, base_ms: 100, max_ms: 1500 }",
+            "{ strategy: Exponential, base_ms: 100, max_ms: 100 }",
+        ];
+        for backoff in valid {
+            let rtu1 = rtu("Valid RTU", "testing-id", vec![device_with(backoff)]);
+            assert_ok!(first_error(retry_backoff_valid(&rtu1)));
+        }
+
+        let invalid = [
+            // base under the timeout
+            "{ strategy: Exponential, base_ms: 10, max_ms: 1500 }",
+            // max under the base
+            "{ strategy: Exponential, base_ms: 500, max_ms: 100 }",
+            // max too long
+            "{ strategy: Exponential, base_ms: 100, max_ms: 5000 }",
+        ];
+        for backoff in invalid {
+            let rtu2 = rtu("Invalid RTU", "testing-id", vec![device_with(backoff)]);
+            assert_err!(first_error(retry_backoff_valid(&rtu2)));
+        }
+    }
+
+    #[test]
+    fn test_analog_output_config_valid() {
+        let valid_device = device(
+            r#"
+            id: valve
+            name: Valve
+            conn:
+                port: /dev/ttyUSB0
+                baudrate: 19200
+                timeout: 50
+                controller: AnalogOutput
+                controller_addr: 3
+                analog_output:
+                    register: 0
+                    min: 0
+                    max: 4095
+            "#,
+        );
+
+        let missing_config = device(
+            r#"
+            id: valve
+            name: Valve
+            conn:
+                port: /dev/ttyUSB0
+                baudrate: 19200
+                timeout: 50
+                controller: AnalogOutput
+                controller_addr: 3
+            "#,
+        );
+
+        let backwards_range = device(
+            r#"
+            id: valve
+            name: Valve
+            conn:
+                port: /dev/ttyUSB0
+                baudrate: 19200
+                timeout: 50
+                controller: AnalogOutput
+                controller_addr: 3
+                analog_output:
+                    register: 0
+                    min: 4095
+                    max: 0
+            "#,
+        );
+
+        let rtu1 = rtu("Valid RTU", "testing-id", vec![valid_device]);
+        assert_ok!(first_error(analog_output_config_valid(&rtu1)));
+
+        let rtu2 = rtu("Invalid RTU", "testing-id", vec![missing_config]);
+        assert_err!(first_error(analog_output_config_valid(&rtu2)));
+
+        let rtu3 = rtu("Invalid RTU", "testing-id", vec![backwards_range]);
+        assert_err!(first_error(analog_output_config_valid(&rtu3)));
+    }
+
+    #[test]
+    fn test_waveshare_relay_count_valid() {
+        let relay = |relay_count: &str, addr: u8| {
+            device(&format!(
+                r#"
+                id: relay
+                name: relay
+                conn:
+                    port: /dev/ttyUSB0
+                    baudrate: 9600
+                    timeout: 15
+                    controller: WaveshareV2
+                    controller_addr: 1
+                    addr: {addr}
+                    {relay_count}
+                "#
+            ))
+        };
+
+        // Defaults to 8
+        let rtu1 = rtu("Valid RTU", "testing-id", vec![relay("", 7)]);
+        assert_ok!(first_error(waveshare_relay_count_valid(&rtu1)));
+        let rtu1 = rtu(
+            "Valid RTU",
+            "testing-id",
+            vec![relay("relay_count: 32", 20)],
+        );
+        assert_ok!(first_error(waveshare_relay_count_valid(&rtu1)));
+
+        let rtu2 = rtu(
+            "Invalid RTU",
+            "testing-id",
+            vec![relay("relay_count: 12", 0)],
+        );
+        assert_err!(first_error(waveshare_relay_count_valid(&rtu2)));
+    }
+
+    #[test]
+    fn test_device_addr_in_range() {
+        let relay = |controller: &str, addr: u8, relay_count: &str| {
+            device(&format!(
+                r#"
+                id: relay
+                name: relay
+                conn:
+                    port: /dev/ttyUSB0
+                    baudrate: 9600
+                    timeout: 15
+                    controller: {controller}
+                    controller_addr: 1
+                    addr: {addr}
+                    {relay_count}
+                "#
+            ))
+        };
+
+        let valid = vec![
+            relay("STR1", 7, ""),
+            // Only on an STR116, but that's just a warning
+            relay("STR1", 15, ""),
+            relay("Waveshare", 7, ""),
+            relay("WaveshareV2", 31, "relay_count: 32"),
+            // addr isn't a relay number for these
+            relay("CN7500", 20, ""),
+        ];
+        for dev in valid {
+            let rtu1 = rtu("Valid RTU", "testing-id", vec![dev]);
+            assert_ok!(first_error(device_addr_in_range(&rtu1)));
+        }
+
+        let invalid = vec![
+            relay("STR1", 16, ""),
+            relay("STR1", 20, ""),
+            relay("Waveshare", 8, ""),
+            relay("WaveshareV2", 16, "relay_count: 16"),
+        ];
+        for dev in invalid {
+            let rtu2 = rtu("Invalid RTU", "testing-id", vec![dev]);
+            assert_err!(first_error(device_addr_in_range(&rtu2)));
+        }
+    }
+
+    #[test]
+    fn test_poll_interval_valid() {
+        let mut dev = device(
+            r#"
+            id: thermometer
+            name: thermometer
+            poll_interval_ms: 5000
+            conn:
+                port: /dev/ttyUSB0
+                baudrate: 19200
+                timeout: 50
+                controller: CN7500
+                controller_addr: 22
+            "#,
+        );
+
+        let mut rtu1 = rtu("Valid RTU", "testing-id", vec![dev.clone()]);
+        assert_ok!(first_error(poll_interval_valid(&rtu1)));
+
+        // No interval is fine too
+        rtu1.devices[0].poll_interval_ms = None;
+        assert_ok!(first_error(poll_interval_valid(&rtu1)));
+
+        dev.poll_interval_ms = Some(20);
+        let rtu2 = rtu("Invalid RTU", "testing-id", vec![dev]);
+        assert_err!(first_error(poll_interval_valid(&rtu2)));
+    }
+
+    #[test]
+    fn test_device_names_unique() {
+        let pump = device(
+            r#"
+            id: pump1
+            name: Pump
+            conn:
+                port: /dev/ttyUSB0
+                baudrate: 9600
+                timeout: 100
+                controller: STR1
+                controller_addr: 254
+                addr: 0
+            "#,
+        );
+        let mut other = pump.clone();
+        other.id = String::from("pump2");
+
+        let rtu1 = rtu("Valid RTU", "testing-id", vec![pump.clone(), other.clone()]);
+        assert_eq!(duplicate_names(&rtu1), vec![("Pump", "pump1", "pump2")]);
+        // Only a warning
+        assert_ok!(first_error(device_names_unique(&rtu1)));
+
+        other.name = String::from("Other Pump");
+        let rtu2 = rtu("Valid RTU", "testing-id", vec![pump, other]);
+        assert!(duplicate_names(&rtu2).is_empty());
+        assert_ok!(first_error(device_names_unique(&rtu2)));
+    }
+
+    #[test]
+    fn test_requirements_valid() {
+        let pump = device(
+            r#"
+            id: pump
+            name: pump
+            requires:
+              - id: valve
+                state: On
+            conn:
+                port: /dev/ttyUSB0
+                baudrate: 9600
+                timeout: 100
+                controller: STR1
+                controller_addr: 254
+                addr: 0
+            "#,
+        );
+        let mut valve = device(
+            r#"
+            id: valve
+            name: valve
+            conn:
+                port: /dev/ttyUSB0
+                baudrate: 9600
+                timeout: 100
+                controller: STR1
+                controller_addr: 254
+                addr: 1
+            "#,
+        );
+
+        let rtu1 = rtu("Valid RTU", "testing-id", vec![pump.clone(), valve.clone()]);
+        assert_ok!(first_error(requirements_valid(&rtu1)));
+
+        // The valve doesn't exist
+        let rtu2 = rtu("Invalid RTU", "testing-id", vec![pump.clone()]);
+        assert_err!(first_error(requirements_valid(&rtu2)));
+
+        // The pump and valve require each other
+        valve.requires = pump.requires.clone();
+        valve.requires[0].id = String::from("pump");
+        let rtu3 = rtu("Invalid RTU", "testing-id", vec![pump, valve]);
+        assert_err!(first_error(requirements_valid(&rtu3)));
     }
 }
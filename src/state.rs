@@ -3,6 +3,8 @@ use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 use thiserror::Error;
 
+use crate::controllers::cn7500::Degree;
+
 /// A process value, alias to `f64`
 pub type PV = f64;
 /// A setpoint value, alias to `f64`
@@ -13,12 +15,18 @@ pub type SV = f64;
 /// A generalized state that is attached to all `Device`s
 ///
 /// Note that each controller uses a different set of these values. For example,
-/// a relay board uses `relay_state` but won't ever touch `pv` or `sv`.
+/// a relay board uses `relay_state` but won't ever touch `pv` or `sv`. Only analog
+/// outputs use `output_pct`.
 #[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct DeviceState {
     pub relay_state: Option<BinaryState>,
     pub pv: Option<PV>,
     pub sv: Option<SV>,
+    /// Output percentage (0-100) for analog outputs, like a proportional valve or a
+    /// variable speed pump. This is the state for anything that isn't just on or off.
+    #[serde(default)]
+    pub output_pct: Option<f64>,
 }
 
 impl Default for DeviceState {
@@ -30,7 +38,8 @@ impl Default for DeviceState {
     /// DeviceState {
     ///     relay_state: Some(BinaryState::Off),
     ///     pv: Some(0.0),
-    ///     sv: Some(0.0)
+    ///     sv: Some(0.0),
+    ///     output_pct: None
     /// }
     /// ```
     fn default() -> Self {
@@ -38,7 +47,95 @@ impl Default for DeviceState {
             relay_state: Default::default(),
             pv: Default::default(),
             sv: Default::default(),
+            output_pct: Default::default(),
+        }
+    }
+}
+
+impl DeviceState {
+    /// Formats the pv and sv with one decimal and the degree unit, for display in logs or the UI.
+    /// Missing values are shown as `-`.
+    ///
+    /// ```rust
+    /// # use brewdrivers::state::DeviceState;
+    /// # use brewdrivers::controllers::cn7500::Degree;
+    /// let mut state = DeviceState::default();
+    /// state.pv = Some(152.0);
+    /// state.sv = Some(155.25);
+    /// assert_eq!(state.format_temps(Degree::Fahrenheit), "pv: 152.0°F, sv: 155.2°F");
+    ///
+    /// state.pv = Some(66.7);
+    /// state.sv = Some(68.0);
+    /// assert_eq!(state.format_temps(Degree::Celsius), "pv: 66.7°C, sv: 68.0°C");
+    ///
+    /// // Relays don't have temperatures
+    /// let state = DeviceState::default();
+    /// assert_eq!(state.format_temps(Degree::Fahrenheit), "pv: -, sv: -");
+    /// ```
+    pub fn format_temps(&self, unit: Degree) -> String {
+        let symbol = match unit {
+            Degree::Fahrenheit => "°F",
+            Degree::Celsius => "°C",
+        };
+        let format = |temp: Option<f64>| match temp {
+            Some(temp) => format!("{temp:.1}{symbol}"),
+            None => String::from("-"),
+        };
+        format!("pv: {}, sv: {}", format(self.pv), format(self.sv))
+    }
+
+    /// Returns `Ok(())` if the state values are safe to write to a controller.
+    ///
+    /// This rejects `pv` or `sv` values that are `NaN` or infinite, which could
+    /// otherwise be scaled into a garbage register value, and an `output_pct` outside of 0-100.
+    ///
+    /// ```rust
+    /// # use brewdrivers::state::DeviceState;
+    /// let mut state = DeviceState::default();
+    /// state.sv = Some(152.0);
+    /// assert!(state.validate().is_ok());
+    ///
+    /// state.sv = Some(f64::NAN);
+    /// assert!(state.validate().is_err());
+    /// ```
+    pub fn validate(&self) -> Result<(), StateError> {
+        if let Some(pv) = self.pv {
+            if !pv.is_finite() {
+                return Err(StateError::NonFinite {
+                    field: "pv",
+                    value: pv,
+                });
+            }
+        }
+
+        if let Some(sv) = self.sv {
+            if !sv.is_finite() {
+                return Err(StateError::NonFinite {
+                    field: "sv",
+                    value: sv,
+                });
+            }
+        }
+
+        if let Some(pct) = self.output_pct {
+            if !pct.is_finite() {
+                return Err(StateError::NonFinite {
+                    field: "output_pct",
+                    value: pct,
+                });
+            }
+
+            if !(0.0..=100.0).contains(&pct) {
+                return Err(StateError::OutOfRange {
+                    field: "output_pct",
+                    value: pct,
+                    min: 0.0,
+                    max: 100.0,
+                });
+            }
         }
+
+        Ok(())
     }
 }
 
@@ -52,10 +149,23 @@ pub enum StateError {
     BadValue(DeviceState),
     #[error("State found to be null")]
     NullState,
+    #[error("State value `{field}` must be a finite number, found `{value}`")]
+    NonFinite { field: &'static str, value: f64 },
+    #[error("State value `{field}` must be in the range [{min}, {max}], found `{value}`")]
+    OutOfRange {
+        field: &'static str,
+        value: f64,
+        min: f64,
+        max: f64,
+    },
 }
 
 /// A binary state, as used in a relay or similar. This can be 'On' or 'Off'.
+///
+/// The canonical string form is title case (`On`/`Off`). That's what `Display` and serde produce.
+/// Use [`to_lowercase_str`](crate::state::BinaryState::to_lowercase_str) if you need `on`/`off`.
 #[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum BinaryState {
     On,
     Off,
@@ -90,6 +200,81 @@ impl FromStr for BinaryState {
     }
 }
 
+impl BinaryState {
+    /// Like [`FromStr`](std::str::FromStr), but also accepts `1`/`0` and `true`/`false`.
+    ///
+    /// Only use this at the network boundary where you know the value is a relay state.
+    /// The front-end sometimes sends booleans or numbers instead of `On`/`Off`. Everywhere
+    /// else, use the strict `FromStr` so we don't confuse binary states with stepped states.
+    ///
+    /// ```rust
+    /// use brewdrivers::state::BinaryState;
+    ///
+    /// assert_eq!(BinaryState::parse_loose("1").unwrap(), BinaryState::On);
+    /// assert_eq!(BinaryState::parse_loose("true").unwrap(), BinaryState::On);
+    /// assert_eq!(BinaryState::parse_loose("0").unwrap(), BinaryState::Off);
+    /// assert_eq!(BinaryState::parse_loose("false").unwrap(), BinaryState::Off);
+    /// assert_eq!(BinaryState::parse_loose("On").unwrap(), BinaryState::On);
+    ///
+    /// assert!(BinaryState::parse_loose("2").is_err());
+    /// ```
+    pub fn parse_loose(s: &str) -> Result<Self, StateError> {
+        match s {
+            "1" | "true" | "True" | "TRUE" => Ok(BinaryState::On),
+            "0" | "false" | "False" | "FALSE" => Ok(BinaryState::Off),
+            _ => BinaryState::from_str(s),
+        }
+    }
+
+    /// The canonical title case form, `"On"` or `"Off"`. This is what `Display` uses.
+    ///
+    /// ```rust
+    /// # use brewdrivers::state::BinaryState;
+    /// assert_eq!(BinaryState::On.to_titlecase_str(), "On");
+    /// ```
+    pub fn to_titlecase_str(&self) -> &'static str {
+        match self {
+            BinaryState::On => "On",
+            BinaryState::Off => "Off",
+        }
+    }
+
+    /// The opposite state, the same as `!state`
+    ///
+    /// ```rust
+    /// # use brewdrivers::state::BinaryState;
+    /// assert_eq!(BinaryState::On.toggle(), BinaryState::Off);
+    /// assert_eq!(BinaryState::Off.toggle(), BinaryState::On);
+    /// ```
+    pub fn toggle(self) -> BinaryState {
+        !self
+    }
+
+    /// `true` if the state is `On`
+    ///
+    /// ```rust
+    /// # use brewdrivers::state::BinaryState;
+    /// assert!(BinaryState::On.as_bool());
+    /// assert!(!BinaryState::Off.as_bool());
+    /// ```
+    pub fn as_bool(&self) -> bool {
+        *self == BinaryState::On
+    }
+
+    /// The lowercase form, `"on"` or `"off"`
+    ///
+    /// ```rust
+    /// # use brewdrivers::state::BinaryState;
+    /// assert_eq!(BinaryState::Off.to_lowercase_str(), "off");
+    /// ```
+    pub fn to_lowercase_str(&self) -> &'static str {
+        match self {
+            BinaryState::On => "on",
+            BinaryState::Off => "off",
+        }
+    }
+}
+
 impl std::fmt::Display for BinaryState {
     /// ```rust
     /// # use brewdrivers::state::BinaryState;
@@ -97,10 +282,7 @@ impl std::fmt::Display for BinaryState {
     /// assert_eq!("Off", format!("{}", BinaryState::Off));
     /// ```
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match self {
-            BinaryState::On => write!(f, "On"),
-            BinaryState::Off => write!(f, "Off"),
-        }
+        write!(f, "{}", self.to_titlecase_str())
     }
 }
 
@@ -120,6 +302,24 @@ impl From<bool> for BinaryState {
     }
 }
 
+impl std::ops::Not for BinaryState {
+    type Output = BinaryState;
+
+    /// The opposite state
+    ///
+    /// ```rust
+    /// # use brewdrivers::state::BinaryState;
+    /// assert_eq!(!BinaryState::On, BinaryState::Off);
+    /// assert_eq!(!BinaryState::Off, BinaryState::On);
+    /// ```
+    fn not(self) -> Self::Output {
+        match self {
+            BinaryState::On => BinaryState::Off,
+            BinaryState::Off => BinaryState::On,
+        }
+    }
+}
+
 impl Default for BinaryState {
     /// Defaults to `BinaryState::Off`
     fn default() -> Self {
@@ -158,4 +358,118 @@ mod tests {
             "Off"
         );
     }
+
+    #[test]
+    fn test_binary_state_casing() {
+        for state in [BinaryState::On, BinaryState::Off] {
+            // Display, serde, and the title case form all agree
+            assert_eq!(state.to_string(), state.to_titlecase_str());
+            assert_eq!(
+                serde_yaml::to_string(&state).unwrap().trim(),
+                state.to_titlecase_str()
+            );
+            assert_eq!(
+                state.to_lowercase_str(),
+                state.to_titlecase_str().to_lowercase()
+            );
+
+            // Every form parses back to the same state
+            assert_eq!(
+                state.to_titlecase_str().parse::<BinaryState>().unwrap(),
+                state
+            );
+            assert_eq!(
+                state.to_lowercase_str().parse::<BinaryState>().unwrap(),
+                state
+            );
+        }
+
+        assert_eq!(BinaryState::On.to_lowercase_str(), "on");
+        assert_eq!(BinaryState::Off.to_titlecase_str(), "Off");
+    }
+
+    #[test]
+    fn test_binary_state_parse_loose() {
+        for on in ["1", "true", "True", "TRUE", "on", "On", "ON"] {
+            assert_eq!(BinaryState::parse_loose(on).unwrap(), BinaryState::On);
+        }
+
+        for off in ["0", "false", "False", "FALSE", "off", "Off", "OFF"] {
+            assert_eq!(BinaryState::parse_loose(off).unwrap(), BinaryState::Off);
+        }
+
+        assert!(BinaryState::parse_loose("yes").is_err());
+        assert!(BinaryState::parse_loose("").is_err());
+
+        // The strict version still rejects these
+        assert!("1".parse::<BinaryState>().is_err());
+        assert!("true".parse::<BinaryState>().is_err());
+    }
+
+    #[test]
+    fn test_validate_rejects_nan() {
+        let mut state = DeviceState::default();
+        assert!(state.validate().is_ok());
+
+        state.pv = Some(f64::NAN);
+        assert!(matches!(
+            state.validate(),
+            Err(StateError::NonFinite { field: "pv", .. })
+        ));
+
+        state.pv = Some(145.0);
+        state.sv = Some(f64::NAN);
+        assert!(matches!(
+            state.validate(),
+            Err(StateError::NonFinite { field: "sv", .. })
+        ));
+    }
+
+    #[test]
+    fn test_validate_output_pct() {
+        let mut state = DeviceState::default();
+        for pct in [0.0, 50.0, 100.0] {
+            state.output_pct = Some(pct);
+            assert!(state.validate().is_ok());
+        }
+
+        state.output_pct = Some(100.1);
+        assert!(matches!(
+            state.validate(),
+            Err(StateError::OutOfRange { .. })
+        ));
+
+        state.output_pct = Some(-1.0);
+        assert!(state.validate().is_err());
+
+        state.output_pct = Some(f64::NAN);
+        assert!(state.validate().is_err());
+    }
+
+    #[test]
+    fn test_output_pct_yaml() {
+        // Older configs don't have output_pct
+        let state: DeviceState =
+            serde_yaml::from_str("relay_state: On\npv: null\nsv: null").unwrap();
+        assert_eq!(state.output_pct, None);
+        assert_eq!(state.relay_state, Some(BinaryState::On));
+
+        let mut state = DeviceState::default();
+        state.output_pct = Some(62.5);
+        let yaml = serde_yaml::to_string(&state).unwrap();
+        assert_eq!(serde_yaml::from_str::<DeviceState>(&yaml).unwrap(), state);
+    }
+
+    #[test]
+    fn test_validate_rejects_infinite() {
+        let mut state = DeviceState::default();
+        state.sv = Some(f64::INFINITY);
+        assert!(state.validate().is_err());
+
+        state.sv = Some(f64::NEG_INFINITY);
+        assert!(state.validate().is_err());
+
+        state.sv = Some(152.5);
+        assert!(state.validate().is_ok());
+    }
 }
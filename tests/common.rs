@@ -1,13 +1,35 @@
+use std::path::Path;
+
 use brewdrivers::controllers::Controller;
 use brewdrivers::model::{Device, RTU};
 
+/// Generates the testing RTU, or returns `None` if there's no testing configuration on this machine.
+/// Hardware tests should return early in that case.
+#[allow(dead_code)]
+pub fn get_test_rtu() -> Option<RTU> {
+    let conf = brewdrivers::defaults::test_config_file();
+    if !Path::new(conf).exists() {
+        eprintln!("skipping hardware test: no test configuration at {}", conf);
+        return None;
+    }
+    Some(RTU::generate(Some(conf)).unwrap())
+}
+
 /// Deserializes the testing configuration and finds the first device in it with the
-/// right controller type, if any
+/// right controller type, if any. This also returns `None` if there's no testing configuration
+/// or the device's port doesn't exist.
+#[allow(dead_code)]
 pub fn get_device_from_configuration(controller_type: Controller) -> Option<Device> {
-    let rtu = RTU::generate(Some(brewdrivers::defaults::test_config_file())).unwrap();
+    let rtu = get_test_rtu()?;
     rtu.devices
         .iter()
         .find(|dev| *dev.conn.controller() == controller_type)
+        .filter(|dev| {
+            let exists = dev.conn.port.exists();
+            if !exists {
+                eprintln!("skipping hardware test: port {} doesn't exist", dev.conn.port());
+            }
+            exists
+        })
         .map(|dev| dev.clone())
 }
-
@@ -0,0 +1,87 @@
+#![cfg(feature = "mqtt")]
+
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use brewdrivers::model::mqtt::{MqttClient, MqttError, StatePublisher};
+use brewdrivers::model::{Device, RTU};
+
+// Each message published, as (topic, payload)
+type Published = Arc<Mutex<Vec<(String, Vec<u8>)>>>;
+
+// Records everything published instead of sending it to a broker
+#[derive(Clone, Default)]
+struct MockClient {
+    published: Published,
+}
+
+#[async_trait]
+impl MqttClient for MockClient {
+    async fn publish(&self, topic: &str, payload: Vec<u8>) -> Result<(), MqttError> {
+        self.published
+            .lock()
+            .unwrap()
+            .push((topic.to_string(), payload));
+        Ok(())
+    }
+}
+
+fn rtu() -> RTU {
+    serde_yaml::from_str(
+        r#"
+        name: Test RTU
+        id: test-rtu
+        ip_addr: 0.0.0.0
+        devices:
+          - id: pump
+            name: Pump
+            command_retries: 0
+            conn:
+              port: /dev/brewdrivers-missing-port
+              baudrate: 9600
+              timeout: 100
+              controller: STR1
+              controller_addr: 254
+              addr: 0
+            state:
+              relay_state: On
+        "#,
+    )
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_publish_device() {
+    let client = MockClient::default();
+    let publisher = StatePublisher::with_client(client.clone(), "brewery/");
+    let rtu = rtu();
+
+    publisher.publish(&rtu.id, &rtu.devices[0]).await.unwrap();
+
+    let published = client.published.lock().unwrap();
+    assert_eq!(published.len(), 1);
+    assert_eq!(published[0].0, "brewery/test-rtu/pump");
+    let device: Device = serde_json::from_slice(&published[0].1).unwrap();
+    assert_eq!(device, rtu.devices[0]);
+}
+
+#[tokio::test]
+async fn test_failed_update_isnt_published() {
+    let client = MockClient::default();
+    let publisher = StatePublisher::with_client(client.clone(), "brewery");
+    let mut rtu = rtu();
+
+    assert!(rtu.update_and_publish(&publisher).await.is_err());
+    assert!(client.published.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_disabled_devices_arent_published() {
+    let client = MockClient::default();
+    let publisher = StatePublisher::with_client(client.clone(), "brewery");
+    let mut rtu = rtu();
+    rtu.devices[0].enabled = false;
+
+    assert!(rtu.enact_and_publish(&publisher).await.is_ok());
+    assert!(client.published.lock().unwrap().is_empty());
+}
@@ -1,7 +1,10 @@
+mod common;
+use common::*;
+
 use brewdrivers::{
     controllers::Controller,
     drivers::InstrumentError,
-    model::{Device, RTU},
+    model::Device,
     state::BinaryState,
 };
 
@@ -39,9 +42,9 @@ async fn test_retry_command_delay() -> Result<(), InstrumentError> {
 async fn test_generate_and_update_device_state() -> Result<(), InstrumentError> {
     // This generates an RTU state from the config file and updates all state
     // values in all devices. It's like taking a snapshot of all state values for the whole RTU.
-    let res = RTU::generate(Some(brewdrivers::defaults::test_config_file()));
-    assert!(res.is_ok());
-    let mut rtu = res.unwrap();
+    let Some(mut rtu) = get_test_rtu() else {
+        return Ok(());
+    };
     assert!(rtu.devices.len() > 0);
 
     let relay = rtu
@@ -87,9 +90,9 @@ async fn test_generate_and_update_device_state() -> Result<(), InstrumentError>
 async fn test_device_enact() -> Result<(), InstrumentError> {
     // There does exist an RTU::enact() method, but we try not to use it
     // because it will try to write to every device. This takes a long time.
-    let res = RTU::generate(Some(brewdrivers::defaults::test_config_file()));
-    assert!(res.is_ok());
-    let mut rtu = res.unwrap();
+    let Some(mut rtu) = get_test_rtu() else {
+        return Ok(());
+    };
     assert!(rtu.devices.len() > 0);
 
     let relay = rtu